@@ -0,0 +1,36 @@
+// A native plugin library using the C interface. `cargo build -p app-core --example native_plugin` leaves it in
+// target/debug/examples, copy it to the plugins folder and Gravel shows up next to the default particles
+use app_core::api::*;
+
+struct Gravel {
+    // Empty and water, the id of water is only known once every plugin is loaded
+    collision_targets: [u16; 2],
+}
+
+impl StablePlugin for Gravel {
+    fn register(&mut self) -> PluginResult {
+        PluginResult {
+            name: String::from("Gravel"),
+            color: Color::from_hex(0x808080),
+            color2: Color::from_hex(0x5A5A5A),
+            motion: Some(MotionProperties::default()),
+            blast_resistance: 2.0,
+            ..Default::default()
+        }
+    }
+
+    // Heavier than sand, it only falls straight down
+    fn update(&self, api: &mut CParticleApi) {
+        if api.is_any_particle_at(0, -1, &self.collision_targets) {
+            api.swap(0, -1);
+        }
+    }
+
+    fn on_plugin_changed(&mut self, api: &CParticleApi) {
+        self.collision_targets[1] = api.id_from_name("Water");
+    }
+}
+
+app_core::export_plugins!(Gravel {
+    collision_targets: [0, Particle::INVALID.id],
+});
@@ -0,0 +1,13 @@
+// What a library built for the first version of the C interface looks like to the app, with 8 bit ids.
+// Only used by the tests to check it's refused before anything else is called
+use app_core::api::*;
+
+#[no_mangle]
+pub extern "C" fn sand_plugin_abi_version() -> u32 {
+    1 << 24
+}
+
+#[no_mangle]
+pub extern "C" fn sand_plugin_create(_index: usize, _out: *mut CPluginVTable) -> bool {
+    panic!("Plugins of a library with another interface version can't be created")
+}
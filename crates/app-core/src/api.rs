@@ -6,9 +6,23 @@ pub mod order_scheme;
 pub mod custom_range;
 pub mod color;
 pub mod vec2;
+pub mod snapshot;
+pub mod chunk_activity;
+pub mod particle_view;
+pub mod property;
+pub mod thermal;
+pub mod reaction;
+pub mod lifecycle;
+pub mod boundary;
+pub mod motion;
+pub mod explosion;
+#[cfg(not(target_family = "wasm"))]
+pub mod chunk_scheduler;
+#[cfg(not(target_family = "wasm"))]
+pub mod c_plugin;
 
 pub(crate) use crate::simulation_state::*;
-pub use crate::simulation_state::Transformation;
+pub use crate::simulation_state::{ResizeAnchor, Transformation};
 pub use crate::particle::*;
 pub use crate::plugin::*;
 pub(crate) use crate::custom_range::*;
@@ -17,7 +31,36 @@ pub(crate) use crate::order_scheme::*;
 pub use crate::color::*;
 pub use crate::color::constants::*;
 pub use crate::vec2::*;
+pub use crate::snapshot::*;
+pub use crate::particle_view::ParticleView;
+pub use crate::property::{PropertyDefinition, PROPERTY_SLOTS};
+pub(crate) use crate::property::*;
+pub use crate::thermal::{PhaseChange, ThermalProperties, AMBIENT_TEMPERATURE};
+pub(crate) use crate::thermal::*;
+pub use crate::reaction::{Reaction, ReactionTable};
+pub(crate) use crate::reaction::ResolvedReaction;
+pub use crate::lifecycle::LifecycleHooks;
+pub(crate) use crate::lifecycle::*;
+pub use crate::boundary::{Boundaries, BoundaryMode};
+pub(crate) use crate::boundary::*;
+pub use crate::motion::{MotionProperties, MAX_SPEED};
+pub(crate) use crate::motion::*;
+pub use crate::explosion::{Explosion, DEFAULT_BLAST_RESISTANCE};
+pub use crate::chunk_activity::{DirtyRect, ACTIVITY_CHUNK_SIZE};
+pub(crate) use crate::chunk_activity::ChunkActivity;
+#[cfg(not(target_family = "wasm"))]
+pub(crate) use crate::chunk_scheduler::*;
+#[cfg(not(target_family = "wasm"))]
+pub use crate::c_plugin::*;
 
 pub const TO_NORMALIZED_COLOR: f32 = 1.0 / 255.0;
 pub const FROM_NORMALIZED_TO_COLOR: f32 = 100.0;
-// pub const FROM_NORMALIZED_TO_COLOR: f32 = 255.0;
\ No newline at end of file
+// pub const FROM_NORMALIZED_TO_COLOR: f32 = 255.0;
+
+// Not in chunk_scheduler as plugins are checked against the halo on every platform
+pub const DEFAULT_CHUNK_SIZE: usize = 64;
+
+// How many cells around its chunk a worker can see. When multithreading, plugins can't read or write
+// further than this from the chunk they are in, anything past it behaves like the world edge.
+// Chunks updated at the same time are a whole chunk apart, so the halo can't be bigger than half a chunk
+pub const CHUNK_HALO: usize = 8;
@@ -0,0 +1,191 @@
+use crate::api::*;
+
+/// What the world does past one of its edges
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundaryMode {
+    /// Nothing goes through. Reads give a particle of the named type, or Particle::INVALID
+    /// without one, which is how edges always behaved
+    Wall(Option<String>),
+    /// Leaving through this edge comes back in through the opposite one
+    Wrap,
+    /// Reads as empty, anything written or moved into it is gone
+    Void,
+}
+
+impl Default for BoundaryMode {
+    fn default() -> Self {
+        BoundaryMode::Wall(None)
+    }
+}
+
+// "wall", "wall:<particle name>", "wrap" or "void"
+impl std::str::FromStr for BoundaryMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        let mode = mode.trim();
+        match mode.to_lowercase().as_str() {
+            "wall" => return Ok(BoundaryMode::Wall(None)),
+            "wrap" => return Ok(BoundaryMode::Wrap),
+            "void" => return Ok(BoundaryMode::Void),
+            _ => (),
+        }
+
+        match mode.split_once(':') {
+            Some((wall, name)) if wall.trim().eq_ignore_ascii_case("wall") && !name.trim().is_empty() => {
+                Ok(BoundaryMode::Wall(Some(name.trim().to_string())))
+            }
+            _ => Err(format!("Unknown boundary mode: {}", mode)),
+        }
+    }
+}
+
+/// Boundary of each edge of the world, the top one is above the first row
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Boundaries {
+    pub left: BoundaryMode,
+    pub right: BoundaryMode,
+    pub top: BoundaryMode,
+    pub bottom: BoundaryMode,
+}
+
+impl Boundaries {
+    pub fn all(mode: BoundaryMode) -> Boundaries {
+        Boundaries {
+            left: mode.clone(),
+            right: mode.clone(),
+            top: mode.clone(),
+            bottom: mode,
+        }
+    }
+
+    pub fn wraps(&self) -> bool {
+        [&self.left, &self.right, &self.top, &self.bottom]
+            .iter()
+            .any(|mode| **mode == BoundaryMode::Wrap)
+    }
+}
+
+// One mode for every edge, or four of them separated by commas in left, right, top, bottom order
+impl std::str::FromStr for Boundaries {
+    type Err = String;
+
+    fn from_str(boundaries: &str) -> Result<Self, Self::Err> {
+        let modes = boundaries
+            .split(',')
+            .map(|mode| mode.parse())
+            .collect::<Result<Vec<BoundaryMode>, String>>()?;
+
+        match <[BoundaryMode; 4]>::try_from(modes) {
+            Ok([left, right, top, bottom]) => Ok(Boundaries { left, right, top, bottom }),
+            Err(modes) if modes.len() == 1 => Ok(Boundaries::all(modes[0].clone())),
+            Err(_) => Err(format!("Expected 1 or 4 boundary modes: {}", boundaries)),
+        }
+    }
+}
+
+// Same as BoundaryMode but with the wall particle already looked up, so it's cheap to copy around
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ResolvedBoundary {
+    Wall(Particle),
+    Wrap,
+    Void,
+}
+
+impl ResolvedBoundary {
+    pub(crate) fn resolve(mode: &BoundaryMode, id_from_name: impl Fn(&str) -> u16) -> ResolvedBoundary {
+        match mode {
+            BoundaryMode::Wall(None) => ResolvedBoundary::Wall(Particle::INVALID),
+            BoundaryMode::Wall(Some(name)) => ResolvedBoundary::Wall(Particle {
+                id: id_from_name(name),
+                ..Particle::INVALID
+            }),
+            BoundaryMode::Wrap => ResolvedBoundary::Wrap,
+            BoundaryMode::Void => ResolvedBoundary::Void,
+        }
+    }
+}
+
+// What a position points to once the boundaries are applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Location {
+    Cell(usize, usize),
+    Wall(Particle),
+    Void,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ResolvedBoundaries {
+    pub left: ResolvedBoundary,
+    pub right: ResolvedBoundary,
+    pub top: ResolvedBoundary,
+    pub bottom: ResolvedBoundary,
+}
+
+impl Default for ResolvedBoundaries {
+    fn default() -> Self {
+        ResolvedBoundaries::all(ResolvedBoundary::Wall(Particle::INVALID))
+    }
+}
+
+impl ResolvedBoundaries {
+    pub(crate) fn all(boundary: ResolvedBoundary) -> ResolvedBoundaries {
+        ResolvedBoundaries {
+            left: boundary,
+            right: boundary,
+            top: boundary,
+            bottom: boundary,
+        }
+    }
+
+    pub(crate) fn resolve(boundaries: &Boundaries, id_from_name: impl Fn(&str) -> u16) -> ResolvedBoundaries {
+        ResolvedBoundaries {
+            left: ResolvedBoundary::resolve(&boundaries.left, &id_from_name),
+            right: ResolvedBoundary::resolve(&boundaries.right, &id_from_name),
+            top: ResolvedBoundary::resolve(&boundaries.top, &id_from_name),
+            bottom: ResolvedBoundary::resolve(&boundaries.bottom, &id_from_name),
+        }
+    }
+
+    pub(crate) fn wraps_x(&self) -> bool {
+        matches!(self.left, ResolvedBoundary::Wrap) || matches!(self.right, ResolvedBoundary::Wrap)
+    }
+
+    pub(crate) fn wraps_y(&self) -> bool {
+        matches!(self.top, ResolvedBoundary::Wrap) || matches!(self.bottom, ResolvedBoundary::Wrap)
+    }
+
+    // x and y are in world coordinates, at least one of them outside the world. The horizontal edge is
+    // crossed first, so a corner past a wall on the sides is a wall even if the top wraps
+    pub(crate) fn locate(&self, x: i32, y: i32, width: usize, height: usize) -> Location {
+        let x = match Self::cross(x, width, self.left, self.right) {
+            Ok(x) => x,
+            Err(location) => return location,
+        };
+        let y = match Self::cross(y, height, self.top, self.bottom) {
+            Ok(y) => y,
+            Err(location) => return location,
+        };
+
+        Location::Cell(x, y)
+    }
+
+    fn cross(
+        position: i32,
+        size: usize,
+        before: ResolvedBoundary,
+        after: ResolvedBoundary,
+    ) -> Result<usize, Location> {
+        let boundary = match position {
+            position if position < 0 => before,
+            position if position >= size as i32 => after,
+            position => return Ok(position as usize),
+        };
+
+        match boundary {
+            ResolvedBoundary::Wall(particle) => Err(Location::Wall(particle)),
+            ResolvedBoundary::Wrap => Ok(position.rem_euclid(size as i32) as usize),
+            ResolvedBoundary::Void => Err(Location::Void),
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::any::Any;
+use std::ffi::c_void;
+use std::sync::Arc;
+
+use crate::api::*;
+
+// Version of the C plugin interface, major << 24 + minor << 16 + patch like pixel_creator_api_crate_version.
+// Functions are only ever added at the end of CApiFunctions and CRegistrar in a minor version, so
+// libraries built for an older minor version keep working. Anything else bumps the major version.
+// 2.0.0 made particle ids 16 bit, which changed Particle too
+pub const PLUGIN_ABI_VERSION: u32 = 2 << 24;
+
+// Symbols a native plugin library exports, export_plugins! writes them:
+//   sand_plugin_abi_version() -> u32                               the PLUGIN_ABI_VERSION it was built with
+//   sand_plugin_create(index: usize, out: *mut CPluginVTable) -> bool   false once there are no more plugins
+pub const ABI_VERSION_SYMBOL: &[u8] = b"sand_plugin_abi_version";
+pub const PLUGIN_CREATE_SYMBOL: &[u8] = b"sand_plugin_create";
+
+fn major(version: u32) -> u32 {
+    version >> 24
+}
+
+fn minor(version: u32) -> u32 {
+    (version >> 16) & 0xFF
+}
+
+pub fn is_abi_compatible(version: u32) -> bool {
+    major(version) == major(PLUGIN_ABI_VERSION) && minor(version) <= minor(PLUGIN_ABI_VERSION)
+}
+
+pub fn format_abi_version(version: u32) -> String {
+    format!("{}.{}.{}", major(version), minor(version), version & 0xFFFF)
+}
+
+/// Signatures of the symbols export_plugins! writes
+pub type AbiVersionFunction = unsafe extern "C" fn() -> u32;
+pub type PluginCreateFunction = unsafe extern "C" fn(usize, *mut CPluginVTable) -> bool;
+
+// What sand_plugin_abi_version returned, the error says which version the library was built for
+pub fn check_abi_version(version: u32) -> Result<(), String> {
+    if is_abi_compatible(version) {
+        Ok(())
+    } else {
+        Err(format!(
+            "built for plugin interface {}, this app supports {}",
+            format_abi_version(version),
+            format_abi_version(PLUGIN_ABI_VERSION)
+        ))
+    }
+}
+
+// Opaque to plugins, it's the simulation state on the app side
+#[repr(C)]
+pub struct ApiHandle {
+    _private: [u8; 0],
+}
+
+// Opaque to plugins, it's the PluginResult being filled on the app side
+#[repr(C)]
+pub struct RegistrationHandle {
+    _private: [u8; 0],
+}
+
+// Same as the ParticleApi functions with the same name, strings are UTF-8 without a trailing 0
+#[repr(C)]
+pub struct CApiFunctions {
+    pub get: extern "C" fn(*mut ApiHandle, i32, i32) -> Particle,
+    pub get_current: extern "C" fn(*mut ApiHandle) -> Particle,
+    pub set: extern "C" fn(*mut ApiHandle, i32, i32, Particle) -> bool,
+    pub swap: extern "C" fn(*mut ApiHandle, i32, i32) -> bool,
+    pub swap_using: extern "C" fn(*mut ApiHandle, i32, i32, Particle) -> bool,
+    pub move_to: extern "C" fn(*mut ApiHandle, i32, i32) -> bool,
+    pub new_particle: extern "C" fn(*mut ApiHandle, u16) -> Particle,
+    pub gen_range: extern "C" fn(*mut ApiHandle, i32, i32) -> i32,
+    pub id_from_name: extern "C" fn(*mut ApiHandle, *const u8, usize) -> u16,
+    pub get_temperature: extern "C" fn(*mut ApiHandle, i32, i32) -> f32,
+    pub set_temperature: extern "C" fn(*mut ApiHandle, i32, i32, f32) -> bool,
+    pub keep_awake: extern "C" fn(*mut ApiHandle),
+}
+
+/// What native plugin libraries get instead of ParticleApi, its layout doesn't depend on the compiler
+#[repr(C)]
+pub struct CParticleApi {
+    handle: *mut ApiHandle,
+    functions: *const CApiFunctions,
+}
+
+// Called by the plugin while registering, colors are 0xRRGGBBAA. Phase changes are 0 melting, 1 boiling and 2 freezing
+#[repr(C)]
+pub struct CRegistrar {
+    pub handle: *mut RegistrationHandle,
+    pub name: extern "C" fn(*mut RegistrationHandle, *const u8, usize),
+    pub colors: extern "C" fn(*mut RegistrationHandle, u32, u32),
+    pub property: extern "C" fn(*mut RegistrationHandle, *const u8, usize, u8, u8, u8),
+    pub thermal: extern "C" fn(*mut RegistrationHandle, f32, f32, f32),
+    pub phase_change: extern "C" fn(*mut RegistrationHandle, u8, f32, *const u8, usize),
+    pub motion: extern "C" fn(*mut RegistrationHandle, f32, f32, f32),
+    pub blast_resistance: extern "C" fn(*mut RegistrationHandle, f32),
+}
+
+// One particle type, instance belongs to the library and is only ever passed back to these functions
+#[repr(C)]
+pub struct CPluginVTable {
+    pub instance: *mut c_void,
+    pub register: extern "C" fn(*mut c_void, *mut CRegistrar),
+    pub update: extern "C" fn(*const c_void, *mut CParticleApi),
+    // Only the functions that read the world do something from it, the ones that write return false
+    pub on_plugin_changed: extern "C" fn(*mut c_void, *const CParticleApi),
+    pub drop: extern "C" fn(*mut c_void),
+}
+
+/// Plugin side of the C interface, the same as Plugin but with CParticleApi. Reactions and hooks in
+/// the PluginResult aren't part of this version of the interface and are left out
+pub trait StablePlugin: Send + Sync {
+    fn register(&mut self) -> PluginResult;
+    fn update(&self, api: &mut CParticleApi);
+    fn on_plugin_changed(&mut self, _api: &CParticleApi) {}
+}
+
+impl CParticleApi {
+    fn call<T>(&self, function: impl FnOnce(&CApiFunctions, *mut ApiHandle) -> T) -> T {
+        function(unsafe { &*self.functions }, self.handle)
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Particle {
+        self.call(|functions, handle| (functions.get)(handle, x, y))
+    }
+
+    pub fn get_type(&self, x: i32, y: i32) -> u16 {
+        self.get(x, y).id
+    }
+
+    pub fn is_empty(&self, x: i32, y: i32) -> bool {
+        self.get_type(x, y) == Particle::EMPTY.id
+    }
+
+    pub fn is_any_particle_at(&self, x: i32, y: i32, ids: &[u16]) -> bool {
+        ids.contains(&self.get_type(x, y))
+    }
+
+    pub fn get_current(&self) -> Particle {
+        self.call(|functions, handle| (functions.get_current)(handle))
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, particle: Particle) -> bool {
+        self.call(|functions, handle| (functions.set)(handle, x, y, particle))
+    }
+
+    pub fn swap(&mut self, x: i32, y: i32) -> bool {
+        self.call(|functions, handle| (functions.swap)(handle, x, y))
+    }
+
+    pub fn swap_using(&mut self, x: i32, y: i32, particle: Particle) -> bool {
+        self.call(|functions, handle| (functions.swap_using)(handle, x, y, particle))
+    }
+
+    pub fn move_to(&mut self, x: i32, y: i32) -> bool {
+        self.call(|functions, handle| (functions.move_to)(handle, x, y))
+    }
+
+    pub fn new_particle(&self, id: u16) -> Particle {
+        self.call(|functions, handle| (functions.new_particle)(handle, id))
+    }
+
+    pub fn gen_range(&self, min_inclusive: i32, max_inclusive: i32) -> i32 {
+        self.call(|functions, handle| (functions.gen_range)(handle, min_inclusive, max_inclusive))
+    }
+
+    pub fn id_from_name(&self, name: &str) -> u16 {
+        self.call(|functions, handle| (functions.id_from_name)(handle, name.as_ptr(), name.len()))
+    }
+
+    pub fn get_temperature(&self, x: i32, y: i32) -> f32 {
+        self.call(|functions, handle| (functions.get_temperature)(handle, x, y))
+    }
+
+    pub fn set_temperature(&mut self, x: i32, y: i32, temperature: f32) -> bool {
+        self.call(|functions, handle| (functions.set_temperature)(handle, x, y, temperature))
+    }
+
+    pub fn keep_awake(&mut self) {
+        self.call(|functions, handle| (functions.keep_awake)(handle))
+    }
+}
+
+fn color_to_u32(color: Color) -> u32 {
+    let color: [u8; 4] = color.into();
+    u32::from_be_bytes(color)
+}
+
+impl CPluginVTable {
+    // Plugin side, what sand_plugin_create hands out for each particle type
+    pub fn new<P: StablePlugin + 'static>(plugin: P) -> CPluginVTable {
+        extern "C" fn register<P: StablePlugin>(instance: *mut c_void, registrar: *mut CRegistrar) {
+            let result = unsafe { &mut *(instance as *mut P) }.register();
+            let registrar = unsafe { &*registrar };
+            let handle = registrar.handle;
+
+            (registrar.name)(handle, result.name.as_ptr(), result.name.len());
+            (registrar.colors)(handle, color_to_u32(result.color), color_to_u32(result.color2));
+            for property in result.properties.iter() {
+                (registrar.property)(
+                    handle,
+                    property.name.as_ptr(),
+                    property.name.len(),
+                    property.default,
+                    property.min,
+                    property.max,
+                );
+            }
+
+            let thermal = &result.thermal;
+            (registrar.thermal)(handle, thermal.conductivity, thermal.temperature, thermal.ambient_exchange);
+            for (kind, phase_change) in [&thermal.melting, &thermal.boiling, &thermal.freezing].into_iter().enumerate() {
+                if let Some(phase_change) = phase_change {
+                    let into = &phase_change.into;
+                    (registrar.phase_change)(handle, kind as u8, phase_change.temperature, into.as_ptr(), into.len());
+                }
+            }
+
+            if let Some(motion) = result.motion {
+                (registrar.motion)(handle, motion.gravity, motion.drag, motion.bounce);
+            }
+            (registrar.blast_resistance)(handle, result.blast_resistance);
+        }
+
+        extern "C" fn update<P: StablePlugin>(instance: *const c_void, api: *mut CParticleApi) {
+            unsafe { (*(instance as *const P)).update(&mut *api) }
+        }
+
+        extern "C" fn on_plugin_changed<P: StablePlugin>(instance: *mut c_void, api: *const CParticleApi) {
+            unsafe { (*(instance as *mut P)).on_plugin_changed(&*api) }
+        }
+
+        extern "C" fn drop<P: StablePlugin>(instance: *mut c_void) {
+            unsafe { std::mem::drop(Box::from_raw(instance as *mut P)) }
+        }
+
+        CPluginVTable {
+            instance: Box::into_raw(Box::new(plugin)) as *mut c_void,
+            register: register::<P>,
+            update: update::<P>,
+            on_plugin_changed: on_plugin_changed::<P>,
+            drop: drop::<P>,
+        }
+    }
+}
+
+/// Writes the symbols the app looks for in a native plugin library, one plugin per expression:
+/// `app_core::export_plugins!(MyParticle::new(), MyOtherParticle::new());`
+#[macro_export]
+macro_rules! export_plugins {
+    ($($plugin:expr),* $(,)?) => {
+        #[no_mangle]
+        pub extern "C" fn sand_plugin_abi_version() -> u32 {
+            $crate::api::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn sand_plugin_create(index: usize, out: *mut $crate::api::CPluginVTable) -> bool {
+            let constructors: &[fn() -> $crate::api::CPluginVTable] = &[$(|| $crate::api::CPluginVTable::new($plugin)),*];
+            match constructors.get(index) {
+                Some(constructor) => {
+                    unsafe { out.write(constructor()) };
+                    true
+                }
+                None => false,
+            }
+        }
+    };
+}
+
+// App side from here on, the api handle is the simulation state the plugin runs on
+
+fn state<'a>(handle: *mut ApiHandle) -> &'a SimulationState {
+    unsafe { &*(handle as *const SimulationState) }
+}
+
+fn state_mut<'a>(handle: *mut ApiHandle) -> &'a mut SimulationState {
+    unsafe { &mut *(handle as *mut SimulationState) }
+}
+
+fn string<'a>(pointer: *const u8, length: usize) -> std::borrow::Cow<'a, str> {
+    String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(pointer, length) })
+}
+
+extern "C" fn api_get(handle: *mut ApiHandle, x: i32, y: i32) -> Particle {
+    state(handle).get(x, y)
+}
+
+extern "C" fn api_get_current(handle: *mut ApiHandle) -> Particle {
+    state(handle).get_current()
+}
+
+extern "C" fn api_set(handle: *mut ApiHandle, x: i32, y: i32, particle: Particle) -> bool {
+    state_mut(handle).set(x, y, particle)
+}
+
+extern "C" fn api_swap(handle: *mut ApiHandle, x: i32, y: i32) -> bool {
+    state_mut(handle).swap(x, y)
+}
+
+extern "C" fn api_swap_using(handle: *mut ApiHandle, x: i32, y: i32, particle: Particle) -> bool {
+    state_mut(handle).swap_using(x, y, particle)
+}
+
+extern "C" fn api_move_to(handle: *mut ApiHandle, x: i32, y: i32) -> bool {
+    state_mut(handle).move_to(x, y)
+}
+
+extern "C" fn api_new_particle(handle: *mut ApiHandle, id: u16) -> Particle {
+    state(handle).new_particle(id)
+}
+
+extern "C" fn api_gen_range(handle: *mut ApiHandle, min_inclusive: i32, max_inclusive: i32) -> i32 {
+    // A panic can't cross into the library, an empty range just gives its start
+    state(handle).gen_range(min_inclusive, max_inclusive.max(min_inclusive))
+}
+
+extern "C" fn api_id_from_name(handle: *mut ApiHandle, name: *const u8, length: usize) -> u16 {
+    state(handle).id_from_name(&string(name, length))
+}
+
+extern "C" fn api_get_temperature(handle: *mut ApiHandle, x: i32, y: i32) -> f32 {
+    state(handle).get_temperature(x, y)
+}
+
+extern "C" fn api_set_temperature(handle: *mut ApiHandle, x: i32, y: i32, temperature: f32) -> bool {
+    state_mut(handle).set_temperature(x, y, temperature)
+}
+
+extern "C" fn api_keep_awake(handle: *mut ApiHandle) {
+    state_mut(handle).keep_awake()
+}
+
+static API_FUNCTIONS: CApiFunctions = CApiFunctions {
+    get: api_get,
+    get_current: api_get_current,
+    set: api_set,
+    swap: api_swap,
+    swap_using: api_swap_using,
+    move_to: api_move_to,
+    new_particle: api_new_particle,
+    gen_range: api_gen_range,
+    id_from_name: api_id_from_name,
+    get_temperature: api_get_temperature,
+    set_temperature: api_set_temperature,
+    keep_awake: api_keep_awake,
+};
+
+// on_plugin_changed only gets a shared reference to the state, so whatever would write does nothing instead
+extern "C" fn read_only_set(_: *mut ApiHandle, _: i32, _: i32, _: Particle) -> bool {
+    false
+}
+
+extern "C" fn read_only_swap(_: *mut ApiHandle, _: i32, _: i32) -> bool {
+    false
+}
+
+extern "C" fn read_only_set_temperature(_: *mut ApiHandle, _: i32, _: i32, _: f32) -> bool {
+    false
+}
+
+extern "C" fn read_only_keep_awake(_: *mut ApiHandle) {}
+
+static READ_ONLY_API_FUNCTIONS: CApiFunctions = CApiFunctions {
+    set: read_only_set,
+    swap: read_only_swap,
+    swap_using: read_only_set,
+    move_to: read_only_swap,
+    set_temperature: read_only_set_temperature,
+    keep_awake: read_only_keep_awake,
+    ..API_FUNCTIONS
+};
+
+fn registration<'a>(handle: *mut RegistrationHandle) -> &'a mut PluginResult {
+    unsafe { &mut *(handle as *mut PluginResult) }
+}
+
+extern "C" fn register_name(handle: *mut RegistrationHandle, name: *const u8, length: usize) {
+    registration(handle).name = string(name, length).into_owned();
+}
+
+extern "C" fn register_colors(handle: *mut RegistrationHandle, color: u32, color2: u32) {
+    let result = registration(handle);
+    result.color = color.to_be_bytes().into();
+    result.color2 = color2.to_be_bytes().into();
+}
+
+extern "C" fn register_property(handle: *mut RegistrationHandle, name: *const u8, length: usize, default: u8, min: u8, max: u8) {
+    registration(handle)
+        .properties
+        .push(PropertyDefinition::new(&string(name, length), default, min, max));
+}
+
+extern "C" fn register_thermal(handle: *mut RegistrationHandle, conductivity: f32, temperature: f32, ambient_exchange: f32) {
+    let thermal = &mut registration(handle).thermal;
+    thermal.conductivity = conductivity;
+    thermal.temperature = temperature;
+    thermal.ambient_exchange = ambient_exchange;
+}
+
+extern "C" fn register_phase_change(handle: *mut RegistrationHandle, kind: u8, temperature: f32, into: *const u8, length: usize) {
+    let thermal = &mut registration(handle).thermal;
+    let phase_change = Some(PhaseChange::new(temperature, &string(into, length)));
+    match kind {
+        0 => thermal.melting = phase_change,
+        1 => thermal.boiling = phase_change,
+        2 => thermal.freezing = phase_change,
+        _ => {}
+    }
+}
+
+extern "C" fn register_motion(handle: *mut RegistrationHandle, gravity: f32, drag: f32, bounce: f32) {
+    registration(handle).motion = Some(MotionProperties { gravity, drag, bounce });
+}
+
+extern "C" fn register_blast_resistance(handle: *mut RegistrationHandle, resistance: f32) {
+    registration(handle).blast_resistance = resistance;
+}
+
+/// A particle type from a native library, built by DylibLoader out of what sand_plugin_create returns
+pub struct CPlugin {
+    vtable: CPluginVTable,
+    // Whatever keeps the library loaded, it's only let go once the instance is dropped. Libraries are
+    // unloaded when the last of their plugins is replaced or removed, so they can be reloaded safely
+    _library: Arc<dyn Any + Send + Sync>,
+}
+
+// StablePlugin is Send + Sync, the instance is whatever implemented it
+unsafe impl Send for CPlugin {}
+unsafe impl Sync for CPlugin {}
+
+impl CPlugin {
+    /// # Safety
+    /// The vtable has to come from sand_plugin_create of a library with a compatible PLUGIN_ABI_VERSION,
+    /// and that library has to stay loaded for as long as `library` is alive
+    pub unsafe fn new(vtable: CPluginVTable, library: Arc<dyn Any + Send + Sync>) -> CPlugin {
+        CPlugin {
+            vtable,
+            _library: library,
+        }
+    }
+
+    fn api(handle: *mut ApiHandle, functions: &'static CApiFunctions) -> CParticleApi {
+        CParticleApi { handle, functions }
+    }
+}
+
+/// Every plugin sand_plugin_create hands out, in order.
+/// # Safety
+/// `create` has to be sand_plugin_create of a library that passed check_abi_version, and that library has
+/// to stay loaded for as long as `library` is alive
+pub unsafe fn create_plugins(
+    create: PluginCreateFunction,
+    library: Arc<dyn Any + Send + Sync>,
+) -> Vec<Box<dyn Plugin>> {
+    let mut plugins: Vec<Box<dyn Plugin>> = Vec::new();
+    let mut vtable = std::mem::MaybeUninit::<CPluginVTable>::uninit();
+    while unsafe { create(plugins.len(), vtable.as_mut_ptr()) } {
+        plugins.push(Box::new(unsafe { CPlugin::new(vtable.assume_init_read(), library.clone()) }));
+    }
+    plugins
+}
+
+impl Plugin for CPlugin {
+    fn register(&mut self) -> PluginResult {
+        let mut result = PluginResult::default();
+        let mut registrar = CRegistrar {
+            handle: &mut result as *mut PluginResult as *mut RegistrationHandle,
+            name: register_name,
+            colors: register_colors,
+            property: register_property,
+            thermal: register_thermal,
+            phase_change: register_phase_change,
+            motion: register_motion,
+            blast_resistance: register_blast_resistance,
+        };
+        (self.vtable.register)(self.vtable.instance, &mut registrar);
+        result
+    }
+
+    fn update(&self, api: &mut ParticleApi) {
+        let mut api = CPlugin::api(api as *mut ParticleApi as *mut ApiHandle, &API_FUNCTIONS);
+        (self.vtable.update)(self.vtable.instance, &mut api);
+    }
+
+    fn on_plugin_changed(&mut self, api: &ParticleApi) {
+        // The handle type is shared by both tables, nothing in READ_ONLY_API_FUNCTIONS writes through it
+        let api = CPlugin::api(api as *const ParticleApi as *mut ApiHandle, &READ_ONLY_API_FUNCTIONS);
+        (self.vtable.on_plugin_changed)(self.vtable.instance, &api);
+    }
+}
+
+impl Drop for CPlugin {
+    fn drop(&mut self) {
+        (self.vtable.drop)(self.vtable.instance);
+    }
+}
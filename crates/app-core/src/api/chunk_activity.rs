@@ -0,0 +1,269 @@
+use crate::api::*;
+
+// Power of two so finding the chunk of a cell is just a shift
+pub const ACTIVITY_CHUNK_SIZE: usize = 16;
+const ACTIVITY_CHUNK_SHIFT: u32 = ACTIVITY_CHUNK_SIZE.trailing_zeros();
+
+// Frames a chunk keeps being updated after its last write, particles that don't write anything
+// get one more frame to do something before it falls asleep
+const AWAKE_FRAMES: u8 = 2;
+
+// Writes this close to a chunk border also wake the chunk on the other side, so particles
+// waiting for that cell to change (sand on top of a hole, water next to a gap...) notice it
+const WAKE_MARGIN: usize = 1;
+
+/// Bounding box of the cells that changed, limits are inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl DirtyRect {
+    pub fn from_point(x: usize, y: usize) -> DirtyRect {
+        DirtyRect {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.max_y - self.min_y + 1
+    }
+
+    pub(crate) fn include(&mut self, x: usize, y: usize) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    pub(crate) fn union(&self, other: &DirtyRect) -> DirtyRect {
+        DirtyRect {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    pub(crate) fn offset(&self, x: usize, y: usize) -> DirtyRect {
+        DirtyRect {
+            min_x: self.min_x + x,
+            min_y: self.min_y + y,
+            max_x: self.max_x + x,
+            max_y: self.max_y + y,
+        }
+    }
+}
+
+/// Keeps track of which parts of the world are doing something. The world is split in chunks,
+/// a chunk that had no writes for a couple of frames falls asleep and it isn't updated until
+/// something writes into it or right next to it.
+#[derive(Clone)]
+pub(crate) struct ChunkActivity {
+    enabled: bool,
+    // World position of the tracked area, it's only not 0 for the chunk workers, as they
+    // track a region of the world but chunks must line up with the world ones
+    origin_x: usize,
+    origin_y: usize,
+    width: usize,
+    height: usize,
+    // World chunk coordinates of the first tracked chunk
+    first_column: usize,
+    first_row: usize,
+    columns: usize,
+    rows: usize,
+    awake_frames: Vec<u8>,
+    written: Vec<bool>,
+    // A row is awake if any of its chunks is, lets us skip full rows of cells at once
+    awake_rows: Vec<bool>,
+    // Edges that wrap around, cells next to them are read from the other side too
+    wrap_x: bool,
+    wrap_y: bool,
+}
+
+impl ChunkActivity {
+    pub(crate) fn new(width: usize, height: usize) -> ChunkActivity {
+        let mut activity = ChunkActivity {
+            enabled: true,
+            origin_x: 0,
+            origin_y: 0,
+            width: 0,
+            height: 0,
+            first_column: 0,
+            first_row: 0,
+            columns: 0,
+            rows: 0,
+            awake_frames: Vec::new(),
+            written: Vec::new(),
+            awake_rows: Vec::new(),
+            wrap_x: false,
+            wrap_y: false,
+        };
+        activity.set_area(0, 0, width, height);
+        activity.wake_all();
+        activity
+    }
+
+    fn set_area(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.origin_x = x;
+        self.origin_y = y;
+        self.width = width;
+        self.height = height;
+        self.first_column = x >> ACTIVITY_CHUNK_SHIFT;
+        self.first_row = y >> ACTIVITY_CHUNK_SHIFT;
+        self.columns = match width {
+            0 => 0,
+            _ => ((x + width - 1) >> ACTIVITY_CHUNK_SHIFT) - self.first_column + 1,
+        };
+        self.rows = match height {
+            0 => 0,
+            _ => ((y + height - 1) >> ACTIVITY_CHUNK_SHIFT) - self.first_row + 1,
+        };
+
+        self.awake_frames.clear();
+        self.awake_frames.resize(self.columns * self.rows, 0);
+        self.written.clear();
+        self.written.resize(self.columns * self.rows, false);
+        self.awake_rows.clear();
+        self.awake_rows.resize(self.rows, false);
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // Everything gets updated next frame, used when the world changes in ways we can't follow
+    pub(crate) fn wake_all(&mut self) {
+        self.written.iter_mut().for_each(|written| *written = true);
+    }
+
+    pub(crate) fn awake_chunk_count(&self) -> usize {
+        self.awake_frames.iter().filter(|frames| **frames > 0).count()
+    }
+
+    pub(crate) fn chunk_count(&self) -> usize {
+        self.awake_frames.len()
+    }
+
+    // Writes from last frame decide which chunks are updated in this one. Doing it at the start
+    // also picks up writes made between frames, like the brush or a paused simulation being edited
+    pub(crate) fn begin_frame(&mut self) {
+        self.awake_rows.iter_mut().for_each(|awake| *awake = false);
+
+        for (index, (awake_frames, written)) in self
+            .awake_frames
+            .iter_mut()
+            .zip(self.written.iter_mut())
+            .enumerate()
+        {
+            *awake_frames = match *written {
+                true => AWAKE_FRAMES,
+                false => awake_frames.saturating_sub(1),
+            };
+            *written = false;
+
+            if *awake_frames > 0 {
+                self.awake_rows[index / self.columns] = true;
+            }
+        }
+    }
+
+    // Coordinates are relative to the tracked area
+    #[inline(always)]
+    pub(crate) fn is_row_awake(&self, y: usize) -> bool {
+        !self.enabled || self.awake_rows[self.row_of(y)]
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_awake(&self, x: usize, y: usize) -> bool {
+        !self.enabled || self.awake_frames[self.row_of(y) * self.columns + self.column_of(x)] > 0
+    }
+
+    #[inline(always)]
+    fn column_of(&self, x: usize) -> usize {
+        ((self.origin_x + x) >> ACTIVITY_CHUNK_SHIFT) - self.first_column
+    }
+
+    #[inline(always)]
+    fn row_of(&self, y: usize) -> usize {
+        ((self.origin_y + y) >> ACTIVITY_CHUNK_SHIFT) - self.first_row
+    }
+
+    // Coordinates are relative to the tracked area
+    #[inline(always)]
+    pub(crate) fn mark_written(&mut self, x: usize, y: usize) {
+        let first_column = self.column_of(x.saturating_sub(WAKE_MARGIN));
+        let last_column = self.column_of((x + WAKE_MARGIN).min(self.width - 1));
+        let first_row = self.row_of(y.saturating_sub(WAKE_MARGIN));
+        let last_row = self.row_of((y + WAKE_MARGIN).min(self.height - 1));
+
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                self.written[row * self.columns + column] = true;
+            }
+        }
+
+        if self.wrap_x && (x < WAKE_MARGIN || x + WAKE_MARGIN >= self.width) {
+            let column = if x < WAKE_MARGIN { self.columns - 1 } else { 0 };
+            for row in first_row..=last_row {
+                self.written[row * self.columns + column] = true;
+            }
+        }
+
+        if self.wrap_y && (y < WAKE_MARGIN || y + WAKE_MARGIN >= self.height) {
+            let row = if y < WAKE_MARGIN { self.rows - 1 } else { 0 };
+            for column in first_column..=last_column {
+                self.written[row * self.columns + column] = true;
+            }
+        }
+    }
+
+    // Only the whole world wraps, chunk workers never do
+    pub(crate) fn set_wrapping(&mut self, wrap_x: bool, wrap_y: bool) {
+        self.wrap_x = wrap_x;
+        self.wrap_y = wrap_y;
+    }
+}
+
+// Used by the chunk scheduler, workers track their own region and write it back when they are done
+#[cfg(not(target_family = "wasm"))]
+impl ChunkActivity {
+    pub(crate) fn empty() -> ChunkActivity {
+        ChunkActivity::new(0, 0)
+    }
+
+    pub(crate) fn load_region(&mut self, world: &ChunkActivity, region: &ChunkRect) {
+        self.enabled = world.enabled;
+        self.set_area(region.x, region.y, region.width, region.height);
+
+        for row in 0..self.rows {
+            let world_start = (self.first_row + row) * world.columns + self.first_column;
+            let awake_frames = &mut self.awake_frames[row * self.columns..(row + 1) * self.columns];
+            awake_frames.copy_from_slice(&world.awake_frames[world_start..world_start + self.columns]);
+            self.awake_rows[row] = awake_frames.iter().any(|frames| *frames > 0);
+        }
+    }
+
+    pub(crate) fn store_region(&mut self, worker: &ChunkActivity) {
+        for row in 0..worker.rows {
+            let world_start = (worker.first_row + row) * self.columns + worker.first_column;
+            for column in 0..worker.columns {
+                self.written[world_start + column] |= worker.written[row * worker.columns + column];
+            }
+        }
+    }
+}
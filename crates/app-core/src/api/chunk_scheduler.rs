@@ -0,0 +1,225 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::api::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkRect {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+struct ChunkWorker {
+    // Cells this worker updates
+    chunk: ChunkRect,
+    // Cells this worker copies, the chunk plus the halo, clipped to the world
+    region: ChunkRect,
+    state: SimulationState,
+    seed: u64,
+}
+
+/// Updates the world in chunks on several threads. Chunks are split in a checkerboard of 4 phases,
+/// chunks in the same phase never touch so they can run in parallel, and phases run one after the other.
+/// Each worker simulates a copy of its region that is written back once the phase is done.
+pub(crate) struct ChunkScheduler {
+    chunk_size: usize,
+    pool: WorkerPool,
+    phases: [Vec<ChunkWorker>; 4],
+    world_size: (usize, usize),
+}
+
+impl ChunkScheduler {
+    pub(crate) fn new(chunk_size: usize) -> ChunkScheduler {
+        let threads = thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1);
+
+        ChunkScheduler {
+            chunk_size: chunk_size.max(CHUNK_HALO * 2),
+            pool: WorkerPool::new(threads),
+            phases: Default::default(),
+            world_size: (0, 0),
+        }
+    }
+
+    pub(crate) fn get_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    // Workers are kept between frames so their buffers don't have to be allocated every frame
+    fn rebuild(&mut self, state: &SimulationState) {
+        let (width, height) = (state.width(), state.height());
+        self.world_size = (width, height);
+        self.phases.iter_mut().for_each(|phase| phase.clear());
+
+        for chunk_y in 0..height.div_ceil(self.chunk_size) {
+            for chunk_x in 0..width.div_ceil(self.chunk_size) {
+                let chunk = ChunkRect {
+                    x: chunk_x * self.chunk_size,
+                    y: chunk_y * self.chunk_size,
+                    width: self.chunk_size.min(width - chunk_x * self.chunk_size),
+                    height: self.chunk_size.min(height - chunk_y * self.chunk_size),
+                };
+
+                let region_x = chunk.x.saturating_sub(CHUNK_HALO);
+                let region_y = chunk.y.saturating_sub(CHUNK_HALO);
+                let region = ChunkRect {
+                    x: region_x,
+                    y: region_y,
+                    width: (chunk.x + chunk.width + CHUNK_HALO).min(width) - region_x,
+                    height: (chunk.y + chunk.height + CHUNK_HALO).min(height) - region_y,
+                };
+
+                let phase = (chunk_x % 2) + (chunk_y % 2) * 2;
+                self.phases[phase].push(ChunkWorker {
+                    chunk,
+                    region,
+                    state: state.new_worker(),
+                    seed: 0,
+                });
+            }
+        }
+    }
+
+    // Same semantics as SimulationState::update, which cells were updated is tracked for the whole world
+    // so a particle moving into a chunk of a later phase isn't updated twice
+    pub(super) fn update(
+        &mut self,
+        state: &mut SimulationState,
+        plugins: &[Box<dyn Plugin>],
+        order_scheme: &OrderScheme,
+    ) {
+        if self.world_size != (state.width(), state.height()) {
+            self.rebuild(state);
+        }
+
+        state.begin_frame();
+
+        for phase in self.phases.iter_mut() {
+            if phase.is_empty() {
+                continue;
+            }
+
+            // Seeds are taken in chunk order before spawning anything, so the result doesn't depend on thread timing
+            for worker in phase.iter_mut() {
+                worker.seed = state.next_worker_seed();
+            }
+
+            let world = state.world_view();
+            let world = &world;
+            let workers_per_thread = phase.len().div_ceil(self.pool.threads());
+
+            let jobs = phase
+                .chunks_mut(workers_per_thread)
+                .map(|workers| {
+                    Box::new(move || {
+                        for worker in workers {
+                            worker.state.load_region(world, &worker.region, worker.seed);
+                            worker.state.update_region(
+                                plugins,
+                                &worker.region,
+                                &worker.chunk,
+                                order_scheme,
+                            );
+                        }
+                    }) as Box<dyn FnOnce() + Send + '_>
+                })
+                .collect();
+            self.pool.run_all(jobs);
+
+            for worker in phase.iter() {
+                state.store_region(&worker.state, &worker.region);
+            }
+        }
+
+        state.end_frame(plugins);
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Threads kept for as long as the scheduler, spawning them for every phase cost more than
+// the phases themselves. The thread that runs the phase takes a share of the work too
+struct WorkerPool {
+    jobs: Vec<mpsc::Sender<Job>>,
+    done: mpsc::Receiver<thread::Result<()>>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    fn new(threads: usize) -> WorkerPool {
+        let (done_sender, done) = mpsc::channel();
+        let mut jobs = Vec::new();
+        let mut handles = Vec::new();
+
+        for _ in 1..threads {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            let done = done_sender.clone();
+            handles.push(thread::spawn(move || {
+                for job in receiver {
+                    // Reported even if it panics, run_all would wait forever otherwise
+                    let _ = done.send(panic::catch_unwind(AssertUnwindSafe(job)));
+                }
+            }));
+            jobs.push(sender);
+        }
+
+        WorkerPool { jobs, done, handles }
+    }
+
+    fn threads(&self) -> usize {
+        self.jobs.len() + 1
+    }
+
+    // One job per thread at most, the first one runs here. Panics in any of them are passed on
+    // once all of them are done
+    fn run_all<'a>(&self, jobs: Vec<Box<dyn FnOnce() + Send + 'a>>) {
+        let mut jobs = jobs.into_iter();
+        let local = jobs.next();
+
+        let mut sent = 0;
+        let mut lost = false;
+        for (sender, job) in self.jobs.iter().zip(jobs.by_ref()) {
+            // Jobs borrow the world, but this doesn't return before every job that was sent is done,
+            // so nothing they borrow can go away while they run
+            let job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'a>, Job>(job) };
+            if sender.send(job).is_err() {
+                lost = true;
+                break;
+            }
+            sent += 1;
+        }
+        lost |= jobs.len() > 0;
+
+        let local = local.map(|job| panic::catch_unwind(AssertUnwindSafe(job)));
+        let mut result = Ok(());
+        for _ in 0..sent {
+            match self.done.recv() {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => result = Err(error),
+                Err(_) => unreachable!("every pool thread keeps a sender"),
+            }
+        }
+
+        if let Some(Err(error)) = local {
+            panic::resume_unwind(error);
+        }
+        if let Err(error) = result {
+            panic::resume_unwind(error);
+        }
+        assert!(!lost, "more jobs than threads, or a chunk worker thread is gone");
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channels ends the threads
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
@@ -13,6 +13,10 @@ impl CustomRange {
             step,
         }
     }
+
+    pub fn is_ascending(&self) -> bool {
+        self.step > 0
+    }
 }
 
 impl Iterator for CustomRange {
@@ -0,0 +1,49 @@
+// Blast resistance of particle types that don't set one, an explosion of strength 2 destroys them right next to it
+pub const DEFAULT_BLAST_RESISTANCE: f32 = 1.0;
+
+// Cells per frame a thrown particle gets for each point of power that reaches it
+pub(crate) const THROW_SPEED: f32 = 4.0;
+
+/// Blast centered on a cell. The power reaching each cell goes down from `strength` at the center
+/// to nothing past `radius`, and what happens to the cell depends on its type's blast resistance:
+/// more power than the resistance destroys it, more than half of it throws it outwards, anything
+/// less leaves it where it is. Particles that can fly are thrown faster the more power reaches them,
+/// the rest are pushed one cell if there's room
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Explosion {
+    pub radius: i32,
+    pub strength: f32,
+    // Destroyed cells turn into this particle, like fire or smoke, or are left empty without it
+    pub spawn: Option<u16>,
+    // Chance of each destroyed cell to turn into the spawned particle, from 0 to 1
+    pub spawn_chance: f32,
+}
+
+impl Explosion {
+    pub fn new(radius: i32, strength: f32) -> Explosion {
+        Explosion {
+            radius,
+            strength,
+            spawn: None,
+            spawn_chance: 1.0,
+        }
+    }
+
+    pub fn with_spawn(self, spawn: u16, spawn_chance: f32) -> Explosion {
+        Explosion {
+            spawn: Some(spawn),
+            spawn_chance,
+            ..self
+        }
+    }
+
+    // Power left at that distance from the center
+    pub(crate) fn power_at(&self, distance: f32) -> f32 {
+        self.strength * (1.0 - distance / (self.radius + 1) as f32)
+    }
+
+    // Impulse of a particle thrown from there, outwards is a unit vector pointing away from the center
+    pub(crate) fn throw(power: f32, outwards: [f32; 2]) -> [f32; 2] {
+        outwards.map(|direction| direction * power * THROW_SPEED)
+    }
+}
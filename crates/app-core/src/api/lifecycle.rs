@@ -0,0 +1,48 @@
+use crate::api::*;
+
+// Hooks calling hooks that call hooks can go on forever, after this many the rest are dropped
+pub(crate) const MAX_HOOK_CALLS: usize = 4096;
+
+/// Which of the optional Plugin callbacks the particle wants. Cells are only watched for
+/// changes while at least one loaded particle asks for something, so leave them off if unused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LifecycleHooks {
+    // Placed by the brush or set over a particle of another type
+    pub on_create: bool,
+    // Overwritten or erased by a particle of another type, moving around doesn't count
+    pub on_destroy: bool,
+    // One of the 8 cells around turned into another type that isn't empty
+    pub on_neighbor_changed: bool,
+}
+
+impl LifecycleHooks {
+    pub fn any(&self) -> bool {
+        self.on_create || self.on_destroy || self.on_neighbor_changed
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum LifecycleEvent {
+    // Type that was created, the cell may hold something else by the time the hook runs
+    Created(u16),
+    Destroyed(Particle),
+    // Relative position of the neighbour that changed, like api.get takes it
+    NeighborChanged(i32, i32),
+}
+
+// Writes only queue the hooks, they run once the plugin that caused them is done
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PendingHook {
+    pub x: usize,
+    pub y: usize,
+    pub event: LifecycleEvent,
+}
+
+// How a cell ended up with another particle. Particles moving around aren't created or destroyed,
+// but what they land on is, and the neighbours see a new type either way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CellChange {
+    Replaced,
+    MovedOver,
+    Swapped,
+}
@@ -0,0 +1,55 @@
+// Fastest a particle can go, in cells per frame
+pub const MAX_SPEED: f32 = 8.0;
+
+// Particles resting on something that go slower than this stop, and their plugin takes over again
+pub(crate) const REST_SPEED: f32 = 0.5;
+
+// Share of its velocity a particle hands to a particle with motion it runs into, that's what makes splashes
+pub(crate) const MOMENTUM_TRANSFER: f32 = 0.5;
+
+/// Ballistic movement of a particle type. Only types with it can have a velocity, and while a particle
+/// has one the core moves it along it instead of updating its plugin, until it comes to rest on something
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionProperties {
+    // Cells per frame added downwards every frame
+    pub gravity: f32,
+    // Share of the speed lost every frame, from 0 to 1
+    pub drag: f32,
+    // Share of the speed kept when bouncing off something, from 0 to 1
+    pub bounce: f32,
+}
+
+impl Default for MotionProperties {
+    fn default() -> Self {
+        MotionProperties {
+            gravity: 0.3,
+            drag: 0.02,
+            bounce: 0.2,
+        }
+    }
+}
+
+impl MotionProperties {
+    pub(crate) fn sanitized(self) -> MotionProperties {
+        MotionProperties {
+            gravity: self.gravity.clamp(-MAX_SPEED, MAX_SPEED),
+            drag: self.drag.clamp(0.0, 1.0),
+            bounce: self.bounce.clamp(0.0, 1.0),
+        }
+    }
+}
+
+pub(crate) fn clamp_velocity(velocity: [f32; 2]) -> [f32; 2] {
+    velocity.map(|speed| match speed.is_finite() {
+        true => speed.clamp(-MAX_SPEED, MAX_SPEED),
+        false => 0.0,
+    })
+}
+
+// Cell the line from 0, 0 to x, y is at after that many steps, the last step lands on x, y
+pub(crate) fn line_step(x: i32, y: i32, step: i32, steps: i32) -> [i32; 2] {
+    [
+        (x as f32 * step as f32 / steps as f32).round() as i32,
+        (y as f32 * step as f32 / steps as f32).round() as i32,
+    ]
+}
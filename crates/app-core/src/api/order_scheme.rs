@@ -13,6 +13,22 @@ impl OrderScheme {
             order_y: order_y,
         }
     }
+
+    // Same scan direction but only over the given rect, used to update chunks on their own
+    pub(super) fn sub_scheme(&self, x: usize, y: usize, width: usize, height: usize) -> OrderScheme {
+        fn range(ascending: bool, start: usize, length: usize) -> CustomRange {
+            if ascending {
+                CustomRange::new(start as isize, (start + length) as isize, 1)
+            } else {
+                CustomRange::new((start + length) as isize - 1, start as isize - 1, -1)
+            }
+        }
+
+        OrderScheme::new(
+            range(self.order_x.is_ascending(), x, width),
+            range(self.order_y.is_ascending(), y, height),
+        )
+    }
 }
 
 pub struct OrderSchemes {
@@ -46,6 +62,11 @@ impl OrderSchemes {
         }
     }
 
+    // Schemes cycle once per frame, this puts the cycle where it would be after frame_count updates
+    pub(super) fn sync_with_frame(&mut self, frame_count: u32) {
+        self.current = (frame_count % 4) as usize;
+    }
+
     pub(super) fn get_ciclying(&mut self) -> &OrderScheme {
         let scheme = match self.current {
             0 => &self.ltr_ttb,
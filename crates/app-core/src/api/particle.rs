@@ -1,20 +1,24 @@
+// 8 bytes, finding a cell in the grid is cheaper that way. C layout as native plugins get it through CParticleApi
 #[derive(Clone, Debug, Copy)]
+#[repr(C, align(8))]
 pub struct Particle {
-    pub id: u8,
+    // Up to MAX_PARTICLE_TYPES, the last value is Particle::INVALID
+    pub id: u16,
     pub opacity: u8,
     pub hue_shift: u8,
     pub color_fade: u8,
     pub extra: u8,
     pub extra2: u8,
     pub extra3: u8,
-    pub(crate) clock: u8,
 }
 
+// Particle::INVALID takes the last id
+pub const MAX_PARTICLE_TYPES: usize = u16::MAX as usize;
+
 impl Particle {
     pub(crate) fn new() -> Particle {
         Particle {
             id: 0,
-            clock: 0,
             opacity: 100,
             color_fade: 0,
             hue_shift: 0,
@@ -24,11 +28,10 @@ impl Particle {
         }
     }
 
-    fn from_id(id: u8) -> Particle {
+    fn from_id(id: u16) -> Particle {
         //print something
         Particle {
             id,
-            clock: 0,
             opacity: 100,
             hue_shift: 0,
             color_fade: 0,
@@ -40,7 +43,6 @@ impl Particle {
 
     pub const EMPTY: Particle = Particle {
         id: 0,
-        clock: 0,
         opacity: 100,
         color_fade: 0,
         hue_shift: 0,
@@ -50,8 +52,7 @@ impl Particle {
     };
 
     pub const INVALID: Particle = Particle {
-        id: u8::MAX,
-        clock: 0,
+        id: u16::MAX,
         opacity: 100,
         color_fade: 0,
         hue_shift: 0,
@@ -69,24 +70,24 @@ impl PartialEq for Particle {
 
 impl PartialEq<usize> for Particle {
     fn eq(&self, other: &usize) -> bool {
-        self.id == *other as u8
+        self.id as usize == *other
     }
 }
 
-impl PartialEq<u8> for Particle {
-    fn eq(&self, other: &u8) -> bool {
+impl PartialEq<u16> for Particle {
+    fn eq(&self, other: &u16) -> bool {
         self.id == *other
     }
 }
 
 impl From<usize> for Particle {
     fn from(id: usize) -> Self {
-        Particle::from_id(id as u8)
+        Particle::from_id(id as u16)
     }
 }
 
-impl From<u8> for Particle {
-    fn from(id: u8) -> Self {
-        Particle::from_id(id as u8)
+impl From<u16> for Particle {
+    fn from(id: u16) -> Self {
+        Particle::from_id(id)
     }
 }
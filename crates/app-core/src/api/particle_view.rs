@@ -0,0 +1,116 @@
+use std::ops::Index;
+
+use crate::api::*;
+
+/// Read only 2D view over particles stored row-major. It can cover the whole world or just a
+/// rectangle of it, in that case rows aren't next to each other in memory but they are still slices.
+/// `view[y][x]` works the same as it did with nested vectors.
+#[derive(Clone, Copy)]
+pub struct ParticleView<'a> {
+    particles: &'a [Particle],
+    width: usize,
+    height: usize,
+    // Distance between the start of two rows, it's the world width for regions
+    stride: usize,
+}
+
+impl<'a> ParticleView<'a> {
+    pub(crate) fn new(particles: &'a [Particle], width: usize, height: usize) -> ParticleView<'a> {
+        debug_assert_eq!(particles.len(), width * height);
+
+        ParticleView {
+            particles,
+            width,
+            height,
+            stride: width,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&'a Particle> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(&self.particles[y * self.stride + x])
+    }
+
+    pub fn row(&self, y: usize) -> &'a [Particle] {
+        let start = y * self.stride;
+        &self.particles[start..start + self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &'a [Particle]> + ExactSizeIterator {
+        let view = *self;
+        (0..self.height).map(move |y| view.row(y))
+    }
+
+    // Cells in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = &'a Particle> {
+        self.rows().flatten()
+    }
+
+    // None if the region doesn't fit inside this view
+    pub fn region(&self, x: usize, y: usize, width: usize, height: usize) -> Option<ParticleView<'a>> {
+        if x + width > self.width || y + height > self.height {
+            return None;
+        }
+
+        // An empty region still has to be a valid slice, so it doesn't point anywhere
+        if width == 0 || height == 0 {
+            return Some(ParticleView {
+                particles: &[],
+                width,
+                height,
+                stride: 0,
+            });
+        }
+
+        let start = y * self.stride + x;
+        let end = start + (height - 1) * self.stride + width;
+
+        Some(ParticleView {
+            particles: &self.particles[start..end],
+            width,
+            height,
+            stride: self.stride,
+        })
+    }
+
+    // The whole thing as one slice, only possible when rows are contiguous, which is always
+    // the case for the world view. Handy for snapshots, diffs or handing the world to other languages
+    pub fn as_slice(&self) -> Option<&'a [Particle]> {
+        match self.stride == self.width || self.height <= 1 {
+            true => Some(&self.particles[..self.width * self.height]),
+            false => None,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<Particle> {
+        self.iter().copied().collect()
+    }
+}
+
+impl<'a> Index<usize> for ParticleView<'a> {
+    type Output = [Particle];
+
+    fn index(&self, y: usize) -> &Self::Output {
+        self.row(y)
+    }
+}
+
+impl<'a> Index<(usize, usize)> for ParticleView<'a> {
+    type Output = Particle;
+
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        assert!(x < self.width, "x {} out of bounds for width {}", x, self.width);
+        &self.row(y)[x]
+    }
+}
@@ -3,18 +3,37 @@ use std::vec;
 use crate::api::*;
 
 pub type ParticleApi = crate::api::SimulationState;
-pub trait Plugin {
+// Send + Sync because plugins are shared between threads when the world is updated in chunks
+pub trait Plugin: Send + Sync {
     fn register(&mut self) -> PluginResult;
     fn update(&self, api: &mut ParticleApi);
     // Called when the simulation adds or remove a new Plugin
     // So particles can cache the id of other particles
     fn on_plugin_changed(&mut self, _: &ParticleApi) {}
+
+    // Lifecycle callbacks, only called for the ones asked for in PluginResult::hooks. They run right
+    // after whatever caused them, with the particle's cell as the current position
+    fn on_create(&self, _api: &mut ParticleApi) {}
+    // The cell already holds whatever replaced the particle, the particle itself is given instead
+    fn on_destroy(&self, _api: &mut ParticleApi, _particle: Particle) {}
+    // x, y is where the neighbour is, relative to the particle
+    fn on_neighbor_changed(&self, _api: &mut ParticleApi, _x: i32, _y: i32) {}
 }
 
 pub struct PluginResult {
     pub name: String,
     pub color: Color,
     pub color2: Color,
+    // Up to PROPERTY_SLOTS named values stored per particle, extra ones are ignored
+    pub properties: Vec<PropertyDefinition>,
+    pub thermal: ThermalProperties,
+    // Reactions this plugin brings, the reactant doesn't have to be the particle itself
+    pub reactions: Vec<Reaction>,
+    pub hooks: LifecycleHooks,
+    // Particles without it never fly, impulses on them are ignored
+    pub motion: Option<MotionProperties>,
+    // How much power an explosion needs to destroy the particle, f32::INFINITY for something that never breaks
+    pub blast_resistance: f32,
 }
 
 impl Default for PluginResult {
@@ -23,6 +42,12 @@ impl Default for PluginResult {
             name: String::from("Empty"),
             color: NOT_BLACK,
             color2: NOT_BLACK,
+            properties: Vec::new(),
+            thermal: ThermalProperties::default(),
+            reactions: Vec::new(),
+            hooks: LifecycleHooks::default(),
+            motion: None,
+            blast_resistance: DEFAULT_BLAST_RESISTANCE,
         }
     }
 }
@@ -35,12 +60,32 @@ impl From<PluginResult> for ParticleCommonData {
         let (h,s,l) = rgb_to_hsl(color);
         let (h2, s2, l2) = rgb_to_hsl(color2);
 
+        let mut properties = plugin_result.properties;
+        if properties.len() > PROPERTY_SLOTS {
+            println!(
+                "{} declares {} properties but only {} fit in a particle, the rest are ignored",
+                plugin_result.name,
+                properties.len(),
+                PROPERTY_SLOTS
+            );
+            properties.truncate(PROPERTY_SLOTS);
+        }
+
         ParticleCommonData {
             name: plugin_result.name,
             color: plugin_result.color.into(),
             color2: plugin_result.color2.into(),
             color_hsl: [h, s, l],
             color_hsl2: [h2, s2, l2],
+            properties,
+            thermal: plugin_result.thermal.sanitized(),
+            phase_changes: ResolvedPhaseChanges::default(),
+            reactions: plugin_result.reactions,
+            resolved_reactions: Vec::new(),
+            hooks: plugin_result.hooks,
+            motion: plugin_result.motion.map(MotionProperties::sanitized),
+            blast_resistance: plugin_result.blast_resistance.max(0.0),
+            removed: false,
         }
     }
 }
@@ -59,13 +104,48 @@ impl Plugin for Empty {
     fn update(&self, _api: &mut SimulationState) {}
 }
 
-#[derive(Debug)]
+// Takes the place of a removed plugin, there are no particles of its type left to update
+pub(crate) struct Tombstone;
+
+impl Plugin for Tombstone {
+    fn register(&mut self) -> PluginResult {
+        PluginResult::default()
+    }
+
+    fn update(&self, _api: &mut SimulationState) {}
+}
+
+#[derive(Debug, Clone)]
 pub struct ParticleCommonData {
     pub name: String,
     pub color: [u8; 4],
     pub color2: [u8; 4],
     pub color_hsl: [f32; 3],
     pub color_hsl2: [f32; 3],
+    // Index is the slot the property is stored in
+    pub properties: Vec<PropertyDefinition>,
+    pub thermal: ThermalProperties,
+    // Filled by the simulation state as target ids depend on the other plugins
+    pub(crate) phase_changes: ResolvedPhaseChanges,
+    // Declared by this plugin, whatever their reactant is
+    pub reactions: Vec<Reaction>,
+    // Reactions where this particle is the reactant, from every plugin and the reaction table
+    pub(crate) resolved_reactions: Vec<ResolvedReaction>,
+    pub hooks: LifecycleHooks,
+    pub motion: Option<MotionProperties>,
+    pub blast_resistance: f32,
+    // Left behind by a removed plugin so the ids after it don't change. The name is kept so the plugin gets
+    // the same id back if it's loaded again, no other particle ever takes it
+    pub removed: bool,
+}
+
+impl ParticleCommonData {
+    pub(crate) fn tombstone(name: String) -> ParticleCommonData {
+        ParticleCommonData {
+            removed: true,
+            ..PluginResult { name, ..Default::default() }.into()
+        }
+    }
 }
 
 // impl ParticleCommonData {
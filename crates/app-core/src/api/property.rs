@@ -0,0 +1,111 @@
+use crate::api::*;
+
+// Properties are stored on extra, extra2 and extra3, in the order the plugin declares them
+pub const PROPERTY_SLOTS: usize = 3;
+
+/// Named per particle value a plugin declares, like a temperature or a lifetime.
+/// Plugins keep working with plain numbers, the name is only so blocks, tools and
+/// snapshots can refer to it without caring about which slot it ended up in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyDefinition {
+    pub name: String,
+    pub default: u8,
+    // Inclusive range, values set through the api are clamped to it
+    pub min: u8,
+    pub max: u8,
+}
+
+impl PropertyDefinition {
+    pub fn new(name: &str, default: u8, min: u8, max: u8) -> PropertyDefinition {
+        PropertyDefinition {
+            name: name.to_string(),
+            default,
+            min,
+            max,
+        }
+    }
+
+    pub fn clamp(&self, value: i32) -> u8 {
+        value.clamp(self.min as i32, self.max as i32) as u8
+    }
+}
+
+impl Particle {
+    pub fn get_slot(&self, slot: usize) -> u8 {
+        match slot {
+            0 => self.extra,
+            1 => self.extra2,
+            2 => self.extra3,
+            _ => 0,
+        }
+    }
+
+    pub fn set_slot(&mut self, slot: usize, value: u8) {
+        match slot {
+            0 => self.extra = value,
+            1 => self.extra2 = value,
+            2 => self.extra3 = value,
+            _ => {}
+        }
+    }
+}
+
+// Position of a property inside a particle definition, that's also its slot
+pub(crate) fn find_property(properties: &[PropertyDefinition], name: &str) -> Option<usize> {
+    properties
+        .iter()
+        .position(|property| property.name.eq_ignore_ascii_case(name))
+}
+
+/// Moves particle state from an old property layout to a new one by name. Properties that
+/// are still there keep their value, new ones get their default and removed ones are dropped.
+/// Slots the new layout doesn't use are left as they are, some plugins use them directly.
+pub(crate) struct PropertyRemap {
+    // For each slot of the new layout, the slot its value comes from in the old one
+    sources: Vec<Option<usize>>,
+    properties: Vec<PropertyDefinition>,
+}
+
+impl PropertyRemap {
+    // None when nothing has to move
+    pub(crate) fn new<T: AsRef<str>>(
+        old_names: &[T],
+        properties: &[PropertyDefinition],
+    ) -> Option<PropertyRemap> {
+        let unchanged = old_names.len() == properties.len()
+            && old_names
+                .iter()
+                .zip(properties.iter())
+                .all(|(name, property)| property.name.eq_ignore_ascii_case(name.as_ref()));
+
+        if unchanged {
+            return None;
+        }
+
+        let sources = properties
+            .iter()
+            .map(|property| {
+                old_names
+                    .iter()
+                    .position(|name| property.name.eq_ignore_ascii_case(name.as_ref()))
+            })
+            .collect();
+
+        Some(PropertyRemap {
+            sources,
+            properties: properties.to_vec(),
+        })
+    }
+
+    pub(crate) fn apply(&self, particle: &mut Particle) {
+        let old = *particle;
+
+        for (slot, (source, property)) in self.sources.iter().zip(self.properties.iter()).enumerate() {
+            let value = match source {
+                Some(source) => property.clamp(old.get_slot(*source) as i32),
+                None => property.default,
+            };
+            particle.set_slot(slot, value);
+        }
+    }
+}
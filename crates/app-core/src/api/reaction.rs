@@ -0,0 +1,125 @@
+use crate::api::*;
+use rustc_hash::FxHashMap;
+
+/// "A touching B becomes C, and B becomes D" rule. Reactions are checked by the core for every
+/// cell before its plugin update, so simple chemistry doesn't need any code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reaction {
+    // Particle being updated
+    pub reactant: String,
+    // Particle it has to touch
+    pub neighbor: String,
+    // What each of the two cells turns into, None leaves it as it is
+    pub reactant_product: Option<String>,
+    pub neighbor_product: Option<String>,
+    // Chance per frame of reacting with a neighbour at each side, same order as ParticleApi::NEIGHBORS
+    pub probabilities: [f32; 8],
+}
+
+impl Reaction {
+    // Same probability on every side
+    pub fn new(
+        reactant: &str,
+        neighbor: &str,
+        reactant_product: Option<&str>,
+        neighbor_product: Option<&str>,
+        probability: f32,
+    ) -> Reaction {
+        Reaction {
+            reactant: reactant.to_string(),
+            neighbor: neighbor.to_string(),
+            reactant_product: reactant_product.map(str::to_string),
+            neighbor_product: neighbor_product.map(str::to_string),
+            probabilities: [probability; 8],
+        }
+    }
+
+    // Only reacts with neighbours at these sides, like water on top of lava
+    pub fn only_towards(mut self, directions: &[[i32; 2]]) -> Reaction {
+        for (index, neighbor) in ParticleApi::NEIGHBORS.iter().enumerate() {
+            if !directions.contains(&[neighbor.x, neighbor.y]) {
+                self.probabilities[index] = 0.0;
+            }
+        }
+
+        self
+    }
+
+    pub fn with_probability(mut self, direction: [i32; 2], probability: f32) -> Reaction {
+        if let Some(index) = neighbor_index(direction) {
+            self.probabilities[index] = probability;
+        }
+
+        self
+    }
+}
+
+fn neighbor_index(direction: [i32; 2]) -> Option<usize> {
+    ParticleApi::NEIGHBORS
+        .iter()
+        .position(|neighbor| neighbor.x == direction[0] && neighbor.y == direction[1])
+}
+
+/// Reactions added from outside the plugins, like a JSON file, keyed by reactant name.
+/// Plugins declare theirs in the PluginResult, both end up checked the same way.
+#[derive(Debug, Clone, Default)]
+pub struct ReactionTable {
+    reactions: FxHashMap<String, Vec<Reaction>>,
+}
+
+impl ReactionTable {
+    pub fn add(&mut self, reaction: Reaction) {
+        self.reactions
+            .entry(reaction.reactant.to_lowercase())
+            .or_default()
+            .push(reaction);
+    }
+
+    pub fn get(&self, reactant: &str) -> &[Reaction] {
+        self.reactions
+            .get(&reactant.to_lowercase())
+            .map_or(&[], |reactions| reactions.as_slice())
+    }
+
+    pub fn remove(&mut self, reactant: &str) {
+        self.reactions.remove(&reactant.to_lowercase());
+    }
+
+    pub fn clear(&mut self) {
+        self.reactions.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Reaction> {
+        self.reactions.values().flatten()
+    }
+}
+
+// Reaction with names already turned into ids, they are solved each time plugins or reactions change
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedReaction {
+    pub(crate) neighbor: u16,
+    pub(crate) reactant_product: Option<u16>,
+    pub(crate) neighbor_product: Option<u16>,
+    pub(crate) probabilities: [f32; 8],
+}
+
+impl ResolvedReaction {
+    // None if any of the particles it mentions isn't loaded, the reaction is just ignored until it is
+    pub(crate) fn resolve(reaction: &Reaction, id_from_name: impl Fn(&str) -> u16) -> Option<ResolvedReaction> {
+        let resolve = |name: &str| match id_from_name(name) {
+            id if id == Particle::INVALID.id => None,
+            id => Some(id),
+        };
+        let resolve_product = |product: &Option<String>| match product {
+            Some(name) => resolve(name).map(Some),
+            None => Some(None),
+        };
+
+        Some(ResolvedReaction {
+            neighbor: resolve(&reaction.neighbor)?,
+            reactant_product: resolve_product(&reaction.reactant_product)?,
+            neighbor_product: resolve_product(&reaction.neighbor_product)?,
+            probabilities: reaction.probabilities.map(|probability| probability.clamp(0.0, 1.0)),
+        })
+    }
+}
@@ -4,41 +4,73 @@ pub struct Simulation {
     simulation_state: SimulationState,
     plugin_data: PluginData,
     order_scheme: OrderSchemes,
-    selected_plugin: u8,
+    selected_plugin: u16,
+    // Only present when multithreading is enabled, wasm always takes the single thread path
+    #[cfg(not(target_family = "wasm"))]
+    chunk_scheduler: Option<ChunkScheduler>,
 }
 
 impl Simulation {
     pub fn new(width: usize, height: usize) -> Simulation {
+        Simulation::new_with_seed(width, height, fastrand::u64(..))
+    }
+
+    // A scene plus a seed plus N steps always gives the same world, useful for bug reports and golden tests
+    pub fn new_with_seed(width: usize, height: usize, seed: u64) -> Simulation {
         Simulation {
-            simulation_state: SimulationState::new(width, height),
+            simulation_state: SimulationState::new_with_seed(width, height, seed),
             plugin_data: PluginData::new(),
             order_scheme: OrderSchemes::new(width, height),
             selected_plugin: 1,
+            #[cfg(not(target_family = "wasm"))]
+            chunk_scheduler: None,
         }
     }
 
-    pub fn get_selected_plugin(&self) -> u8 {
+    pub fn get_selected_plugin(&self) -> u16 {
         self.selected_plugin
     }
 
+    // Removed plugins are skipped
     pub fn select_next_plugin(&mut self)
     {
-        self.selected_plugin = (self.selected_plugin + 1) % self.get_plugin_count() as u8;
+        self.select_plugin_by(1);
     }
 
     pub fn select_previous_plugin(&mut self)
     {
-        self.selected_plugin = (self.selected_plugin + self.get_plugin_count() as u8 - 1) % self.get_plugin_count() as u8;
+        self.select_plugin_by(self.get_plugin_count() - 1);
+    }
+
+    fn select_plugin_by(&mut self, step: usize) {
+        let count = self.get_plugin_count();
+        let mut selected = self.selected_plugin as usize;
+        for _ in 0..count {
+            selected = (selected + step) % count;
+            if self.simulation_state.has_particle_type(selected as u16) {
+                self.selected_plugin = selected as u16;
+                return;
+            }
+        }
+    }
+
+    // Ids of removed plugins are ignored
+    pub fn set_selected_plugin(&mut self, selected_plugin: u16) -> () {
+        if self.simulation_state.has_particle_type(selected_plugin) {
+            self.selected_plugin = selected_plugin;
+        }
     }
 
-    pub fn set_selected_plugin(&mut self, selected_plugin: u8) -> () {
-        self.selected_plugin = selected_plugin;
+    // Whether the id belongs to a loaded plugin, removed ones keep their id so the others don't change
+    pub fn has_particle_type(&self, id: u16) -> bool {
+        self.simulation_state.has_particle_type(id)
     }
 
     pub fn get_particle_definitions(&self) -> &Vec<ParticleCommonData> {
         &self.simulation_state.get_particle_definitions()
     }
 
+    // Removed plugins included, see has_particle_type
     pub fn get_plugin_count(&self) -> usize {
         self.plugin_data.plugins.len()
     }
@@ -52,28 +84,101 @@ impl Simulation {
     }
 
     pub fn update(&mut self) -> () {
-        self.simulation_state.update(
-            &mut self.plugin_data.plugins,
-            &self.order_scheme.get_ciclying(),
-        );
+        let order_scheme = self.order_scheme.get_ciclying();
+
+        // Chunk workers can't see the other side of the world, so worlds that wrap around use a single thread
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(scheduler) = &mut self.chunk_scheduler {
+            if !self.simulation_state.get_boundaries().wraps() {
+                scheduler.update(&mut self.simulation_state, &self.plugin_data.plugins, order_scheme);
+                return;
+            }
+        }
+
+        self.simulation_state
+            .update(&self.plugin_data.plugins, order_scheme);
+    }
+
+    // Updates non adjacent chunks in parallel, worth it for big worlds. Plugins can't reach further than
+    // CHUNK_HALO cells out of their chunk while this is enabled
+    #[cfg(not(target_family = "wasm"))]
+    pub fn set_multithreaded(&mut self, enabled: bool, chunk_size: usize) {
+        self.chunk_scheduler = enabled.then(|| ChunkScheduler::new(chunk_size));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub fn is_multithreaded(&self) -> bool {
+        self.chunk_scheduler.is_some()
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub fn get_chunk_size(&self) -> Option<usize> {
+        self.chunk_scheduler
+            .as_ref()
+            .map(|scheduler| scheduler.get_chunk_size())
+    }
+
+    // What happens past each edge of the world, every edge is an invisible wall by default.
+    // Worlds with an edge that wraps around are updated on a single thread even if multithreading is enabled
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.simulation_state.set_boundaries(boundaries);
+    }
+
+    pub fn get_boundaries(&self) -> &Boundaries {
+        self.simulation_state.get_boundaries()
     }
 
     pub fn get_buffer(&self) -> &[u8] {
         self.simulation_state.get_buffer()
     }
 
-    pub fn get_particles(&self) -> &Vec<Vec<Particle>> {
+    // Area whose colors changed since the last call, None if nothing did. Meant for uploading
+    // only part of the buffer to the GPU, so it should be called once per drawn frame
+    pub fn take_dirty_rect(&mut self) -> Option<DirtyRect> {
+        self.simulation_state.take_dirty_rect()
+    }
+
+    // Chunks that had no changes for a couple of frames are skipped until something changes near them
+    pub fn set_sleeping_enabled(&mut self, enabled: bool) {
+        self.simulation_state.set_sleeping_enabled(enabled);
+    }
+
+    pub fn is_sleeping_enabled(&self) -> bool {
+        self.simulation_state.is_sleeping_enabled()
+    }
+
+    // Returns (awake, total) chunks
+    pub fn get_awake_chunks(&self) -> (usize, usize) {
+        self.simulation_state.get_awake_chunks()
+    }
+
+    pub fn get_particles(&self) -> ParticleView<'_> {
         self.simulation_state.get_particles()
     }
 
+    // None outside the world
+    pub fn get_temperature(&self, x: usize, y: usize) -> Option<f32> {
+        self.simulation_state.get_temperature_at(x, y)
+    }
+
     pub fn get_particle_name(&self, id: usize) -> Result<&String, String> {
-        if id >= self.get_plugin_count() {
+        if id >= self.get_plugin_count() || !self.has_particle_type(id as u16) {
             return Err("Particle with id ".to_string() + &id.to_string() + " not found");
         }
 
         Ok(&self.simulation_state.get_particle_name(id))
     }
 
+    pub fn get_particle_id(&self, name: &str) -> Result<u16, String> {
+        let id = self.simulation_state.id_from_name(name);
+
+        if id == Particle::INVALID.id {
+            return Err("Particle with name ".to_string() + name + " not found");
+        }
+
+        Ok(id)
+    }
+
     pub fn get_particle_color(&self, id: usize) -> Result<&[u8; 4], String> {
         if id >= self.get_plugin_count() {
             return Err("Particle with id ".to_string() + &id.to_string() + " not found");
@@ -83,13 +188,15 @@ impl Simulation {
     }
 
 
-    pub fn add_plugin(&mut self, plugin: Box<dyn Plugin>) -> () {
+    // Refused when there's no id left for a new particle type
+    pub fn add_plugin(&mut self, plugin: Box<dyn Plugin>) -> Result<(), String> {
         let mut plugin = plugin;
 
-        // The simulation state returns the id of the particle definition if it already exists
+        // The simulation state returns the id of the particle definition if it already exists, or if it took
+        // the place of a removed one
         let id = self
             .simulation_state
-            .add_or_replace_particle_definition(plugin.register().into());
+            .add_or_replace_particle_definition(plugin.register().into())?;
 
         match id {
             Some(id) => {
@@ -105,27 +212,63 @@ impl Simulation {
         
         #[cfg(not(target_family = "wasm"))] // This shouldnt be here, this is because of our wasm version specific thing...
         self.plugin_data.notify(&self.simulation_state);
+        Ok(())
     }
 
-    pub fn remove_plugin(&mut self, id: u8) -> () {
-        self.simulation_state.remove_particle_definition(id);
-        self.plugin_data.plugins.remove(id as usize);
+    // Other plugins keep their ids, the removed one is left as a tombstone until the same plugin is loaded again
+    pub fn remove_plugin(&mut self, id: u16) -> () {
+        if !self.simulation_state.remove_particle_definition(id) {
+            return;
+        }
+
+        self.plugin_data.plugins[id as usize] = Box::new(Tombstone);
         self.repaint();
         self.plugin_data.notify(&self.simulation_state);
 
-        self.selected_plugin = self.selected_plugin.min(self.get_plugin_count() as u8 - 1);
+        if self.selected_plugin == id {
+            self.select_previous_plugin();
+        }
     }
 
-    pub fn add_plugins(&mut self, plugins: Vec<Box<dyn Plugin>>) -> () {
+    // The ones that fit are added even if some are refused, the first error is returned
+    pub fn add_plugins(&mut self, plugins: Vec<Box<dyn Plugin>>) -> Result<(), String> {
+        let mut result = Ok(());
         for plugin in plugins {
-            self.add_plugin(plugin);
+            if let Err(error) = self.add_plugin(plugin) {
+                result = result.and(Err(error));
+            }
         }
+        result
     }
 
     pub fn clear(&mut self) -> () {
         self.simulation_state.clear();
     }
 
+    // Reactions are kept by name, so they can be added before the particles they mention are loaded
+    pub fn add_reaction(&mut self, reaction: Reaction) {
+        self.simulation_state.add_reaction(reaction);
+    }
+
+    pub fn add_reactions(&mut self, reactions: Vec<Reaction>) {
+        for reaction in reactions {
+            self.add_reaction(reaction);
+        }
+    }
+
+    // Only the reactions added here, the ones plugins declare stay until the plugin is removed
+    pub fn remove_reactions(&mut self, reactant: &str) {
+        self.simulation_state.remove_reactions(reactant);
+    }
+
+    pub fn clear_reactions(&mut self) {
+        self.simulation_state.clear_reactions();
+    }
+
+    pub fn get_reaction_table(&self) -> &ReactionTable {
+        self.simulation_state.get_reaction_table()
+    }
+
     pub fn repaint(&mut self) -> () {
         self.simulation_state.repaint();
     }
@@ -134,18 +277,62 @@ impl Simulation {
         self.simulation_state.get_frame_count()
     }
 
-    pub fn resize(&mut self, size: u32) -> () {
-        self.simulation_state.resize(size);
+    // Saving and restoring this lets you replay a simulation from any frame
+    pub fn get_rng_state(&self) -> u64 {
+        self.simulation_state.get_rng_state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.simulation_state.set_rng_state(state);
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize, anchor: ResizeAnchor) {
+        // An empty world can't be iterated by the order schemes
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.simulation_state.resize(width, height, anchor);
         self.order_scheme = OrderSchemes::new(self.get_width(), self.get_height());
     }
 
+    pub fn save_snapshot(&self, writer: &mut impl std::io::Write) -> Result<(), String> {
+        self.simulation_state.to_snapshot().write_to(writer)
+    }
+
+    // The world is resized to the snapshot size, the caller is in charge of resizing anything that depends on it
+    pub fn load_snapshot(&mut self, reader: &mut impl std::io::Read) -> Result<(), String> {
+        let snapshot = Snapshot::read_from(reader)?;
+        self.simulation_state.restore_snapshot(snapshot);
+        self.order_scheme = OrderSchemes::new(self.get_width(), self.get_height());
+        self.order_scheme.sync_with_frame(self.get_frame_count());
+        Ok(())
+    }
+
+    pub fn save_snapshot_to_file(&self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path)
+            .map_err(|error| format!("Unable to create snapshot {}: {}", path, error))?;
+        let mut writer = std::io::BufWriter::new(file);
+        self.save_snapshot(&mut writer)?;
+        std::io::Write::flush(&mut writer)
+            .map_err(|error| format!("Unable to write snapshot {}: {}", path, error))
+    }
+
+    pub fn load_snapshot_from_file(&mut self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::open(path)
+            .map_err(|error| format!("Unable to open snapshot {}: {}", path, error))?;
+        self.load_snapshot(&mut std::io::BufReader::new(file))
+    }
+
     pub fn set_selected_particle(&mut self, x: usize, y: usize) -> () {
         self.simulation_state
             .set_particle_at_by_id(x, y, self.selected_plugin.into());
+        self.simulation_state.run_hooks(&self.plugin_data.plugins);
     }
 
     pub fn set_particle(&mut self, x: usize, y: usize, particle: Particle) -> () {
         self.simulation_state
             .set_particle_at_by_id(x, y, particle.id);
+        self.simulation_state.run_hooks(&self.plugin_data.plugins);
     }
 }
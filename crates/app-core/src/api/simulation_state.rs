@@ -1,7 +1,9 @@
 use crate::api::*;
 use rustc_hash::FxHashMap;
 
+use std::cell::Cell;
 use std::println;
+use std::sync::Arc;
 use std::vec;
 
 pub struct Vec2i {
@@ -49,43 +51,158 @@ impl Transformation {
     }
 }
 
+// Which part of the world stays in place when it's resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeAnchor {
+    #[default]
+    Center,
+    // Horizontally centered, but the floor stays where it is so particles don't end up floating
+    Bottom,
+    TopLeft,
+}
+
+impl std::str::FromStr for ResizeAnchor {
+    type Err = String;
+
+    fn from_str(anchor: &str) -> Result<Self, Self::Err> {
+        match anchor.trim().to_lowercase().as_str() {
+            "center" => Ok(ResizeAnchor::Center),
+            "bottom" => Ok(ResizeAnchor::Bottom),
+            "topleft" | "top-left" | "top_left" => Ok(ResizeAnchor::TopLeft),
+            _ => Err(format!("Unknown resize anchor: {}", anchor)),
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub(crate) struct WorldView<'a> {
+    particles: &'a [Particle],
+    temperature: &'a [f32],
+    color_buffer: &'a [u8],
+    width: usize,
+    updated: &'a [bool],
+    frame_count: u32,
+    particle_definitions: &'a Arc<Vec<ParticleCommonData>>,
+    particle_name_to_id: &'a Arc<FxHashMap<String, u16>>,
+    activity: &'a ChunkActivity,
+    has_hooks: bool,
+    boundaries: ResolvedBoundaries,
+    velocity: &'a [[f32; 2]],
+    has_motion: bool,
+    motion_active: bool,
+}
+
 pub struct SimulationState {
-    particle_definitions: Vec<ParticleCommonData>,
-    particles: Vec<Vec<Particle>>,
+    // Shared with the chunk workers when updating on several threads, they are rarely modified
+    particle_definitions: Arc<Vec<ParticleCommonData>>,
+    // Row-major, the particle at x, y is at y * width + x
+    particles: Vec<Particle>,
     current_x: usize,
     current_y: usize,
     width: usize,
     height: usize,
-    clock: u8,
+    // Cells whose particle was already updated or written this frame, so it isn't updated (again) until
+    // the next one. It's cleared when the frame starts, so it doesn't matter what happened on earlier frames
+    updated: Vec<bool>,
     color_buffer: Vec<u8>,
-    particle_name_to_id: FxHashMap<String, u8>,
+    particle_name_to_id: Arc<FxHashMap<String, u16>>,
     transformation: Transformation,
     frame_count: u32,
+    // fastrand::Rng is just a u64 but it needs &mut to generate numbers, and plugins ask for
+    // random numbers through &self, so we keep the raw state here and rebuild the generator on each call
+    rng_state: Cell<u64>,
+    // Which chunks have to be updated and which ones are asleep
+    activity: ChunkActivity,
+    // Cells whose color changed since the last time someone asked, so only that part has to be uploaded
+    dirty_rect: Option<DirtyRect>,
+    // Degrees of each cell, laid out like the particles. Heat travels with particles when they move or swap
+    temperature: Vec<f32>,
+    // Per cell buffers the heat diffusion needs, kept so they aren't allocated every frame
+    temperature_scratch: Vec<f32>,
+    conductivity_scratch: Vec<f32>,
+    ambient_exchange_scratch: Vec<f32>,
+    // False once temperatures stop changing, the diffusion is skipped until a temperature is written again
+    thermal_active: bool,
+    // Some particle type conducts or holds heat differently than the default or air, otherwise there's no diffusion
+    has_thermal: bool,
+    // At least one particle type has phase changes, otherwise there's no need to look for them
+    has_phase_changes: bool,
+    // Reactions that don't come from a plugin
+    reaction_table: ReactionTable,
+    // Some particle type has lifecycle hooks, otherwise writes don't look at what they overwrite
+    has_hooks: bool,
+    pending_hooks: Vec<PendingHook>,
+    // The hooks being run, kept so the allocation is reused
+    hook_scratch: Vec<PendingHook>,
+    // What is past each edge of the world, the resolved ones have the wall particles looked up
+    boundaries: Boundaries,
+    resolved_boundaries: ResolvedBoundaries,
+    // Cells per frame of each cell, x to the right and y up like the relative positions. It travels
+    // with the particle, and it's only not 0 for flying particles of types with motion properties
+    velocity: Vec<[f32; 2]>,
+    // Some particle is flying, otherwise the motion step is skipped
+    motion_active: bool,
+    // Some particle type has motion properties, otherwise velocities don't have to follow the particles
+    has_motion: bool,
+    // Cells the motion step already moved this frame
+    motion_scratch: Vec<bool>,
 }
 
 impl SimulationState {
     pub fn new(width: usize, height: usize) -> SimulationState {
+        SimulationState::new_with_seed(width, height, fastrand::u64(..))
+    }
+
+    // Same seed, same scene and same plugins always produce the same world
+    pub fn new_with_seed(width: usize, height: usize, seed: u64) -> SimulationState {
         let color_buffer = vec![0; width * height * 4];
 
         let mut state = SimulationState {
-            particles: vec![vec![Particle::new(); width]; height],
+            particles: vec![Particle::new(); width * height],
             current_x: 0,
             current_y: 0,
             width,
             height,
-            particle_definitions: Vec::new(),
+            particle_definitions: Arc::new(Vec::new()),
             color_buffer,
-            clock: 0,
-            particle_name_to_id: FxHashMap::default(),
+            updated: vec![false; width * height],
+            particle_name_to_id: Arc::new(FxHashMap::default()),
             transformation: Transformation::None,
             frame_count: 0,
+            rng_state: Cell::new(seed),
+            activity: ChunkActivity::new(width, height),
+            dirty_rect: None,
+            temperature: vec![AMBIENT_TEMPERATURE; width * height],
+            temperature_scratch: Vec::new(),
+            conductivity_scratch: Vec::new(),
+            ambient_exchange_scratch: Vec::new(),
+            thermal_active: true,
+            has_phase_changes: false,
+            has_thermal: false,
+            reaction_table: ReactionTable::default(),
+            has_hooks: false,
+            pending_hooks: Vec::new(),
+            hook_scratch: Vec::new(),
+            boundaries: Boundaries::default(),
+            resolved_boundaries: ResolvedBoundaries::default(),
+            velocity: vec![[0.0; 2]; width * height],
+            motion_active: false,
+            has_motion: false,
+            motion_scratch: Vec::new(),
         };
 
-        state.add_or_replace_particle_definition(
+        // The first one, there's always room for it
+        let _ = state.add_or_replace_particle_definition(
             PluginResult {
                 name: String::from("Empty"),
                 color: Color::from_rgba(204, 225, 251, 255),
                 color2: Color::from_rgba(204, 225, 251, 255),
+                properties: Vec::new(),
+                thermal: ThermalProperties::air(),
+                reactions: Vec::new(),
+                hooks: LifecycleHooks::default(),
+                motion: None,
+                blast_resistance: DEFAULT_BLAST_RESISTANCE,
             }
             .into(),
         );
@@ -146,60 +263,175 @@ impl SimulationState {
         &self.transformation
     }
 
-    pub fn id_from_name(&self, name: &str) -> u8 {
+    pub fn id_from_name(&self, name: &str) -> u16 {
         *self
             .particle_name_to_id
             .get(&name.to_lowercase())
             .unwrap_or(&Particle::INVALID.id)
     }
 
-    // Returns Some(id) if particle was updated None if it was added
+    // Returns Some(id) if the particle was updated or took the place of a removed one, None if it was added at the end.
+    // It's refused once every id is taken
     pub(crate) fn add_or_replace_particle_definition(
         &mut self,
         particle_definition: ParticleCommonData,
-    ) -> Option<usize> {
+    ) -> Result<Option<usize>, String> {
         let name = particle_definition.name.to_lowercase();
 
         if self.particle_name_to_id.contains_key(&name) {
             let id = *self.particle_name_to_id.get(&name).unwrap();
-            self.particle_definitions[id as usize] = particle_definition;
-            Some(id as usize)
+
+            // Existing particles keep their property values even if the plugin moved them around
+            let old_names = self.particle_definitions[id as usize]
+                .properties
+                .iter()
+                .map(|property| property.name.as_str())
+                .collect::<Vec<_>>();
+            if let Some(remap) = PropertyRemap::new(&old_names, &particle_definition.properties) {
+                self.particles
+                    .iter_mut()
+                    .filter(|particle| particle.id == id)
+                    .for_each(|particle| remap.apply(particle));
+            }
+
+            Arc::make_mut(&mut self.particle_definitions)[id as usize] = particle_definition;
+            self.resolve_names();
+            Ok(Some(id as usize))
+        } else if let Some(id) = self.free_slot(&name) {
+            Arc::make_mut(&mut self.particle_definitions)[id] = particle_definition;
+            Arc::make_mut(&mut self.particle_name_to_id).insert(name.clone(), id as u16);
+
+            println!("Added or updated particle definition: {}", name);
+            self.resolve_names();
+            Ok(Some(id))
+        } else if self.particle_definitions.len() >= MAX_PARTICLE_TYPES {
+            Err(format!(
+                "Unable to add {}, there can't be more than {} particle types",
+                particle_definition.name, MAX_PARTICLE_TYPES
+            ))
         } else {
-            self.particle_definitions.push(particle_definition);
-            self.particle_name_to_id.insert(
+            Arc::make_mut(&mut self.particle_definitions).push(particle_definition);
+            Arc::make_mut(&mut self.particle_name_to_id).insert(
                 name.to_lowercase().clone(),
-                (self.particle_definitions.len() - 1) as u8,
+                (self.particle_definitions.len() - 1) as u16,
             );
 
             println!("Added or updated particle definition: {}", name);
-            None
+            self.resolve_names();
+            Ok(None)
         }
     }
 
-    pub(crate) fn remove_particle_definition(&mut self, id: u8) -> () {
-        let name = self.particle_definitions[id as usize].name.to_lowercase();
-        self.particle_name_to_id.remove(&name);
-        self.particle_definitions.remove(id as usize);
+    // Slot a removed particle had, only that same particle gets it back. Giving it to another type
+    // would make ids held by plugins or the frontend silently point to something else
+    fn free_slot(&self, name: &str) -> Option<usize> {
+        self.particle_definitions
+            .iter()
+            .position(|definition| definition.removed && definition.name.to_lowercase() == name)
+    }
+
+    // Phase change targets and reactions are stored by name. Ids never change, but the particle a name points to
+    // can be loaded or removed after them, so they're resolved again every time plugins change
+    fn resolve_names(&mut self) {
+        let name_to_id = Arc::clone(&self.particle_name_to_id);
+        let id_from_name = |name: &str| {
+            *name_to_id
+                .get(&name.to_lowercase())
+                .unwrap_or(&Particle::INVALID.id)
+        };
 
-        // Now, values in the map might be invalidated, every value higher than the removed one will be shifted (decreased by 1)
-        self.particle_name_to_id.iter_mut().for_each(|(_, value)| {
-            if *value > id {
-                *value -= 1;
+        // Plugins can declare reactions for other particles, so they are gathered before handing them out
+        let mut reactions = self.reaction_table.clone();
+        self.particle_definitions
+            .iter()
+            .flat_map(|definition| definition.reactions.iter())
+            .for_each(|reaction| reactions.add(reaction.clone()));
+
+        for definition in Arc::make_mut(&mut self.particle_definitions).iter_mut() {
+            // Reactions can still mention a removed particle by name, they just don't apply until it's back
+            if definition.removed {
+                continue;
             }
-        });
+            definition.phase_changes = ResolvedPhaseChanges::resolve(&definition.thermal, id_from_name);
+            definition.resolved_reactions = reactions
+                .get(&definition.name)
+                .iter()
+                .filter_map(|reaction| ResolvedReaction::resolve(reaction, id_from_name))
+                .collect();
+        }
 
-        // We have to update the particle buffer as indices higher than the removed one will be shifted
-        // If the index is the same as the removed one we'll just replace it with an empty particle
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let particle = &mut self.particles[y][x];
-                if particle.id == id as u8 {
-                    *particle = Particle::EMPTY;
-                } else if particle.id > id as u8 {
-                    particle.id -= 1;
-                }
+        self.has_phase_changes = self
+            .particle_definitions
+            .iter()
+            .any(|definition| !definition.phase_changes.is_empty());
+        let air = ThermalProperties::air();
+        self.has_thermal = self
+            .particle_definitions
+            .iter()
+            .any(|definition| definition.thermal != ThermalProperties::default() && definition.thermal != air);
+        self.has_hooks = self
+            .particle_definitions
+            .iter()
+            .any(|definition| definition.hooks.any());
+        self.has_motion = self
+            .particle_definitions
+            .iter()
+            .any(|definition| definition.motion.is_some());
+        self.resolved_boundaries = ResolvedBoundaries::resolve(&self.boundaries, id_from_name);
+        self.thermal_active = true;
+    }
+
+    pub(crate) fn get_boundaries(&self) -> &Boundaries {
+        &self.boundaries
+    }
+
+    pub(crate) fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+        self.resolve_names();
+        self.activity
+            .set_wrapping(self.resolved_boundaries.wraps_x(), self.resolved_boundaries.wraps_y());
+        // Cells next to the edges may see something else now
+        self.activity.wake_all();
+    }
+
+    // The id is left as a tombstone, so every other particle keeps its id and nothing cached by plugins or
+    // anyone else goes stale. Returns false if there's nothing to remove, Empty can't be removed either
+    pub(crate) fn remove_particle_definition(&mut self, id: u16) -> bool {
+        if id == 0 || !self.has_particle_type(id) {
+            return false;
+        }
+
+        let name = self.particle_definitions[id as usize].name.clone();
+        Arc::make_mut(&mut self.particle_name_to_id).remove(&name.to_lowercase());
+        Arc::make_mut(&mut self.particle_definitions)[id as usize] = ParticleCommonData::tombstone(name);
+
+        for particle in self.particles.iter_mut() {
+            if particle.id == id {
+                *particle = Particle::EMPTY;
             }
         }
+
+        self.resolve_names();
+        true
+    }
+
+    pub(crate) fn add_reaction(&mut self, reaction: Reaction) {
+        self.reaction_table.add(reaction);
+        self.resolve_names();
+    }
+
+    pub(crate) fn remove_reactions(&mut self, reactant: &str) {
+        self.reaction_table.remove(reactant);
+        self.resolve_names();
+    }
+
+    pub(crate) fn clear_reactions(&mut self) {
+        self.reaction_table.clear();
+        self.resolve_names();
+    }
+
+    pub(crate) fn get_reaction_table(&self) -> &ReactionTable {
+        &self.reaction_table
     }
 
     pub(crate) fn get_particle_definitions(&self) -> &Vec<ParticleCommonData> {
@@ -227,43 +459,124 @@ impl SimulationState {
     }
 
     pub fn get_current(&self) -> Particle {
-        self.particles[self.current_y][self.current_x]
+        self.particles[self.index(self.current_x, self.current_y)]
     }
 
     pub fn get(&self, x: i32, y: i32) -> Particle {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => self.particles[self.index(local_x, local_y)],
+            Location::Wall(particle) => particle,
+            Location::Void => Particle::EMPTY,
+        }
+    }
 
-        if !self.is_inside_at(local_x, local_y) {
-            return Particle::INVALID;
+    // Cell at x, y relative to the current position, outside the world the boundaries decide
+    #[inline(always)]
+    fn locate(&self, x: i32, y: i32) -> Location {
+        let local_x = self.current_x as i32 + x;
+        let local_y = self.current_y as i32 - y;
+
+        if self.is_inside_at(local_x as usize, local_y as usize) {
+            return Location::Cell(local_x as usize, local_y as usize);
+        }
+
+        self.locate_outside(local_x, local_y)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn locate_outside(&self, x: i32, y: i32) -> Location {
+        self.resolved_boundaries.locate(x, y, self.width, self.height)
+    }
+
+    // Whatever goes into the void is gone, and nothing comes back out to take its place
+    fn fall_into_void(&mut self) -> bool {
+        if self.has_hooks {
+            self.queue_hooks(self.current_x, self.current_y, self.get_current(), Particle::EMPTY, CellChange::Replaced);
+        }
+        self.set_particle_at_unchecked(self.current_x, self.current_y, Particle::EMPTY);
+        if self.has_motion {
+            let index = self.index(self.current_x, self.current_y);
+            self.velocity[index] = [0.0; 2];
         }
+        true
+    }
 
-        self.particles[local_y][local_x]
+    // Ids go up to this, removed particles included
+    pub fn get_particle_count(&self) -> u16 {
+        self.particle_definitions.len() as u16
     }
 
-    pub fn get_particle_count(&self) -> u8 {
-        self.particle_definitions.len() as u8
+    // Whether particles with that id can exist, it might be out of range or belong to a removed plugin
+    pub fn has_particle_type(&self, particle_id: u16) -> bool {
+        self.particle_definitions
+            .get(particle_id as usize)
+            .is_some_and(|definition| !definition.removed)
     }
 
-    pub fn new_particle(&self, particle_id: u8) -> Particle {
-        Particle {
+    pub fn new_particle(&self, particle_id: u16) -> Particle {
+        let mut particle = Particle {
             id: particle_id,
             opacity: 100,
             hue_shift: 0,
             color_fade: self.gen_range(0, 100) as u8,
-            clock: !self.clock,
             extra: 0,
             extra2: 0,
             extra3: 0,
+        };
+
+        for (slot, property) in self.get_property_definitions(particle_id).iter().enumerate() {
+            particle.set_slot(slot, property.default);
         }
+
+        particle
     }
 
-    pub(crate) fn set_particle_at_by_id(&mut self, x: usize, y: usize, particle_id: u8) -> () {
-        if !self.is_inside_at(x, y) {
+    // Properties the particle type declared, empty for unknown ids
+    pub fn get_property_definitions(&self, particle_id: u16) -> &[PropertyDefinition] {
+        self.particle_definitions
+            .get(particle_id as usize)
+            .map_or(&[], |definition| definition.properties.as_slice())
+    }
+
+    // None if the particle type doesn't have that property
+    pub fn get_property(&self, particle: &Particle, name: &str) -> Option<u8> {
+        find_property(self.get_property_definitions(particle.id), name).map(|slot| particle.get_slot(slot))
+    }
+
+    // The value is clamped to the property range. Returns false if the particle type doesn't have
+    // that property. It only changes the given particle, it still has to be set back to the world
+    pub fn set_property(&self, particle: &mut Particle, name: &str, value: i32) -> bool {
+        let properties = self.get_property_definitions(particle.id);
+
+        match find_property(properties, name) {
+            Some(slot) => {
+                particle.set_slot(slot, properties[slot].clamp(value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn set_particle_at_by_id(&mut self, x: usize, y: usize, particle_id: u16) -> () {
+        if !self.is_inside_at(x, y) || !self.has_particle_type(particle_id) {
             return;
         }
 
-        self.set_particle_at_unchecked(x, y, self.new_particle(particle_id));
+        let particle = self.new_particle(particle_id);
+        if self.has_hooks {
+            self.queue_hooks(x, y, self.particles[self.index(x, y)], particle, CellChange::Replaced);
+        }
+        self.set_particle_at_unchecked(x, y, particle);
+
+        // Placed by hand, so it starts with the temperature its type has by default
+        let index = self.index(x, y);
+        let temperature = self.particle_definitions[particle_id as usize].thermal.temperature;
+        if self.temperature[index] != temperature {
+            self.temperature[index] = temperature;
+            self.thermal_active = true;
+        }
+        self.velocity[index] = [0.0; 2];
     }
 
     pub(crate) fn update_particle_data(&mut self, x: usize, y: usize, particle: Particle) {
@@ -271,7 +584,17 @@ impl SimulationState {
             a + (b - a) * t
         }
         
-        self.particles[y][x] = particle;
+        let index = self.index(x, y);
+        self.particles[index] = particle;
+        self.activity.mark_written(x, y);
+        // A particle written over a cell that isn't at the ambient temperature might conduct differently or change phase
+        if self.temperature[index] != AMBIENT_TEMPERATURE {
+            self.thermal_active = true;
+        }
+        match &mut self.dirty_rect {
+            Some(dirty_rect) => dirty_rect.include(x, y),
+            None => self.dirty_rect = Some(DirtyRect::from_point(x, y)),
+        }
 
         // All this hue shifting mangling should be done on the GPU
         // Sadly I can't send an array of floats to the GPU because macroquad
@@ -300,7 +623,7 @@ impl SimulationState {
         let g = lerp(g, g2, particle.color_fade as f32 / 100.0);
         let b = lerp(b, b2, particle.color_fade as f32 / 100.0);
 
-        let start_index = (y * self.width + x) * 4;
+        let start_index = index * 4;
         self.color_buffer[start_index] = (r * 255.0) as u8;
         self.color_buffer[start_index + 1] = (g * 255.0) as u8;
         self.color_buffer[start_index + 2] = (b * 255.0) as u8;
@@ -314,7 +637,8 @@ impl SimulationState {
         particle: Particle,
     ) -> () {
         self.update_particle_data(x, y, particle);
-        self.particles[y][x].clock = !self.clock;
+        let index = self.index(x, y);
+        self.updated[index] = true;
     }
 
     pub(crate) fn set_particle_at_unchecked_relaxed(
@@ -326,8 +650,13 @@ impl SimulationState {
         self.update_particle_data(x, y, particle);
     }
 
-    pub fn get_particles(&self) -> &Vec<Vec<Particle>> {
-        &self.particles
+    pub fn get_particles(&self) -> ParticleView<'_> {
+        ParticleView::new(&self.particles, self.width, self.height)
+    }
+
+    #[inline(always)]
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
     }
 
     // Sets a particle at x, y relative to the current position
@@ -336,109 +665,297 @@ impl SimulationState {
     // But don't use this if you just want to mutate partile state, it doesn't make sense
     // That changing a particle opacity or extra negates the particle update, and it also makes some bugs arise
     pub fn set(&mut self, x: i32, y: i32, particle: Particle) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return true,
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        if self.has_hooks {
+            self.queue_hooks(local_x, local_y, self.particles[self.index(local_x, local_y)], particle, CellChange::Replaced);
         }
-
         self.set_particle_at_unchecked(local_x, local_y, particle);
+        // Whatever was written starts at rest, set_relaxed keeps it as it's meant for changing the same particle
+        if self.has_motion {
+            let index = self.index(local_x, local_y);
+            self.velocity[index] = [0.0; 2];
+        }
         true
     }
 
     pub fn set_relaxed(&mut self, x: i32, y: i32, particle: Particle) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return true,
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        let index = self.index(local_x, local_y);
+        if self.has_hooks {
+            self.queue_hooks(local_x, local_y, self.particles[index], particle, CellChange::Replaced);
+        }
+        // Changing the data of a particle doesn't use up its turn, but a particle of another type showing up
+        // here is a new one, like with set, otherwise a particle copying itself ahead would be updated twice
+        if self.particles[index].id != particle.id {
+            self.updated[index] = true;
         }
-
         self.set_particle_at_unchecked_relaxed(local_x, local_y, particle);
         true
     }
 
+    // True if there is a cell at x, y, edges that wrap around always have one
     pub fn is_inside(&self, x: i32, y: i32) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
-
-        self.is_inside_at(local_x, local_y)
+        matches!(self.locate(x, y), Location::Cell(..))
     }
 
     pub fn move_to(&mut self, x: i32, y: i32) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return self.fall_into_void(),
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        let particle = self.get_current();
+        if self.has_hooks {
+            self.queue_hooks(local_x, local_y, self.particles[self.index(local_x, local_y)], particle, CellChange::MovedOver);
         }
-
-        let particle = self.particles[self.current_y][self.current_x];
         self.set_particle_at_unchecked(local_x, local_y, particle);
         self.set_particle_at_unchecked(self.current_x, self.current_y, Particle::EMPTY);
 
+        self.swap_temperature(local_x, local_y);
+
         self.current_x = local_x;
         self.current_y = local_y;
         true
     }
 
     pub fn move_to_using(&mut self, x: i32, y: i32, particle: Particle) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return self.fall_into_void(),
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        if self.has_hooks {
+            self.queue_hooks(local_x, local_y, self.particles[self.index(local_x, local_y)], particle, CellChange::MovedOver);
         }
-
         self.set_particle_at_unchecked(local_x, local_y, particle);
         self.set_particle_at_unchecked(self.current_x, self.current_y, Particle::EMPTY);
 
+        self.swap_temperature(local_x, local_y);
+
         self.current_x = local_x;
         self.current_y = local_y;
         true
     }
 
     pub fn swap(&mut self, x: i32, y: i32) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return self.fall_into_void(),
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        let swap_particle = self.particles[self.index(local_x, local_y)];
+        let particle = self.get_current();
+        if self.has_hooks {
+            self.queue_hooks(self.current_x, self.current_y, particle, swap_particle, CellChange::Swapped);
+            self.queue_hooks(local_x, local_y, swap_particle, particle, CellChange::Swapped);
         }
-
-        let swap_particle = self.particles[local_y][local_x];
-        let particle = self.particles[self.current_y][self.current_x];
         self.set_particle_at_unchecked(self.current_x, self.current_y, swap_particle);
         self.set_particle_at_unchecked(local_x, local_y, particle);
 
+        self.swap_temperature(local_x, local_y);
+
         self.current_x = local_x;
         self.current_y = local_y;
         true
     }
 
     pub fn swap_using(&mut self, x: i32, y: i32, particle: Particle) -> bool {
-        let local_x = (self.current_x as i32 + x) as usize;
-        let local_y = (self.current_y as i32 - y) as usize;
+        let (local_x, local_y) = match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => (local_x, local_y),
+            Location::Wall(_) => return false,
+            Location::Void => return self.fall_into_void(),
+        };
 
-        if !self.is_inside_at(local_x, local_y) {
-            return false;
+        let swap_particle = self.particles[self.index(local_x, local_y)];
+        if self.has_hooks {
+            let current = self.get_current();
+            self.queue_hooks(self.current_x, self.current_y, current, swap_particle, CellChange::Swapped);
+            self.queue_hooks(local_x, local_y, swap_particle, particle, CellChange::Swapped);
         }
-
-        let swap_particle = self.particles[local_y][local_x];
         self.set_particle_at_unchecked(local_x, local_y, particle);
         self.set_particle_at_unchecked(self.current_x, self.current_y, swap_particle);
 
+        self.swap_temperature(local_x, local_y);
+
         self.current_x = local_x;
         self.current_y = local_y;
         true
     }
 
-    pub fn is_particle_at(&self, x: i32, y: i32, particle_id: u8) -> bool {
+    // The particle takes its heat and its velocity with it, whatever was at the other side gets ours
+    fn swap_temperature(&mut self, x: usize, y: usize) {
+        let current = self.index(self.current_x, self.current_y);
+        let other = self.index(x, y);
+        self.temperature.swap(current, other);
+        if self.has_motion {
+            self.velocity.swap(current, other);
+        }
+    }
+
+    // Temperature at x, y relative to the current position, outside the world it's the ambient temperature
+    pub fn get_temperature(&self, x: i32, y: i32) -> f32 {
+        match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => self.temperature[self.index(local_x, local_y)],
+            Location::Wall(_) | Location::Void => AMBIENT_TEMPERATURE,
+        }
+    }
+
+    pub fn set_temperature(&mut self, x: i32, y: i32, temperature: f32) -> bool {
+        let Location::Cell(local_x, local_y) = self.locate(x, y) else {
+            return false;
+        };
+
+        let index = self.index(local_x, local_y);
+        self.temperature[index] = temperature;
+        self.thermal_active = true;
+
+        // Nothing to repaint, but workers only copy back what is inside the dirty rect
+        match &mut self.dirty_rect {
+            Some(dirty_rect) => dirty_rect.include(local_x, local_y),
+            None => self.dirty_rect = Some(DirtyRect::from_point(local_x, local_y)),
+        }
+        true
+    }
+
+    pub(crate) fn get_temperature_at(&self, x: usize, y: usize) -> Option<f32> {
+        if !self.is_inside_at(x, y) {
+            return None;
+        }
+
+        Some(self.temperature[self.index(x, y)])
+    }
+
+    // Velocity at x, y relative to the current position in cells per frame, y goes up like the positions
+    pub fn get_velocity(&self, x: i32, y: i32) -> [f32; 2] {
+        match self.locate(x, y) {
+            Location::Cell(local_x, local_y) => self.velocity[self.index(local_x, local_y)],
+            Location::Wall(_) | Location::Void => [0.0; 2],
+        }
+    }
+
+    // Only particle types with motion properties can fly, false for anything else
+    pub fn set_velocity(&mut self, x: i32, y: i32, velocity: [f32; 2]) -> bool {
+        let Location::Cell(local_x, local_y) = self.locate(x, y) else {
+            return false;
+        };
+
+        let index = self.index(local_x, local_y);
+        if self.particle_definitions[self.particles[index].id as usize].motion.is_none() {
+            return false;
+        }
+
+        self.velocity[index] = clamp_velocity(velocity);
+        self.motion_active = true;
+
+        // Same as the temperature, workers only copy back what is inside the dirty rect
+        match &mut self.dirty_rect {
+            Some(dirty_rect) => dirty_rect.include(local_x, local_y),
+            None => self.dirty_rect = Some(DirtyRect::from_point(local_x, local_y)),
+        }
+        true
+    }
+
+    pub fn add_impulse(&mut self, x: i32, y: i32, impulse: [f32; 2]) -> bool {
+        let [velocity_x, velocity_y] = self.get_velocity(x, y);
+        self.set_velocity(x, y, [velocity_x + impulse[0], velocity_y + impulse[1]])
+    }
+
+    // Blast centered at x, y relative to the current position, returns how many cells it destroyed.
+    // Like the area queries, chunk workers only see CHUNK_HALO cells around their chunk
+    pub fn explode(&mut self, x: i32, y: i32, explosion: Explosion) -> i32 {
+        let radius = explosion.radius.max(0);
+        let mut cells = Vec::new();
+        for offset_y in -radius..=radius {
+            for offset_x in -radius..=radius {
+                if offset_x * offset_x + offset_y * offset_y <= radius * radius {
+                    cells.push([offset_x, offset_y]);
+                }
+            }
+        }
+        // Outer cells first, so the inner ones have room to be pushed into
+        cells.sort_by_key(|[offset_x, offset_y]| -(offset_x * offset_x + offset_y * offset_y));
+
+        let mut destroyed = 0;
+        for [offset_x, offset_y] in cells {
+            let (cell_x, cell_y) = (x + offset_x, y + offset_y);
+            if !self.is_inside(cell_x, cell_y) || self.get_type(cell_x, cell_y) == Particle::EMPTY.id {
+                continue;
+            }
+            let particle = self.get(cell_x, cell_y);
+
+            let distance = ((offset_x * offset_x + offset_y * offset_y) as f32).sqrt();
+            let power = explosion.power_at(distance);
+            // The center has nowhere to be pushed to, it goes up
+            let outwards = match distance > 0.0 {
+                true => [offset_x as f32 / distance, offset_y as f32 / distance],
+                false => [0.0, 1.0],
+            };
+
+            let resistance = self.particle_definitions[particle.id as usize].blast_resistance;
+            if power > resistance {
+                let spawn = explosion
+                    .spawn
+                    .filter(|&spawn| self.has_particle_type(spawn))
+                    .filter(|_| self.with_rng(|rng| rng.f32()) < explosion.spawn_chance);
+                let remains = spawn.map_or(Particle::EMPTY, |spawn| self.new_particle(spawn));
+                self.set(cell_x, cell_y, remains);
+                destroyed += 1;
+
+                // Whatever spawned is thrown too if it can fly, like the smoke of the blast
+                self.add_impulse(cell_x, cell_y, Explosion::throw(power, outwards));
+            } else if power > resistance / 2.0 && !self.add_impulse(cell_x, cell_y, Explosion::throw(power, outwards)) {
+                let push = outwards.map(|direction| direction.round() as i32);
+                self.displace([cell_x, cell_y], [cell_x + push[0], cell_y + push[1]]);
+            }
+        }
+
+        destroyed
+    }
+
+    // Moves the particle at from into the empty cell at to, both relative to the current position
+    fn displace(&mut self, from: [i32; 2], to: [i32; 2]) -> bool {
+        let Location::Cell(from_x, from_y) = self.locate(from[0], from[1]) else {
+            return false;
+        };
+
+        let (to_x, to_y) = match self.locate(to[0], to[1]) {
+            Location::Cell(to_x, to_y) if self.particles[self.index(to_x, to_y)].id == Particle::EMPTY.id => (to_x, to_y),
+            Location::Void => return self.set(from[0], from[1], Particle::EMPTY),
+            _ => return false,
+        };
+
+        let (from_index, to_index) = (self.index(from_x, from_y), self.index(to_x, to_y));
+        let particle = self.particles[from_index];
+        if self.has_hooks {
+            self.queue_hooks(to_x, to_y, self.particles[to_index], particle, CellChange::MovedOver);
+        }
+        self.set_particle_at_unchecked(to_x, to_y, particle);
+        self.set_particle_at_unchecked(from_x, from_y, Particle::EMPTY);
+        self.temperature.swap(from_index, to_index);
+        if self.has_motion {
+            self.velocity.swap(from_index, to_index);
+        }
+        true
+    }
+
+    pub fn is_particle_at(&self, x: i32, y: i32, particle_id: u16) -> bool {
         self.get(x, y) == particle_id
     }
 
-    pub fn is_any_particle_at(&self, x: i32, y: i32, particles: &[u8]) -> bool {
+    pub fn is_any_particle_at(&self, x: i32, y: i32, particles: &[u16]) -> bool {
         let particle = self.get(x, y);
         particles.contains(&particle.id)
     }
@@ -449,48 +966,419 @@ impl SimulationState {
 
     pub(super) fn update(
         &mut self,
-        plugins: &mut Vec<Box<dyn Plugin>>,
+        plugins: &[Box<dyn Plugin>],
         order_scheme: &OrderScheme,
-    ) -> () {
-        self.clock = !self.clock;
+    ) {
+        self.begin_frame();
 
         for y in order_scheme.order_y {
+            if !self.activity.is_row_awake(y as usize) {
+                continue;
+            }
+
             for x in order_scheme.order_x {
-                let x = x as usize;
-                let y = y as usize;
+                self.update_cell(x as usize, y as usize, plugins);
+            }
+        }
+
+        self.end_frame(plugins);
+    }
+
+    pub(crate) fn begin_frame(&mut self) {
+        self.updated.fill(false);
+        self.activity.begin_frame();
+    }
+
+    pub(crate) fn end_frame(&mut self, plugins: &[Box<dyn Plugin>]) {
+        self.update_motion();
+        self.update_temperature();
+        self.run_hooks(plugins);
+
+        self.current_x = 0;
+        self.current_y = 0;
+        self.frame_count += 1;
+    }
+
+    #[inline(always)]
+    fn update_cell(&mut self, x: usize, y: usize, plugins: &[Box<dyn Plugin>]) {
+        // Nothing around here changed lately, so the plugins would most likely do nothing
+        if !self.activity.is_awake(x, y) {
+            return;
+        }
+
+        // Something moved or placed here this frame, it already had its turn
+        let index = self.index(x, y);
+        if self.updated[index] {
+            return;
+        }
+
+        self.current_x = x;
+        self.current_y = y;
+        let current_particle = self.particles[index];
+
+        // Reactions go first, if the particle turned into something else there's nothing left to update
+        if !self.particle_definitions[current_particle.id as usize].resolved_reactions.is_empty() {
+            let definitions = Arc::clone(&self.particle_definitions);
+            if self.react(&definitions[current_particle.id as usize].resolved_reactions) {
+                self.run_hooks(plugins);
+                return;
+            }
+        }
+
+        // Flying particles are moved by the motion step, their plugin takes over again once they land
+        if self.motion_active && self.velocity[index] != [0.0; 2] {
+            return;
+        }
+
+        let plugin = &plugins[current_particle.id as usize];
+        plugin.update(self);
+
+        // Wherever the particle ended up, it's done for this frame even if the plugin didn't write anything
+        let index = self.index(self.current_x, self.current_y);
+        self.updated[index] = true;
+        self.run_hooks(plugins);
+    }
+
+    pub fn get_frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    // Keeps the chunk of the current cell awake without writing anything, for particles that are waiting
+    // for something that isn't a change around them, like a random chance or a number of frames
+    pub fn keep_awake(&mut self) {
+        self.activity.mark_written(self.current_x, self.current_y);
+    }
+
+    // First reaction that happens wins, returns true if the current particle changed into something else
+    fn react(&mut self, reactions: &[ResolvedReaction]) -> bool {
+        let mut waiting = false;
+
+        for reaction in reactions {
+            for (neighbor, probability) in ParticleApi::NEIGHBORS.iter().zip(reaction.probabilities) {
+                if probability <= 0.0 || self.get_type(neighbor.x, neighbor.y) != reaction.neighbor {
+                    continue;
+                }
 
-                self.current_x = x;
-                self.current_y = y;
-                let current_particle = self.particles[y][x];
-                if current_particle.clock != self.clock {
+                if probability < 1.0 && self.with_rng(|rng| rng.f32()) >= probability {
+                    waiting = true;
                     continue;
                 }
 
-                let plugin = &mut plugins[current_particle.id as usize];
-                plugin.update(self);
+                if let Some(product) = reaction.neighbor_product {
+                    self.set(neighbor.x, neighbor.y, self.new_particle(product));
+                }
+
+                if let Some(product) = reaction.reactant_product {
+                    self.set(0, 0, self.new_particle(product));
+                    return true;
+                }
+
+                return false;
+            }
+        }
+
+        // It may still react later, nothing has to move for that so the chunk must not fall asleep
+        if waiting {
+            self.keep_awake();
+        }
+
+        false
+    }
 
-                // Make sure the particle is updated next frame and also that is updated next frame
-                // If the user makes some operation that doesn't change the particle, its clock won't change so to avoid that we do this patch
+    // Moves every flying particle along its velocity, it runs once per frame after all particles updated
+    // and before the heat, so whatever lands this frame is heated where it landed
+    fn update_motion(&mut self) {
+        if !self.motion_active {
+            return;
+        }
+
+        self.motion_scratch.clear();
+        self.motion_scratch.resize(self.width * self.height, false);
+        self.motion_active = false;
 
-                // But for some reason calling this single line here makes the simulation twice slower
-                // Not havint his only affects one kind of block and it's still easily solvable modifying that block
-                // so even if it's not formally correct, I'll leave it like this, but I'll keep this comment as a reminder
-                // self.particles[y][x].clock = !self.clock;
+        // Bottom up, so falling particles get out of the way of the ones falling on top of them
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let index = self.index(x, y);
+                if !self.motion_scratch[index] && self.velocity[index] != [0.0; 2] {
+                    self.move_ballistic(x, y);
+                }
             }
         }
 
         self.current_x = 0;
         self.current_y = 0;
-        self.frame_count += 1;
     }
 
-    pub fn get_frame_count(&self) -> u32 {
-        self.frame_count
+    fn move_ballistic(&mut self, x: usize, y: usize) {
+        self.current_x = x;
+        self.current_y = y;
+
+        let index = self.index(x, y);
+        // It changed into something that can't fly since it got the velocity
+        let Some(motion) = self.particle_definitions[self.particles[index].id as usize].motion else {
+            self.velocity[index] = [0.0; 2];
+            return;
+        };
+
+        let [velocity_x, velocity_y] = self.velocity[index];
+        let [mut velocity_x, mut velocity_y] = clamp_velocity([
+            velocity_x * (1.0 - motion.drag),
+            (velocity_y - motion.gravity) * (1.0 - motion.drag),
+        ]);
+
+        // Whole cells only, fractions move a cell now and then so slow particles still get somewhere
+        let distance_x = self.round_randomly(velocity_x);
+        let distance_y = self.round_randomly(velocity_y);
+        let steps = distance_x.abs().max(distance_y.abs());
+
+        let mut moved = [0, 0];
+        for step in 1..=steps {
+            let [step_x, step_y] = line_step(distance_x, distance_y, step, steps);
+            let (offset_x, offset_y) = (step_x - moved[0], step_y - moved[1]);
+
+            let hit = match self.locate(offset_x, offset_y) {
+                Location::Void => {
+                    self.fall_into_void();
+                    return;
+                }
+                Location::Cell(hit_x, hit_y) => self.particles[self.index(hit_x, hit_y)],
+                Location::Wall(particle) => particle,
+            };
+
+            if hit.id == Particle::EMPTY.id {
+                self.move_to(offset_x, offset_y);
+                moved = [step_x, step_y];
+                continue;
+            }
+
+            // Whatever it ran into gets part of the momentum if it can fly too, walls are never a cell
+            let can_fly = |id: u16| self.particle_definitions.get(id as usize).is_some_and(|definition| definition.motion.is_some());
+            if can_fly(hit.id) {
+                let impulse = [velocity_x * MOMENTUM_TRANSFER, velocity_y * MOMENTUM_TRANSFER];
+                self.add_impulse(offset_x, offset_y, impulse);
+            }
+
+            // Bounce off the axis that is blocked, both of them when it only hit a corner
+            let blocked_x = offset_x != 0 && self.get(offset_x, 0).id != Particle::EMPTY.id;
+            let blocked_y = offset_y != 0 && self.get(0, offset_y).id != Particle::EMPTY.id;
+            if blocked_x || !blocked_y {
+                velocity_x = -velocity_x * motion.bounce;
+            }
+            if blocked_y || !blocked_x {
+                velocity_y = -velocity_y * motion.bounce;
+            }
+            break;
+        }
+
+        let speed = (velocity_x * velocity_x + velocity_y * velocity_y).sqrt();
+        let velocity = match speed < REST_SPEED && self.get(0, -1).id != Particle::EMPTY.id {
+            true => [0.0; 2],
+            false => [velocity_x, velocity_y],
+        };
+
+        let index = self.index(self.current_x, self.current_y);
+        self.velocity[index] = velocity;
+        self.motion_scratch[index] = true;
+        self.motion_active |= velocity != [0.0; 2];
+    }
+
+    fn round_randomly(&self, value: f32) -> i32 {
+        let whole = value.floor();
+        whole as i32 + (self.with_rng(|rng| rng.f32()) < value - whole) as i32
+    }
+
+    // Heat diffusion plus the phase changes it causes, it runs once per frame after all particles updated
+    fn update_temperature(&mut self) {
+        if !self.thermal_active || !self.has_thermal {
+            return;
+        }
+
+        let cells = self.width * self.height;
+        self.temperature_scratch.resize(cells, AMBIENT_TEMPERATURE);
+        self.conductivity_scratch.clear();
+        self.ambient_exchange_scratch.clear();
+
+        let definitions = &self.particle_definitions;
+        self.conductivity_scratch.extend(
+            self.particles
+                .iter()
+                .map(|particle| definitions[particle.id as usize].thermal.conductivity),
+        );
+        self.ambient_exchange_scratch.extend(
+            self.particles
+                .iter()
+                .map(|particle| definitions[particle.id as usize].thermal.ambient_exchange),
+        );
+
+        let activity = &mut self.activity;
+        let max_change = diffuse_heat(
+            &self.temperature,
+            &mut self.temperature_scratch,
+            &self.conductivity_scratch,
+            &self.ambient_exchange_scratch,
+            self.width,
+            self.height,
+            |x, y| activity.mark_written(x, y),
+        );
+        std::mem::swap(&mut self.temperature, &mut self.temperature_scratch);
+        self.thermal_active = max_change >= SETTLED_TEMPERATURE_CHANGE;
+
+        if !self.has_phase_changes {
+            return;
+        }
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = self.index(x, y);
+                let phase_changes = &self.particle_definitions[self.particles[index].id as usize].phase_changes;
+
+                // The new particle keeps the temperature, if it's still past its own thresholds it changes again next frame
+                if let Some(target) = phase_changes.target(self.temperature[index]) {
+                    let particle = self.new_particle(target);
+                    if self.has_hooks {
+                        self.queue_hooks(x, y, self.particles[index], particle, CellChange::Replaced);
+                    }
+                    self.set_particle_at_unchecked(x, y, particle);
+                }
+            }
+        }
+    }
+
+    // Only called when some type has hooks, a particle landing on one of its own type doesn't count.
+    // Kept out of line so moving particles around stays as cheap as before when nobody uses them
+    #[inline(never)]
+    fn queue_hooks(&mut self, x: usize, y: usize, old: Particle, new: Particle, change: CellChange) {
+        if old.id == new.id {
+            return;
+        }
+
+        let definitions = &self.particle_definitions;
+        if change != CellChange::Swapped && definitions[old.id as usize].hooks.on_destroy {
+            let event = LifecycleEvent::Destroyed(old);
+            self.pending_hooks.push(PendingHook { x, y, event });
+        }
+        if change == CellChange::Replaced && definitions[new.id as usize].hooks.on_create {
+            let event = LifecycleEvent::Created(new.id);
+            self.pending_hooks.push(PendingHook { x, y, event });
+        }
+
+        // Something leaving isn't touching anything
+        if new.id == Particle::EMPTY.id {
+            return;
+        }
+
+        for neighbor in ParticleApi::NEIGHBORS.iter() {
+            // Same lookup as get, so across a wrapping edge the cell on the other side is a neighbour too
+            let (neighbor_x, neighbor_y) = (x as i32 + neighbor.x, y as i32 + neighbor.y);
+            let (neighbor_x, neighbor_y) = if self.is_inside_at(neighbor_x as usize, neighbor_y as usize) {
+                (neighbor_x as usize, neighbor_y as usize)
+            } else {
+                match self.locate_outside(neighbor_x, neighbor_y) {
+                    Location::Cell(neighbor_x, neighbor_y) => (neighbor_x, neighbor_y),
+                    Location::Wall(_) | Location::Void => continue,
+                }
+            };
+            let id = self.particles[self.index(neighbor_x, neighbor_y)].id;
+            if id != new.id && self.particle_definitions[id as usize].hooks.on_neighbor_changed {
+                // Seen from the neighbour, in the same coordinates api.get takes
+                let event = LifecycleEvent::NeighborChanged(-neighbor.x, neighbor.y);
+                self.pending_hooks.push(PendingHook { x: neighbor_x, y: neighbor_y, event });
+            }
+        }
+    }
+
+    // Runs the hooks queued so far, and the ones those queue, as if each particle was being updated
+    pub(crate) fn run_hooks(&mut self, plugins: &[Box<dyn Plugin>]) {
+        if self.pending_hooks.is_empty() {
+            return;
+        }
+
+        let (current_x, current_y, transformation) = (self.current_x, self.current_y, self.transformation);
+        self.transformation = Transformation::None;
+
+        let mut hooks = std::mem::take(&mut self.hook_scratch);
+        let mut calls = 0;
+        while !self.pending_hooks.is_empty() && calls < MAX_HOOK_CALLS {
+            std::mem::swap(&mut hooks, &mut self.pending_hooks);
+
+            for hook in hooks.drain(..) {
+                if calls == MAX_HOOK_CALLS {
+                    break;
+                }
+                calls += 1;
+
+                self.current_x = hook.x;
+                self.current_y = hook.y;
+                let id = self.particles[self.index(hook.x, hook.y)].id;
+
+                match hook.event {
+                    LifecycleEvent::Created(created) if created == id => plugins[id as usize].on_create(self),
+                    LifecycleEvent::Created(_) => (),
+                    LifecycleEvent::Destroyed(particle) => plugins[particle.id as usize].on_destroy(self, particle),
+                    LifecycleEvent::NeighborChanged(x, y) => {
+                        if self.particle_definitions[id as usize].hooks.on_neighbor_changed {
+                            plugins[id as usize].on_neighbor_changed(self, x, y);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending_hooks.clear();
+        self.hook_scratch = hooks;
+        self.current_x = current_x;
+        self.current_y = current_y;
+        self.transformation = transformation;
+    }
+
+    // Returns the area that changed since the last call and starts tracking again
+    pub fn take_dirty_rect(&mut self) -> Option<DirtyRect> {
+        self.dirty_rect.take()
+    }
+
+    pub fn is_sleeping_enabled(&self) -> bool {
+        self.activity.is_enabled()
+    }
+
+    // When disabled every cell is updated every frame, for plugins that do things without writing anything
+    pub fn set_sleeping_enabled(&mut self, enabled: bool) {
+        self.activity.set_enabled(enabled);
+    }
+
+    // (awake, total) chunks, for debugging
+    pub fn get_awake_chunks(&self) -> (usize, usize) {
+        (self.activity.awake_chunk_count(), self.activity.chunk_count())
+    }
+
+    // Sizes changed, so all the tracking starts from scratch with everything awake
+    fn reset_activity(&mut self) {
+        let enabled = self.activity.is_enabled();
+        self.activity = ChunkActivity::new(self.width, self.height);
+        self.activity.set_enabled(enabled);
+        self.activity
+            .set_wrapping(self.resolved_boundaries.wraps_x(), self.resolved_boundaries.wraps_y());
+        self.dirty_rect = None;
+    }
+
+    fn with_rng<T>(&self, func: impl FnOnce(&mut fastrand::Rng) -> T) -> T {
+        let mut rng = fastrand::Rng::with_seed(self.rng_state.get());
+        let result = func(&mut rng);
+        self.rng_state.set(rng.get_seed());
+        result
+    }
+
+    pub fn get_rng_state(&self) -> u64 {
+        self.rng_state.get()
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng_state.set(state);
     }
 
     /// Range, min and max are inclusive
     pub fn gen_range(&self, min_inclusive: i32, max_inclusive: i32) -> i32 {
-        fastrand::i32(min_inclusive..=max_inclusive)
+        self.with_rng(|rng| rng.i32(min_inclusive..=max_inclusive))
     }
 
     pub fn is_empty(&self, x: i32, y: i32) -> bool {
@@ -498,53 +1386,171 @@ impl SimulationState {
     }
 
     pub fn random_sign(&self) -> i32 {
-        fastrand::i32(0..2) * 2 - 1
+        self.with_rng(|rng| rng.i32(0..2)) * 2 - 1
     }
 
     pub fn random_bool(&self) -> bool {
-        fastrand::bool()
+        self.with_rng(|rng| rng.bool())
     }
 
-    pub fn get_type(&self, x: i32, y: i32) -> u8 {
+    pub fn get_type(&self, x: i32, y: i32) -> u16 {
         self.get(x, y).id
     }
 
+    // Area queries are relative to the current cell like get, cells outside the world are skipped.
+    // Chunk workers only see their chunk plus the halo, keep the reach under CHUNK_HALO if that matters
+
+    /// Steps of (x, y) until the first cell that isn't empty, None if there is nothing within max_distance steps.
+    /// Past the world edge the boundary decides: a wall is hit on the step that reaches it, whatever its particle,
+    /// wrap keeps going on the other side and void ends the ray with None as nothing comes back out of it.
+    pub fn ray_cast(&self, x: i32, y: i32, max_distance: i32) -> Option<i32> {
+        if x == 0 && y == 0 {
+            return None;
+        }
+
+        for distance in 1..=max_distance {
+            match self.locate(x * distance, y * distance) {
+                Location::Cell(local_x, local_y) => {
+                    if self.particles[self.index(local_x, local_y)] != Particle::EMPTY {
+                        return Some(distance);
+                    }
+                }
+                Location::Wall(_) => return Some(distance),
+                Location::Void => return None,
+            }
+        }
+        None
+    }
+
+    /// Matching cells in a circle of the radius, without the current one
+    pub fn count_in_radius(&self, radius: i32, matches: impl Fn(Particle) -> bool) -> i32 {
+        let mut count = 0;
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                if (x != 0 || y != 0)
+                    && x * x + y * y <= radius * radius
+                    && self.is_inside(x, y)
+                    && matches(self.get(x, y))
+                {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Matching cells in the rectangle between two corners, both included in any order.
+    /// The current cell counts if it's inside.
+    pub fn count_in_area(
+        &self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        matches: impl Fn(Particle) -> bool,
+    ) -> i32 {
+        let mut count = 0;
+        for y in y1.min(y2)..=y1.max(y2) {
+            for x in x1.min(x2)..=x1.max(x2) {
+                if self.is_inside(x, y) && matches(self.get(x, y)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Closest matching cell in a circle of the radius, without the current one. Ties go to
+    /// the first one scanning rows from the top
+    pub fn find_nearest(&self, radius: i32, matches: impl Fn(Particle) -> bool) -> Option<[i32; 2]> {
+        let mut nearest = None;
+        let mut nearest_distance = radius * radius + 1;
+        for y in (-radius..=radius).rev() {
+            for x in -radius..=radius {
+                let distance = x * x + y * y;
+                if (x != 0 || y != 0)
+                    && distance < nearest_distance
+                    && self.is_inside(x, y)
+                    && matches(self.get(x, y))
+                {
+                    nearest = Some([x, y]);
+                    nearest_distance = distance;
+                }
+            }
+        }
+        nearest
+    }
+
     pub fn clear(&mut self) -> () {
         for y in 0..self.height {
             for x in 0..self.width {
                 self.set_particle_at_unchecked(x, y, Particle::EMPTY);
             }
         }
-    }
-
-    pub fn resize(&mut self, size: u32) {
-        let current_size = self.width as u32;
 
-        self.width = size as usize;
-        self.height = size as usize;
+        self.temperature.fill(AMBIENT_TEMPERATURE);
+        self.velocity.fill([0.0; 2]);
+        self.motion_active = false;
+    }
 
-        let mut new_particles: Vec<Vec<Particle>> =
-            vec![vec![Particle::EMPTY; size as usize]; size as usize];
+    pub fn resize(&mut self, width: usize, height: usize, anchor: ResizeAnchor) {
+        // Not only reached from Simulation::resize, and an empty world can't be iterated
+        if width == 0 || height == 0 {
+            return;
+        }
 
-        let offset = ((size as i32) - (current_size as i32)) / 2;
+        let (current_width, current_height) = (self.width as i32, self.height as i32);
+
+        self.width = width;
+        self.height = height;
+
+        let mut new_particles = vec![Particle::EMPTY; width * height];
+        let mut new_temperature = vec![AMBIENT_TEMPERATURE; width * height];
+        let mut new_velocity = vec![[0.0; 2]; width * height];
+
+        let (offset_x, offset_y) = match anchor {
+            ResizeAnchor::Center => (
+                (width as i32 - current_width) / 2,
+                (height as i32 - current_height) / 2,
+            ),
+            ResizeAnchor::Bottom => (
+                (width as i32 - current_width) / 2,
+                height as i32 - current_height,
+            ),
+            ResizeAnchor::TopLeft => (0, 0),
+        };
 
         // I want to make this feel like a "crop", so if a Particle is in the midddle, it will stay in the midle when you resize
         // Particle position is their position in the array, but now the array changed so we have to adapt it
         // Sadly this is not an in-place solution like using vector::resize, but this function is called very sparingly
         // so it's fine to keep it like this.
-        for (y, row) in self.particles.iter().enumerate() {
+        // Nothing to keep from an empty world, and rows of it can't be taken
+        let rows = if current_width > 0 && current_height > 0 {
+            self.particles.chunks_exact(current_width as usize)
+        } else {
+            [].chunks_exact(1)
+        };
+        for (y, row) in rows.enumerate() {
             for (x, particle) in row.iter().enumerate() {
-                let new_x = x as i32 + offset;
-                let new_y = y as i32 + offset;
-                if new_x >= 0 && new_x < size as i32 && new_y >= 0 && new_y < size as i32 {
-                    new_particles[new_y as usize][new_x as usize] = *particle;
+                let new_x = x as i32 + offset_x;
+                let new_y = y as i32 + offset_y;
+                if new_x >= 0 && new_x < width as i32 && new_y >= 0 && new_y < height as i32 {
+                    let new_index = new_y as usize * width + new_x as usize;
+                    new_particles[new_index] = *particle;
+                    new_temperature[new_index] = self.temperature[y * current_width as usize + x];
+                    new_velocity[new_index] = self.velocity[y * current_width as usize + x];
                 }
             }
         }
 
         self.particles = new_particles;
+        self.updated = vec![false; width * height];
+        self.temperature = new_temperature;
+        self.velocity = new_velocity;
+        self.motion_active = true;
+        self.reset_activity();
 
-        let color_buffer_size = (size * size * 4) as usize;
+        let color_buffer_size = width * height * 4;
         self.color_buffer
             .resize(color_buffer_size, Default::default());
 
@@ -553,12 +1559,283 @@ impl SimulationState {
         self.repaint();
     }
 
+    pub(crate) fn to_snapshot(&self) -> Snapshot {
+        Snapshot {
+            width: self.width,
+            height: self.height,
+            frame_count: self.frame_count,
+            rng_state: self.get_rng_state(),
+            // Removed particles are written without a name so nothing maps to them when it's loaded
+            particle_names: self
+                .particle_definitions
+                .iter()
+                .map(|definition| if definition.removed { String::new() } else { definition.name.clone() })
+                .collect(),
+            particle_properties: self
+                .particle_definitions
+                .iter()
+                .map(|definition| {
+                    definition
+                        .properties
+                        .iter()
+                        .map(|property| property.name.clone())
+                        .collect()
+                })
+                .collect(),
+            particles: self.particles.clone(),
+            temperature: self.temperature.clone(),
+            velocity: self.velocity.clone(),
+        }
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        // Ids in the snapshot belong to the plugin set it was taken with, so we map them by name
+        // to the current ones. Particles whose plugin isn't loaded anymore just become empty
+        let id_remap = snapshot
+            .particle_names
+            .iter()
+            .map(|name| self.id_from_name(name))
+            .collect::<Vec<_>>();
+
+        // Same for properties, the plugin might store them in other slots now
+        let property_remap = id_remap
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| {
+                let names = snapshot.particle_properties.get(index)?;
+                PropertyRemap::new(names, self.get_property_definitions(id))
+            })
+            .collect::<Vec<_>>();
+
+        self.width = snapshot.width;
+        self.height = snapshot.height;
+        self.frame_count = snapshot.frame_count;
+
+        self.set_rng_state(snapshot.rng_state);
+
+        self.particles = snapshot
+            .particles
+            .iter()
+            .map(|particle| match id_remap.get(particle.id as usize) {
+                Some(&id) if id != Particle::INVALID.id => {
+                    let mut remapped = Particle { id, ..*particle };
+                    if let Some(remap) = &property_remap[particle.id as usize] {
+                        remap.apply(&mut remapped);
+                    }
+                    remapped
+                }
+                _ => Particle::EMPTY,
+            })
+            .collect();
+
+        self.color_buffer
+            .resize(self.width * self.height * 4, Default::default());
+        self.reset_activity();
+
+        self.temperature = snapshot.temperature;
+        self.thermal_active = true;
+
+        self.velocity = snapshot
+            .velocity
+            .into_iter()
+            .map(clamp_velocity)
+            .collect();
+        self.motion_active = true;
+
+        // Nothing is carried over between frames, so the snapshot continues exactly where it was left
+        self.updated = vec![false; self.width * self.height];
+        self.repaint();
+    }
+
     pub fn repaint(&mut self) {
         for y in 0..self.height {
             for x in 0..self.width {
-                let particle = &self.particles[y][x];
-                self.set_particle_at_unchecked(x, y, *particle);
+                let particle = self.particles[self.index(x, y)];
+                self.set_particle_at_unchecked(x, y, particle);
             }
         }
     }
 }
+
+// Used by the chunk scheduler to update the world on several threads
+#[cfg(not(target_family = "wasm"))]
+impl SimulationState {
+    // Read only view of the world for the chunk workers, unlike the state itself it can be shared between threads
+    pub(crate) fn world_view(&self) -> WorldView<'_> {
+        WorldView {
+            particles: &self.particles,
+            temperature: &self.temperature,
+            color_buffer: &self.color_buffer,
+            width: self.width,
+            updated: &self.updated,
+            frame_count: self.frame_count,
+            particle_definitions: &self.particle_definitions,
+            particle_name_to_id: &self.particle_name_to_id,
+            activity: &self.activity,
+            has_hooks: self.has_hooks,
+            boundaries: self.resolved_boundaries,
+            velocity: &self.velocity,
+            has_motion: self.has_motion,
+            motion_active: self.motion_active,
+        }
+    }
+
+    // Workers are regular states holding a copy of a region of the world, so plugins can't tell the difference
+    pub(crate) fn new_worker(&self) -> SimulationState {
+        SimulationState {
+            particle_definitions: Arc::clone(&self.particle_definitions),
+            particles: Vec::new(),
+            current_x: 0,
+            current_y: 0,
+            width: 0,
+            height: 0,
+            updated: Vec::new(),
+            color_buffer: Vec::new(),
+            particle_name_to_id: Arc::clone(&self.particle_name_to_id),
+            transformation: Transformation::None,
+            frame_count: self.frame_count,
+            rng_state: Cell::new(0),
+            activity: ChunkActivity::empty(),
+            dirty_rect: None,
+            temperature: Vec::new(),
+            temperature_scratch: Vec::new(),
+            conductivity_scratch: Vec::new(),
+            ambient_exchange_scratch: Vec::new(),
+            thermal_active: false,
+            has_phase_changes: false,
+            has_thermal: false,
+            reaction_table: ReactionTable::default(),
+            has_hooks: false,
+            pending_hooks: Vec::new(),
+            hook_scratch: Vec::new(),
+            boundaries: Boundaries::default(),
+            resolved_boundaries: ResolvedBoundaries::default(),
+            velocity: Vec::new(),
+            motion_active: false,
+            has_motion: false,
+            motion_scratch: Vec::new(),
+        }
+    }
+
+    // Copies the region into the worker, allocations are reused between frames
+    pub(crate) fn load_region(&mut self, world: &WorldView, region: &ChunkRect, seed: u64) {
+        self.width = region.width;
+        self.height = region.height;
+        self.frame_count = world.frame_count;
+        self.transformation = Transformation::None;
+        self.rng_state.set(seed);
+        self.activity.load_region(world.activity, region);
+        self.dirty_rect = None;
+        self.has_hooks = world.has_hooks;
+        self.pending_hooks.clear();
+        self.has_motion = world.has_motion;
+        self.motion_active = world.motion_active;
+        self.thermal_active = false;
+
+        // Only the world edges have boundaries, past the halo plugins can't reach anyway.
+        // Worlds that wrap around aren't split in chunks, the other side wouldn't be there
+        let inner = ResolvedBoundary::Wall(Particle::INVALID);
+        let world_height = world.particles.len() / world.width;
+        self.resolved_boundaries = ResolvedBoundaries {
+            left: if region.x == 0 { world.boundaries.left } else { inner },
+            right: if region.x + region.width == world.width { world.boundaries.right } else { inner },
+            top: if region.y == 0 { world.boundaries.top } else { inner },
+            bottom: if region.y + region.height == world_height { world.boundaries.bottom } else { inner },
+        };
+
+        // Cheap, it's just a reference count, but this way workers see plugins added since last frame
+        if !Arc::ptr_eq(&self.particle_definitions, world.particle_definitions) {
+            self.particle_definitions = Arc::clone(world.particle_definitions);
+            self.particle_name_to_id = Arc::clone(world.particle_name_to_id);
+        }
+
+        self.particles.clear();
+        self.temperature.clear();
+        self.velocity.clear();
+        self.updated.clear();
+        for y in region.y..region.y + region.height {
+            let source_start = y * world.width + region.x;
+            self.particles
+                .extend_from_slice(&world.particles[source_start..source_start + region.width]);
+            self.updated
+                .extend_from_slice(&world.updated[source_start..source_start + region.width]);
+            self.temperature
+                .extend_from_slice(&world.temperature[source_start..source_start + region.width]);
+            self.velocity
+                .extend_from_slice(&world.velocity[source_start..source_start + region.width]);
+        }
+
+        self.color_buffer.resize(region.width * region.height * 4, 0);
+        for y in 0..region.height {
+            let source_start = ((region.y + y) * world.width + region.x) * 4;
+            let target_start = y * region.width * 4;
+            self.color_buffer[target_start..target_start + region.width * 4]
+                .copy_from_slice(&world.color_buffer[source_start..source_start + region.width * 4]);
+        }
+    }
+
+    // Updates the chunk, given in world coordinates, with the same scan direction as the order scheme
+    pub(super) fn update_region(
+        &mut self,
+        plugins: &[Box<dyn Plugin>],
+        region: &ChunkRect,
+        chunk: &ChunkRect,
+        order_scheme: &OrderScheme,
+    ) {
+        let local = order_scheme.sub_scheme(
+            chunk.x - region.x,
+            chunk.y - region.y,
+            chunk.width,
+            chunk.height,
+        );
+
+        for y in local.order_y {
+            if !self.activity.is_row_awake(y as usize) {
+                continue;
+            }
+
+            for x in local.order_x {
+                self.update_cell(x as usize, y as usize, plugins);
+            }
+        }
+    }
+
+    // Copies back what a worker simulated, only the part it wrote to as the rest is still the same
+    pub(crate) fn store_region(&mut self, worker: &SimulationState, region: &ChunkRect) {
+        self.activity.store_region(&worker.activity);
+
+        let Some(dirty_rect) = worker.dirty_rect else {
+            return;
+        };
+
+        let (x, width) = (dirty_rect.min_x, dirty_rect.width());
+        for y in dirty_rect.min_y..=dirty_rect.max_y {
+            let target_start = self.index(region.x + x, region.y + y);
+            let source_start = worker.index(x, y);
+            self.particles[target_start..target_start + width]
+                .copy_from_slice(&worker.particles[source_start..source_start + width]);
+            self.updated[target_start..target_start + width]
+                .copy_from_slice(&worker.updated[source_start..source_start + width]);
+            self.temperature[target_start..target_start + width]
+                .copy_from_slice(&worker.temperature[source_start..source_start + width]);
+            self.velocity[target_start..target_start + width]
+                .copy_from_slice(&worker.velocity[source_start..source_start + width]);
+            self.color_buffer[target_start * 4..(target_start + width) * 4]
+                .copy_from_slice(&worker.color_buffer[source_start * 4..(source_start + width) * 4]);
+        }
+
+        self.thermal_active |= worker.thermal_active;
+        self.motion_active |= worker.motion_active;
+
+        let dirty_rect = dirty_rect.offset(region.x, region.y);
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some(current) => current.union(&dirty_rect),
+            None => dirty_rect,
+        });
+    }
+
+    // Workers get their own generator each phase, seeded from ours so multithreaded runs stay deterministic too
+    pub(crate) fn next_worker_seed(&self) -> u64 {
+        self.with_rng(|rng| rng.u64(..))
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{Read, Write};
+
+use crate::api::*;
+
+// Every snapshot starts with this so we can reject random files early
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"SAND";
+// Bumped whenever the layout changes, files with any other version are refused
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Bytes written per particle, every field in declaration order with the id in little endian
+const PARTICLE_SIZE: usize = 8;
+
+// Biggest world a snapshot can hold, 4096x4096 or anything with the same cell count. The size comes from the file,
+// so without a limit a broken or crafted header would make us allocate whatever it says before reading anything
+pub const MAX_SNAPSHOT_CELLS: usize = 1 << 24;
+
+/// Plain copy of the world state that can be written to and read from a binary file.
+/// Particle ids are only meaningful together with `particle_names`, as the plugin set
+/// might be different when the snapshot is loaded back.
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+    pub frame_count: u32,
+    pub rng_state: u64,
+    // Index is the particle id at the time the snapshot was taken
+    pub particle_names: Vec<String>,
+    // Property names of each particle type, in slot order
+    pub particle_properties: Vec<Vec<String>>,
+    // Row major, width * height particles
+    pub particles: Vec<Particle>,
+    // Same layout as the particles
+    pub temperature: Vec<f32>,
+    // Same layout as the particles, x and y of each cell
+    pub velocity: Vec<[f32; 2]>,
+}
+
+impl Snapshot {
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), String> {
+        // It couldn't be read back
+        if self.width * self.height > MAX_SNAPSHOT_CELLS {
+            return Err(format!(
+                "Unable to save a {}x{} world, snapshots can have up to {} cells",
+                self.width, self.height, MAX_SNAPSHOT_CELLS
+            ));
+        }
+        let cells = self.width * self.height;
+        if self.particles.len() != cells || self.temperature.len() != cells || self.velocity.len() != cells {
+            return Err(format!("Unable to save snapshot, it doesn't have {} of every cell", cells));
+        }
+        self.write_to_internal(writer)
+            .map_err(|error| format!("Error writing snapshot: {}", error))
+    }
+
+    fn write_to_internal(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(&SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.width as u32).to_le_bytes())?;
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+        writer.write_all(&self.frame_count.to_le_bytes())?;
+        writer.write_all(&self.rng_state.to_le_bytes())?;
+
+        writer.write_all(&(self.particle_names.len() as u32).to_le_bytes())?;
+        for name in self.particle_names.iter() {
+            write_string(writer, name)?;
+        }
+
+        for index in 0..self.particle_names.len() {
+            let properties = self
+                .particle_properties
+                .get(index)
+                .map_or(&[] as &[String], |properties| properties.as_slice());
+
+            writer.write_all(&(properties.len() as u32).to_le_bytes())?;
+            for property in properties {
+                write_string(writer, property)?;
+            }
+        }
+
+        // Buffering the whole grid is way faster than writing particle by particle on unbuffered writers
+        let mut buffer = Vec::with_capacity(self.particles.len() * PARTICLE_SIZE);
+        for particle in self.particles.iter() {
+            buffer.extend_from_slice(&particle.id.to_le_bytes());
+            buffer.extend_from_slice(&[
+                particle.opacity,
+                particle.hue_shift,
+                particle.color_fade,
+                particle.extra,
+                particle.extra2,
+                particle.extra3,
+            ]);
+        }
+        writer.write_all(&buffer)?;
+
+        let buffer = self
+            .temperature
+            .iter()
+            .flat_map(|temperature| temperature.to_le_bytes())
+            .collect::<Vec<_>>();
+        writer.write_all(&buffer)?;
+
+        let buffer = self
+            .velocity
+            .iter()
+            .flat_map(|[x, y]| x.to_le_bytes().into_iter().chain(y.to_le_bytes()))
+            .collect::<Vec<_>>();
+        writer.write_all(&buffer)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Snapshot, String> {
+        let mut magic = [0; 4];
+        read_exact(reader, &mut magic)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(String::from("Not a snapshot file"));
+        }
+
+        let version = read_u32(reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Unsupported snapshot version {}, only version {} is supported",
+                version, SNAPSHOT_VERSION
+            ));
+        }
+
+        let width = read_u32(reader)? as usize;
+        let height = read_u32(reader)? as usize;
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid snapshot size {}x{}", width, height));
+        }
+        let cells = match width.checked_mul(height) {
+            Some(cells) if cells <= MAX_SNAPSHOT_CELLS => cells,
+            _ => {
+                return Err(format!(
+                    "Snapshot size {}x{} is too big, it can have up to {} cells",
+                    width, height, MAX_SNAPSHOT_CELLS
+                ))
+            }
+        };
+
+        let frame_count = read_u32(reader)?;
+        let rng_state = read_u64(reader)?;
+
+        let name_count = read_u32(reader)? as usize;
+        let mut particle_names = Vec::with_capacity(name_count.min(MAX_PARTICLE_TYPES));
+        for _ in 0..name_count {
+            particle_names.push(read_string(reader)?);
+        }
+
+        let mut particle_properties = Vec::with_capacity(particle_names.len());
+        for _ in 0..name_count {
+            let property_count = read_u32(reader)? as usize;
+            let mut properties = Vec::with_capacity(property_count.min(PROPERTY_SLOTS));
+            for _ in 0..property_count {
+                properties.push(read_string(reader)?);
+            }
+            particle_properties.push(properties);
+        }
+
+        let mut buffer = vec![0; cells * PARTICLE_SIZE];
+        read_exact(reader, &mut buffer)?;
+
+        let particles = buffer
+            .chunks_exact(PARTICLE_SIZE)
+            .map(|bytes| Particle {
+                id: u16::from_le_bytes([bytes[0], bytes[1]]),
+                opacity: bytes[2],
+                hue_shift: bytes[3],
+                color_fade: bytes[4],
+                extra: bytes[5],
+                extra2: bytes[6],
+                extra3: bytes[7],
+            })
+            .collect();
+
+        let mut buffer = vec![0; cells * 4];
+        read_exact(reader, &mut buffer)?;
+        let temperature = buffer
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+
+        let mut buffer = vec![0; cells * 8];
+        read_exact(reader, &mut buffer)?;
+        let velocity = buffer
+            .chunks_exact(8)
+            .map(|bytes| {
+                [
+                    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                    f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                ]
+            })
+            .collect();
+
+        Ok(Snapshot {
+            width,
+            height,
+            frame_count,
+            rng_state,
+            particle_names,
+            particle_properties,
+            particles,
+            temperature,
+            velocity,
+        })
+    }
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> std::io::Result<()> {
+    writer.write_all(&(string.len() as u32).to_le_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, String> {
+    let length = read_u32(reader)? as usize;
+    let mut string = Vec::new();
+    reader
+        .take(length as u64)
+        .read_to_end(&mut string)
+        .map_err(|error| format!("Error reading snapshot: {}", error))?;
+    if string.len() != length {
+        return Err(String::from("Snapshot is truncated"));
+    }
+    String::from_utf8(string).map_err(|_| String::from("Snapshot contains an invalid name"))
+}
+
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), String> {
+    reader.read_exact(buffer).map_err(|error| match error.kind() {
+        std::io::ErrorKind::UnexpectedEof => String::from("Snapshot is truncated"),
+        _ => format!("Error reading snapshot: {}", error),
+    })
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, String> {
+    let mut bytes = [0; 8];
+    read_exact(reader, &mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,187 @@
+use crate::api::*;
+
+// Temperature every cell starts with, in degrees
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+// Fraction of the difference between two cells that moves from one to the other each frame when both
+// conduct perfectly. Each cell has 4 neighbours, anything above 0.25 would make the diffusion oscillate
+const DIFFUSION_RATE: f32 = 0.25;
+
+// Cells whose temperature moves more than this in one frame wake their chunk,
+// so plugins that react to heat get updated even if nothing around them moved
+pub(crate) const WAKE_TEMPERATURE_CHANGE: f32 = 1.0;
+
+// Below this the whole world is considered to be at rest and the diffusion stops until something changes
+pub(crate) const SETTLED_TEMPERATURE_CHANGE: f32 = 0.01;
+
+/// Particle turns into another one when its temperature crosses a threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseChange {
+    pub temperature: f32,
+    // Name of the particle it turns into
+    pub into: String,
+}
+
+impl PhaseChange {
+    pub fn new(temperature: f32, into: &str) -> PhaseChange {
+        PhaseChange {
+            temperature,
+            into: into.to_string(),
+        }
+    }
+}
+
+/// How a particle type behaves with heat
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermalProperties {
+    // From 0, doesn't let heat through, to 1, conducts as fast as possible
+    pub conductivity: f32,
+    // Temperature particles placed with the brush start with
+    pub temperature: f32,
+    // How fast the particle goes back to the ambient temperature on its own, from 0 to 1. Air uses it
+    // so the heat doesn't pile up forever in a closed world
+    pub ambient_exchange: f32,
+    // Melting and boiling happen at or above their temperature, freezing at or below it
+    pub melting: Option<PhaseChange>,
+    pub boiling: Option<PhaseChange>,
+    pub freezing: Option<PhaseChange>,
+}
+
+impl Default for ThermalProperties {
+    fn default() -> Self {
+        ThermalProperties {
+            conductivity: 0.1,
+            temperature: AMBIENT_TEMPERATURE,
+            ambient_exchange: 0.0,
+            melting: None,
+            boiling: None,
+            freezing: None,
+        }
+    }
+}
+
+impl ThermalProperties {
+    // Empty cells are air, it barely conducts and it slowly loses its heat
+    pub fn air() -> ThermalProperties {
+        ThermalProperties {
+            conductivity: 0.02,
+            ambient_exchange: 0.01,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn sanitized(self) -> ThermalProperties {
+        ThermalProperties {
+            conductivity: self.conductivity.clamp(0.0, 1.0),
+            ambient_exchange: self.ambient_exchange.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+}
+
+// Phase changes with the target name already turned into an id, they are solved each time plugins change
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ResolvedPhaseChanges {
+    // (temperature, id), the highest one is checked first
+    pub(crate) heating: [Option<(f32, u16)>; 2],
+    pub(crate) freezing: Option<(f32, u16)>,
+}
+
+impl ResolvedPhaseChanges {
+    pub(crate) fn resolve(thermal: &ThermalProperties, id_from_name: impl Fn(&str) -> u16) -> ResolvedPhaseChanges {
+        let resolve = |phase_change: &Option<PhaseChange>| {
+            let phase_change = phase_change.as_ref()?;
+            let id = id_from_name(&phase_change.into);
+
+            if id == Particle::INVALID.id {
+                return None;
+            }
+
+            Some((phase_change.temperature, id))
+        };
+
+        let mut heating = [resolve(&thermal.boiling), resolve(&thermal.melting)];
+        if let [Some(first), Some(second)] = heating {
+            if second.0 > first.0 {
+                heating = [Some(second), Some(first)];
+            }
+        }
+
+        ResolvedPhaseChanges {
+            heating,
+            freezing: resolve(&thermal.freezing),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.heating.iter().all(|change| change.is_none()) && self.freezing.is_none()
+    }
+
+    // Id the particle turns into at this temperature, if any
+    pub(crate) fn target(&self, temperature: f32) -> Option<u16> {
+        for (threshold, id) in self.heating.iter().flatten() {
+            if temperature >= *threshold {
+                return Some(*id);
+            }
+        }
+
+        match self.freezing {
+            Some((threshold, id)) if temperature <= threshold => Some(id),
+            _ => None,
+        }
+    }
+}
+
+// One explicit diffusion step over the whole grid, borders don't let heat through. Cells that
+// change a lot are reported through on_change. Returns the biggest change of any cell, it's used to know when the world settled
+pub(crate) fn diffuse_heat(
+    temperature: &[f32],
+    output: &mut [f32],
+    conductivity: &[f32],
+    ambient_exchange: &[f32],
+    width: usize,
+    height: usize,
+    mut on_change: impl FnMut(usize, usize),
+) -> f32 {
+    let mut max_change: f32 = 0.0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let current = temperature[index];
+            let current_conductivity = conductivity[index];
+            let mut flow = 0.0;
+
+            // Heat flows as fast as the worst conductor of the two lets it
+            let mut exchange = |neighbor: usize| {
+                flow += current_conductivity.min(conductivity[neighbor]) * (temperature[neighbor] - current);
+            };
+
+            if x > 0 {
+                exchange(index - 1);
+            }
+            if x + 1 < width {
+                exchange(index + 1);
+            }
+            if y > 0 {
+                exchange(index - width);
+            }
+            if y + 1 < height {
+                exchange(index + width);
+            }
+
+            let mut new_temperature = current + flow * DIFFUSION_RATE;
+            new_temperature += (AMBIENT_TEMPERATURE - new_temperature) * ambient_exchange[index];
+
+            let change = (new_temperature - current).abs();
+            if change >= WAKE_TEMPERATURE_CHANGE {
+                on_change(x, y);
+            }
+
+            max_change = max_change.max(change);
+            output[index] = new_temperature;
+        }
+    }
+
+    max_change
+}
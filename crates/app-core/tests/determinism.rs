@@ -0,0 +1,111 @@
+use app_core::*;
+
+// Falls and slides to a random side, with a random tint so every roll shows up in the grid
+struct Grain;
+
+impl Plugin for Grain {
+    fn register(&mut self) -> PluginResult {
+        PluginResult {
+            name: String::from("Grain"),
+            ..Default::default()
+        }
+    }
+
+    fn update(&self, api: &mut ParticleApi) {
+        let side = api.gen_range(-1, 1);
+        if api.is_empty(0, -1) {
+            api.move_to(0, -1);
+        } else if side != 0 && api.is_empty(side, -1) {
+            api.move_to(side, -1);
+        } else {
+            let mut particle = api.get_current();
+            particle.hue_shift = api.gen_range(0, 40) as u8;
+            api.set(0, 0, particle);
+        }
+    }
+}
+
+const SIZE: usize = 96;
+
+fn world(seed: u64, multithreaded: bool) -> Simulation {
+    let mut simulation = Simulation::new_with_seed(SIZE, SIZE, seed);
+    simulation.add_plugin(Box::new(Grain)).unwrap();
+    if multithreaded {
+        simulation.set_multithreaded(true, 32);
+    }
+
+    let id = simulation.get_particle_id("Grain").unwrap();
+    for y in SIZE / 2..SIZE {
+        for x in 0..SIZE {
+            if (x * 7 + y * 3) % 5 != 0 {
+                simulation.set_particle(x, y, Particle { id, ..Particle::EMPTY });
+            }
+        }
+    }
+
+    simulation
+}
+
+fn grid(simulation: &Simulation) -> Vec<(u16, u8)> {
+    simulation
+        .get_particles()
+        .iter()
+        .map(|particle| (particle.id, particle.hue_shift))
+        .collect()
+}
+
+fn run(simulation: &mut Simulation, frames: usize) -> Vec<(u16, u8)> {
+    for _ in 0..frames {
+        simulation.update();
+    }
+    grid(simulation)
+}
+
+fn assert_same_seed_same_grid(multithreaded: bool) {
+    let first = run(&mut world(11, multithreaded), 60);
+    let second = run(&mut world(11, multithreaded), 60);
+    assert!(first == second, "the same seed ended up in different grids");
+
+    let other_seed = run(&mut world(12, multithreaded), 60);
+    assert!(first != other_seed, "the seed didn't change anything");
+}
+
+// Saves the world halfway through, then plays the rest again from there with the saved rng state
+fn assert_rng_state_replays(multithreaded: bool) {
+    let mut simulation = world(5, multithreaded);
+    run(&mut simulation, 30);
+
+    let mut snapshot = Vec::new();
+    simulation.save_snapshot(&mut snapshot).unwrap();
+    let state = simulation.get_rng_state();
+    let original = run(&mut simulation, 30);
+
+    simulation.load_snapshot(&mut snapshot.as_slice()).unwrap();
+    simulation.set_rng_state(state ^ 0x9e37_79b9);
+    let other_state = run(&mut simulation, 30);
+    assert!(original != other_state, "the rng state didn't change anything");
+
+    simulation.load_snapshot(&mut snapshot.as_slice()).unwrap();
+    simulation.set_rng_state(state);
+    assert!(run(&mut simulation, 30) == original, "restoring the rng state didn't replay the same frames");
+}
+
+#[test]
+fn same_seed_same_grid() {
+    assert_same_seed_same_grid(false);
+}
+
+#[test]
+fn same_seed_same_grid_multithreaded() {
+    assert_same_seed_same_grid(true);
+}
+
+#[test]
+fn rng_state_replays() {
+    assert_rng_state_replays(false);
+}
+
+#[test]
+fn rng_state_replays_multithreaded() {
+    assert_rng_state_replays(true);
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use app_core::*;
+
+// The example libraries, cargo test builds examples before running the tests
+fn example_library(name: &str) -> libloading::Library {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    let path: PathBuf = path.join("examples").join(libloading::library_filename(name));
+
+    unsafe { libloading::Library::new(&path) }
+        .unwrap_or_else(|error| panic!("{}, build it with cargo build --examples: {}", path.display(), error))
+}
+
+fn abi_version(library: &libloading::Library) -> u32 {
+    unsafe { library.get::<AbiVersionFunction>(ABI_VERSION_SYMBOL).unwrap()() }
+}
+
+#[test]
+fn library_with_the_same_version_is_loaded() {
+    let library = example_library("native_plugin");
+    let version = abi_version(&library);
+    assert_eq!(version, PLUGIN_ABI_VERSION);
+    assert_eq!(check_abi_version(version), Ok(()));
+
+    let create = unsafe { *library.get::<PluginCreateFunction>(PLUGIN_CREATE_SYMBOL).unwrap() };
+    let plugins = unsafe { create_plugins(create, Arc::new(library)) };
+    assert_eq!(plugins.len(), 1);
+
+    // It runs like any other plugin
+    let mut simulation = Simulation::new_with_seed(10, 10, 3);
+    simulation.add_plugins(plugins).unwrap();
+    let gravel = simulation.get_particle_id("Gravel").unwrap();
+    simulation.set_particle(4, 0, Particle::from(gravel));
+    for _ in 0..20 {
+        simulation.update();
+    }
+
+    let column = simulation.get_particles().rows().map(|row| row[4].id).collect::<Vec<_>>();
+    assert_eq!(column.iter().filter(|id| **id == gravel).count(), 1);
+    assert_eq!(column[9], gravel);
+}
+
+#[test]
+fn library_with_another_major_version_is_refused() {
+    let library = example_library("outdated_native_plugin");
+    let version = abi_version(&library);
+
+    let error = check_abi_version(version).unwrap_err();
+    assert!(error.contains("1.0.0") && error.contains(&format_abi_version(PLUGIN_ABI_VERSION)), "{}", error);
+}
+
+#[test]
+fn older_minor_versions_are_compatible() {
+    assert!(is_abi_compatible(PLUGIN_ABI_VERSION + 7));
+    assert!(!is_abi_compatible(PLUGIN_ABI_VERSION + (1 << 16)));
+    assert!(!is_abi_compatible(PLUGIN_ABI_VERSION + (1 << 24)));
+}
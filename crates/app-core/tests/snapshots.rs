@@ -0,0 +1,169 @@
+use app_core::*;
+
+// A particle that does nothing, with the given properties and starting temperature
+struct Still {
+    name: &'static str,
+    properties: &'static [&'static str],
+    temperature: f32,
+}
+
+impl Plugin for Still {
+    fn register(&mut self) -> PluginResult {
+        PluginResult {
+            name: String::from(self.name),
+            properties: self
+                .properties
+                .iter()
+                .map(|name| PropertyDefinition::new(name, 0, 0, 255))
+                .collect(),
+            thermal: ThermalProperties {
+                temperature: self.temperature,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn update(&self, _: &mut ParticleApi) {}
+}
+
+const STONE: Still = Still { name: "Stone", properties: &[], temperature: AMBIENT_TEMPERATURE };
+const MOSS: Still = Still { name: "Moss", properties: &["age", "water"], temperature: AMBIENT_TEMPERATURE };
+const EMBER: Still = Still { name: "Ember", properties: &[], temperature: 400.0 };
+
+fn world(plugins: Vec<Still>) -> Simulation {
+    let mut simulation = Simulation::new_with_seed(12, 10, 3);
+    for plugin in plugins {
+        simulation.add_plugin(Box::new(plugin)).unwrap();
+    }
+    simulation
+}
+
+// Stone, moss and ember in stripes, moss with an age of x and a water of y
+fn fill(simulation: &mut Simulation) {
+    let ids = ["Stone", "Moss", "Ember"].map(|name| simulation.get_particle_id(name).unwrap());
+    for y in 0..simulation.get_height() {
+        for x in 0..simulation.get_width() {
+            let id = ids[(x + y) % 3];
+            let (extra, extra2) = if id == ids[1] { (x as u8, y as u8) } else { (0, 0) };
+            simulation.set_particle(x, y, Particle { id, extra, extra2, ..Particle::EMPTY });
+        }
+    }
+}
+
+fn names(simulation: &Simulation) -> Vec<(String, u8, u8)> {
+    simulation
+        .get_particles()
+        .iter()
+        .map(|particle| {
+            let name = simulation.get_particle_name(particle.id as usize).cloned().unwrap_or_default();
+            (name, particle.extra, particle.extra2)
+        })
+        .collect()
+}
+
+fn temperatures(simulation: &Simulation) -> Vec<f32> {
+    (0..simulation.get_width() * simulation.get_height())
+        .map(|i| simulation.get_temperature(i % simulation.get_width(), i / simulation.get_width()).unwrap())
+        .collect()
+}
+
+fn save(simulation: &Simulation) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    simulation.save_snapshot(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn round_trip() {
+    let mut original = world(vec![STONE, MOSS, EMBER]);
+    fill(&mut original);
+    for _ in 0..5 {
+        original.update();
+    }
+    let bytes = save(&original);
+
+    let mut loaded = world(vec![STONE, MOSS, EMBER]);
+    loaded.load_snapshot(&mut bytes.as_slice()).unwrap();
+
+    assert!(loaded.get_particles().iter().eq(original.get_particles().iter()));
+    assert_eq!(names(&loaded), names(&original));
+    assert_eq!(temperatures(&loaded), temperatures(&original));
+    assert_eq!(loaded.get_frame_count(), original.get_frame_count());
+    assert_eq!(loaded.get_rng_state(), original.get_rng_state());
+    assert_eq!(save(&loaded), bytes);
+}
+
+#[test]
+fn names_are_remapped() {
+    let mut original = world(vec![STONE, MOSS, EMBER]);
+    fill(&mut original);
+    let bytes = save(&original);
+
+    // Other ids, and moss keeps its properties in other slots
+    let mut reordered = world(vec![
+        EMBER,
+        Still { name: "Moss", properties: &["water", "age"], temperature: AMBIENT_TEMPERATURE },
+        STONE,
+    ]);
+    reordered.load_snapshot(&mut bytes.as_slice()).unwrap();
+    let swapped = names(&original)
+        .into_iter()
+        .map(|(name, age, water)| (name, water, age))
+        .collect::<Vec<_>>();
+    assert_eq!(names(&reordered), swapped);
+
+    // Particles of a removed plugin are gone, the others keep their ids
+    let ember = original.get_particle_id("Ember").unwrap();
+    let mut removed = world(vec![STONE, MOSS, EMBER]);
+    removed.remove_plugin(removed.get_particle_id("Moss").unwrap());
+    removed.load_snapshot(&mut bytes.as_slice()).unwrap();
+    for (particle, before) in removed.get_particles().iter().zip(original.get_particles().iter()) {
+        match original.get_particle_name(before.id as usize).unwrap().as_str() {
+            "Moss" => assert_eq!(particle.id, Particle::EMPTY.id),
+            _ => assert_eq!(particle.id, before.id),
+        }
+    }
+    assert_eq!(removed.get_particle_id("Ember").unwrap(), ember);
+}
+
+#[test]
+fn truncated() {
+    let mut simulation = world(vec![STONE, MOSS, EMBER]);
+    fill(&mut simulation);
+    let bytes = save(&simulation);
+
+    // Cut inside the header, the names and the cells
+    for length in [2, 10, 40, bytes.len() - 1] {
+        let error = simulation.load_snapshot(&mut &bytes[..length]).err();
+        assert_eq!(error.as_deref(), Some("Snapshot is truncated"), "cut at {}", length);
+    }
+}
+
+#[test]
+fn oversized() {
+    let mut bytes = SNAPSHOT_MAGIC.to_vec();
+    bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+
+    let error = world(vec![]).load_snapshot(&mut bytes.as_slice()).unwrap_err();
+    assert!(error.contains("too big"), "{}", error);
+
+    let big = Simulation::new_with_seed(MAX_SNAPSHOT_CELLS + 1, 1, 0);
+    assert!(big.save_snapshot(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn wrong_magic_or_version() {
+    let mut simulation = world(vec![STONE]);
+    let mut bytes = save(&simulation);
+
+    bytes[0] = b'X';
+    assert_eq!(simulation.load_snapshot(&mut bytes.as_slice()).unwrap_err(), "Not a snapshot file");
+
+    bytes[0] = SNAPSHOT_MAGIC[0];
+    bytes[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    let error = simulation.load_snapshot(&mut bytes.as_slice()).unwrap_err();
+    assert!(error.starts_with("Unsupported snapshot version"), "{}", error);
+}
@@ -0,0 +1,128 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use app_core::*;
+
+// Counts how many times its particles were updated, then does whatever the test needs
+struct Counted {
+    updates: Arc<AtomicUsize>,
+    behavior: fn(&mut ParticleApi),
+}
+
+impl Plugin for Counted {
+    fn register(&mut self) -> PluginResult {
+        PluginResult {
+            name: String::from("Counted"),
+            ..Default::default()
+        }
+    }
+
+    fn update(&self, api: &mut ParticleApi) {
+        self.updates.fetch_add(1, Ordering::Relaxed);
+        (self.behavior)(api);
+    }
+}
+
+// A world with the top rows full of counted particles, returns how many there are
+fn world(width: usize, height: usize, rows: usize, behavior: fn(&mut ParticleApi)) -> (Simulation, Arc<AtomicUsize>, usize) {
+    let updates = Arc::new(AtomicUsize::new(0));
+    let mut simulation = Simulation::new_with_seed(width, height, 7);
+    simulation.add_plugin(Box::new(Counted {
+        updates: Arc::clone(&updates),
+        behavior,
+    }))
+    .unwrap();
+
+    let id = simulation.get_particle_id("Counted").unwrap();
+    for y in 0..rows {
+        for x in 0..width {
+            simulation.set_particle(x, y, Particle { id, ..Particle::EMPTY });
+        }
+    }
+
+    (simulation, updates, width * rows)
+}
+
+fn assert_updated_once_per_frame(simulation: &mut Simulation, updates: &AtomicUsize, particles: usize, frames: usize) {
+    for frame in 0..frames {
+        simulation.update();
+        assert_eq!(updates.swap(0, Ordering::Relaxed), particles, "frame {}", frame);
+    }
+}
+
+fn fall(api: &mut ParticleApi) {
+    if api.get_type(0, -1) == Particle::EMPTY.id {
+        api.move_to(0, -1);
+    }
+}
+
+// Moves without move_to, copying itself below and emptying its cell
+fn fall_relaxed(api: &mut ParticleApi) {
+    if api.get_type(0, -1) == Particle::EMPTY.id {
+        api.set_relaxed(0, -1, api.get_current());
+        api.set_relaxed(0, 0, Particle::EMPTY);
+    }
+}
+
+#[test]
+fn idle_particles_are_updated_every_frame() {
+    let (mut simulation, updates, particles) = world(20, 20, 20, |_| ());
+    simulation.set_sleeping_enabled(false);
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 10);
+}
+
+#[test]
+fn waiting_particles_keep_their_chunk_awake() {
+    let (mut simulation, updates, particles) = world(40, 40, 40, |api| api.keep_awake());
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 10);
+}
+
+#[test]
+fn idle_particles_fall_asleep() {
+    let (mut simulation, updates, _) = world(40, 40, 40, |_| ());
+
+    for _ in 0..10 {
+        simulation.update();
+    }
+    updates.store(0, Ordering::Relaxed);
+    simulation.update();
+    assert_eq!(updates.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn falling_particles_are_updated_once_per_frame() {
+    let (mut simulation, updates, particles) = world(20, 40, 5, fall);
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 40);
+}
+
+#[test]
+fn particles_moved_with_set_relaxed_are_updated_once_per_frame() {
+    let (mut simulation, updates, particles) = world(20, 40, 5, fall_relaxed);
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 40);
+}
+
+#[test]
+fn particles_crossing_chunks_are_updated_once_per_frame() {
+    let (mut simulation, updates, particles) = world(64, 96, 16, fall);
+    simulation.set_multithreaded(true, 16);
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 90);
+}
+
+#[test]
+fn snapshots_continue_with_every_particle_pending() {
+    let (mut simulation, updates, particles) = world(20, 20, 20, |_| ());
+    simulation.set_sleeping_enabled(false);
+    simulation.update();
+
+    let mut snapshot = Vec::new();
+    simulation.save_snapshot(&mut snapshot).unwrap();
+    simulation.load_snapshot(&mut snapshot.as_slice()).unwrap();
+    updates.store(0, Ordering::Relaxed);
+
+    assert_updated_once_per_frame(&mut simulation, &updates, particles, 3);
+}
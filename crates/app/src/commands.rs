@@ -1,20 +1,28 @@
 use std::collections::VecDeque;
 
-use app_core::Simulation;
+use app_core::{ResizeAnchor, Simulation};
 
 #[allow(unused)]
 pub enum Command {
     NewPlugin(String),
-    RemovePlugin(u8),
+    // Path of a native plugin library, plugins already loaded with the same names are replaced
+    NewNativePlugin(String),
+    // A WebAssembly module, binary or in the text format
+    NewWasmPlugin(Vec<u8>),
+    NewReactions(String),
+    NewProcedures(String),
+    RemovePlugin(u16),
     Debug((String, f32)),
-    CanvasSize(u32),
+    CanvasSize(u32, u32, ResizeAnchor),
     SetMouseHidden(bool),
     Pause(bool),
     SetBrushSize(isize),
-    ParticleSelected(u8),
+    ParticleSelected(u16),
     StepSimulation,
     SimulationMethod(Box<dyn Fn(&mut Simulation)>),
     NewBackgroundColor([u8; 4]),
+    SaveSnapshot(String),
+    LoadSnapshot(String),
     Clear,
 }
 
@@ -3,19 +3,18 @@ use egui_macroquad::macroquad::{
     color::{hsl_to_rgb, rgb_to_hsl, Color, WHITE},
     input::*,
     shapes::draw_circle_lines,
-    window::*,
 };
 
-use crate::{push_command, Command, Entity};
+use crate::{push_command, simulation_screen_rect, Command, Entity};
 
 // I'm just mapping the mouse position to the texture coordinates
 fn mouse_pos_to_square(width: usize, height: usize) -> (isize, isize) {
     let (mouse_x, mouse_y) = mouse_position();
-    let min = screen_height().min(screen_width());
-    let x = (mouse_x - (screen_width() - min) / 2.0) / min * width as f32;
-    let y = (mouse_y - (screen_height() - min) / 2.0) / min * height as f32;
+    let (rect_x, rect_y, rect_width, rect_height) = simulation_screen_rect(width, height);
+    let x = (mouse_x - rect_x) / rect_width * width as f32;
+    let y = (mouse_y - rect_y) / rect_height * height as f32;
 
-    (x as isize, y as isize)
+    (x.floor() as isize, y.floor() as isize)
 }
 
 pub struct Brush {
@@ -79,8 +78,8 @@ impl Entity for Brush {
                 let sim_height = simulation.get_height();
 
                 let (mouse_x, mouse_y) = mouse_pos_to_square(sim_width, sim_height);
-                let screen_ratio_to_texture =
-                    screen_height().min(screen_width()) / (sim_width.min(sim_height)) as f32;
+                let (_, _, rect_width, _) = simulation_screen_rect(sim_width, sim_height);
+                let screen_ratio_to_texture = rect_width / sim_width as f32;
 
                 let radius = (radius as f32 / screen_ratio_to_texture) as isize;
 
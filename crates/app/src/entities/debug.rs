@@ -2,6 +2,8 @@ use egui_macroquad::macroquad::input::{is_key_pressed, KeyCode};
 
 use crate::{push_command, Command, Entity };
 
+const SNAPSHOT_PATH: &str = "snapshot.sand";
+
 pub struct Debug {}
 
 impl Debug {
@@ -19,5 +21,13 @@ impl Entity for Debug {
             push_command(Command::NewPlugin(data));
             push_command(Command::NewPlugin(data2));
         }
+
+        if is_key_pressed(KeyCode::F5) {
+            push_command(Command::SaveSnapshot(String::from(SNAPSHOT_PATH)));
+        }
+
+        if is_key_pressed(KeyCode::F9) {
+            push_command(Command::LoadSnapshot(String::from(SNAPSHOT_PATH)));
+        }
     }
 }
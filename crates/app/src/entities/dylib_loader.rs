@@ -1,37 +1,84 @@
-use app_core::api::Plugin;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-type PluginLoader<'a> =
-    Result<libloading::Symbol<'a, fn() -> Vec<Box<dyn Plugin>>>, libloading::Error>;
+use app_core::api::{
+    check_abi_version, create_plugins, AbiVersionFunction, Plugin, PluginCreateFunction, ABI_VERSION_SYMBOL,
+    PLUGIN_CREATE_SYMBOL,
+};
+
+// Symbol of libraries built against the Rust plugin interface, only used to explain why they are refused
+const RUST_PLUGIN_SYMBOL: &[u8] = b"plugin";
+
+// A copy of the library is what gets loaded, so the file itself can be rebuilt while the app runs and
+// loading it again gives the new code instead of the one already in memory
+struct LoadedLibrary {
+    library: Option<libloading::Library>,
+    copy: PathBuf,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        // Closed before the copy is removed, Windows doesn't let a loaded library be deleted
+        drop(self.library.take());
+        let _ = fs::remove_file(&self.copy);
+    }
+}
 
 pub struct DylibLoader {
-    libraries: Vec<libloading::Library>,
+    // Tells copies of the same library apart
+    loaded: usize,
 }
 
 impl DylibLoader {
     pub fn new() -> DylibLoader {
-        DylibLoader {
-            libraries: Vec::new(),
-        }
+        DylibLoader { loaded: 0 }
+    }
+
+    fn copy(&mut self, path: &str) -> Result<LoadedLibrary, String> {
+        let file_name = Path::new(path).file_name().ok_or_else(|| format!("{} isn't a file", path))?;
+        let copy = std::env::temp_dir().join(format!(
+            "sand-{}-{}-{}",
+            std::process::id(),
+            self.loaded,
+            file_name.to_string_lossy()
+        ));
+        self.loaded += 1;
+
+        fs::copy(path, &copy).map_err(|error| format!("Unable to copy library {}: {}", path, error))?;
+        let mut loaded = LoadedLibrary { library: None, copy };
+        let library = unsafe { libloading::Library::new(&loaded.copy) }
+            .map_err(|error| format!("Error loading library: {:?} at path {}", error, path))?;
+        loaded.library = Some(library);
+        Ok(loaded)
     }
 
+    // Only libraries using the C plugin interface, see app_core::export_plugins!. The version is checked
+    // before anything else is called, a library built for another one is refused instead of crashing.
+    // Each plugin keeps the library loaded, it's unloaded once all of them are dropped
     pub fn load(&mut self, path: &str) -> Result<Vec<Box<dyn Plugin>>, String> {
-        let plugin_lib = unsafe { libloading::Library::new(path) };
-        if let Ok(plugin_lib) = plugin_lib {
-            let plugin_loader: PluginLoader = unsafe { plugin_lib.get(b"plugin") };
-
-            match plugin_loader {
-                Ok(plugin_loader) => {
-                    let result = plugin_loader();
-                    self.libraries.push(plugin_lib);
-                    return Ok(result);
-                }
-                Err(e) => {
-                    return Err(format!("Error loading plugin: {:?}, at path {}", e, path));
-                }
+        let loaded = Arc::new(self.copy(path)?);
+        let library = loaded.library.as_ref().unwrap();
+
+        let version = match unsafe { library.get::<AbiVersionFunction>(ABI_VERSION_SYMBOL) } {
+            Ok(version) => unsafe { version() },
+            Err(_) if unsafe { library.get::<AbiVersionFunction>(RUST_PLUGIN_SYMBOL) }.is_ok() => {
+                return Err(format!(
+                    "{} was built against the Rust plugin interface, which only works with the exact compiler the app was built with. Export its plugins with app_core::export_plugins! instead",
+                    path
+                ));
             }
-        } else {
-            return Err(format!("Error loading library: {:?} at path {}", plugin_lib.err(), path));
-        }
+            Err(error) => {
+                return Err(format!("Error loading plugin: {:?}, at path {}", error, path));
+            }
+        };
+
+        check_abi_version(version).map_err(|error| format!("{} was {}", path, error))?;
+
+        let create = unsafe { library.get::<PluginCreateFunction>(PLUGIN_CREATE_SYMBOL) }
+            .map_err(|error| format!("Error loading plugin: {:?}, at path {}", error, path))?;
+
+        Ok(unsafe { create_plugins(*create, loaded.clone()) })
     }
 
     pub fn extension() -> String {
@@ -51,4 +98,4 @@ impl DylibLoader {
 
         return str.to_string();
     }
-}
\ No newline at end of file
+}
@@ -4,6 +4,8 @@ mod debug;
 #[cfg(not(target_family = "wasm"))]
 mod dylib_loader;
 mod entity;
+#[cfg(not(target_family = "wasm"))]
+mod plugin_watcher;
 #[cfg(debug_assertions)]
 mod message_queue;
 mod state;
@@ -15,6 +17,8 @@ pub use debug::*;
 #[cfg(not(target_family = "wasm"))]
 pub use dylib_loader::*;
 pub use entity::*;
+#[cfg(not(target_family = "wasm"))]
+pub use plugin_watcher::*;
 #[cfg(debug_assertions)]
 pub use message_queue::*;
 pub use state::*;
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use app_core::Reaction;
+use js_plugin::reactions::reactions_from_json;
+
+use crate::{push_command, Command, DylibLoader, Entity};
+
+// Looked for next to the executable and in the working directory
+const PLUGINS_DIRECTORY: &str = "plugins";
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+// A file counts as changed when any of these does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    length: u64,
+}
+
+// What a file in the plugins directories holds, going by its name. They are loaded in this order, so JSON
+// plugins can refer to the particles of the others and import procedures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PluginFile {
+    Library,
+    // Binary modules or the text format, WasmPlugin takes both
+    Wasm,
+    // name.procedures.json
+    Procedures,
+    Json,
+    // name.reactions.json
+    Reactions,
+}
+
+impl PluginFile {
+    fn from_path(path: &Path) -> Option<PluginFile> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let extension = path.extension()?.to_str()?.to_lowercase();
+
+        match extension.as_str() {
+            "json" if name.ends_with(".procedures.json") => Some(PluginFile::Procedures),
+            "json" if name.ends_with(".reactions.json") => Some(PluginFile::Reactions),
+            "json" => Some(PluginFile::Json),
+            "wasm" | "wat" => Some(PluginFile::Wasm),
+            extension if extension == DylibLoader::extension() => Some(PluginFile::Library),
+            _ => None,
+        }
+    }
+}
+
+// Loads JSON plugins, wasm plugins and native libraries from the plugins directories, and loads them again whenever
+// they change. Plugins with the same name replace the loaded ones in place, so the world is kept as it is
+pub struct PluginWatcher {
+    directories: Vec<PathBuf>,
+    loaded: HashMap<PathBuf, FileStamp>,
+    // Changed files wait until a scan finds them the same as the last one, so files that are still
+    // being written aren't loaded halfway
+    changed: HashMap<PathBuf, FileStamp>,
+    // What each reactions file added, by path so they are in the same order they were loaded
+    reactions: BTreeMap<PathBuf, Vec<Reaction>>,
+    last_scan: Instant,
+}
+
+impl PluginWatcher {
+    pub fn new() -> Self {
+        let mut directories = Vec::new();
+        if let Some(directory) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(|parent| parent.join(PLUGINS_DIRECTORY))) {
+            directories.push(directory);
+        }
+        if let Ok(directory) = std::env::current_dir().map(|directory| directory.join(PLUGINS_DIRECTORY)) {
+            if !directories.contains(&directory) {
+                directories.push(directory);
+            }
+        }
+
+        PluginWatcher {
+            directories,
+            loaded: HashMap::new(),
+            changed: HashMap::new(),
+            reactions: BTreeMap::new(),
+            last_scan: Instant::now(),
+        }
+    }
+
+    // In PluginFile order, then by name so the order doesn't change between runs
+    fn plugin_files(&self) -> Vec<(PathBuf, PluginFile, FileStamp)> {
+        let mut files = Vec::new();
+
+        for directory in self.directories.iter() {
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
+            };
+
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                let Some(kind) = PluginFile::from_path(&path) else {
+                    continue;
+                };
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+                let Ok(modified) = metadata.modified() else {
+                    continue;
+                };
+                files.push((path, kind, FileStamp { modified, length: metadata.len() }));
+            }
+        }
+
+        files.sort_by_key(|(path, kind, _)| (*kind, path.clone()));
+        files
+    }
+
+    fn scan(&mut self, wait_for_changes: bool) {
+        let files = self.plugin_files();
+        let mut changed_reactions = Vec::new();
+
+        for (path, kind, stamp) in files.iter() {
+            if self.loaded.get(path) == Some(stamp) {
+                self.changed.remove(path);
+                continue;
+            }
+
+            if wait_for_changes && self.changed.insert(path.clone(), *stamp) != Some(*stamp) {
+                continue;
+            }
+
+            self.changed.remove(path);
+            self.loaded.insert(path.clone(), *stamp);
+            match kind {
+                PluginFile::Reactions => changed_reactions.push(path.clone()),
+                _ => load(path, *kind),
+            }
+        }
+
+        // Deleted files are forgotten so they are loaded again if they come back. Their particles stay, but
+        // reactions don't belong to any particle so the ones from a deleted file are removed
+        let deleted = self
+            .loaded
+            .keys()
+            .filter(|path| !files.iter().any(|(file, _, _)| file == *path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in deleted {
+            println!("Plugin {} was deleted", path.display());
+            self.loaded.remove(&path);
+            self.changed.remove(&path);
+            if self.reactions.contains_key(&path) {
+                changed_reactions.push(path);
+            }
+        }
+
+        if !changed_reactions.is_empty() {
+            self.load_reactions(&changed_reactions);
+        }
+    }
+
+    // Reactions from files add up, so the ones a file added before are removed when it changes. They can
+    // only be removed by reactant, which also takes the ones added elsewhere for it. Other files have theirs added back
+    fn load_reactions(&mut self, paths: &[PathBuf]) {
+        let mut removed = HashSet::new();
+        for path in paths {
+            if let Some(reactions) = self.reactions.remove(path) {
+                removed.extend(reactions.into_iter().map(|reaction| reaction.reactant.to_lowercase()));
+            }
+
+            if !self.loaded.contains_key(path) {
+                continue;
+            }
+
+            println!("Loading plugin {}", path.display());
+            let reactions = fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|json| reactions_from_json(&json).map_err(|error| error.to_string()));
+            match reactions {
+                Ok(reactions) => {
+                    self.reactions.insert(path.clone(), reactions);
+                }
+                Err(error) => println!("Error loading reactions {}: {}", path.display(), error),
+            }
+        }
+
+        let added = self
+            .reactions
+            .iter()
+            .flat_map(|(path, reactions)| reactions.iter().map(move |reaction| (path, reaction)))
+            .filter(|(path, reaction)| paths.contains(path) || removed.contains(&reaction.reactant.to_lowercase()))
+            .map(|(_, reaction)| reaction.clone())
+            .collect::<Vec<_>>();
+
+        let removed = removed.into_iter().collect::<Vec<_>>();
+        push_command(Command::SimulationMethod(Box::new(move |simulation| {
+            for reactant in removed.iter() {
+                simulation.remove_reactions(reactant);
+            }
+            simulation.add_reactions(added.clone());
+        })));
+    }
+}
+
+fn load(path: &Path, kind: PluginFile) {
+    println!("Loading plugin {}", path.display());
+
+    let command = match kind {
+        PluginFile::Library => Ok(Command::NewNativePlugin(path.to_string_lossy().into_owned())),
+        PluginFile::Wasm => fs::read(path).map(Command::NewWasmPlugin),
+        PluginFile::Procedures => fs::read_to_string(path).map(Command::NewProcedures),
+        PluginFile::Json => fs::read_to_string(path).map(Command::NewPlugin),
+        PluginFile::Reactions => unreachable!("reactions files are loaded by PluginWatcher::load_reactions"),
+    };
+
+    match command {
+        Ok(command) => push_command(command),
+        Err(error) => println!("Unable to read plugin {}: {}", path.display(), error),
+    }
+}
+
+impl Entity for PluginWatcher {
+    // What's already there is loaded right away
+    fn init(&mut self) {
+        self.scan(false);
+    }
+
+    fn update(&mut self) {
+        if self.last_scan.elapsed() >= SCAN_INTERVAL {
+            self.last_scan = Instant::now();
+            self.scan(true);
+        }
+    }
+}
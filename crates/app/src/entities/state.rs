@@ -8,6 +8,8 @@ use crate::Debug;
 use crate::Entity;
 #[cfg(debug_assertions)]
 use crate::MessageQueue;
+#[cfg(not(target_family = "wasm"))]
+use crate::PluginWatcher;
 use crate::Universe;
 
 pub struct State {
@@ -23,6 +25,8 @@ impl State {
             Box::new(Universe::new()),
             #[cfg(not(target_family = "wasm"))]
             Box::new(Debug::new()),
+            #[cfg(not(target_family = "wasm"))]
+            Box::new(PluginWatcher::new()),
             #[cfg(debug_assertions)]
             Box::new(MessageQueue::new()),
             Box::new(Brush::new()),
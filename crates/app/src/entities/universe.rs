@@ -3,7 +3,16 @@ use egui_macroquad::macroquad::texture::Texture2D;
 #[cfg(not(target_family = "wasm"))]
 use egui_macroquad::{egui, macroquad::texture::Texture2D};
 
-use js_plugin::plugins::JSPlugin;
+use app_core::{ResizeAnchor, DEFAULT_CHUNK_SIZE};
+use js_plugin::plugins::{Backend, JSPlugin};
+use js_plugin::procedures::ProcedureLibrary;
+use js_plugin::reactions::reactions_from_json;
+use js_plugin::validation::Diagnostics;
+use wasm_plugin::plugins::WasmPlugin;
+#[cfg(not(target_family = "wasm"))]
+use js_plugin::validation::Severity;
+#[cfg(not(target_family = "wasm"))]
+use app_core::{Boundaries, BoundaryMode};
 
 use crate::*;
 
@@ -11,22 +20,42 @@ const SIMULATION_STARTING_WIDTH: usize = 150;
 const SIMULATION_STARTING_HEIGHT: usize = 150;
 
 
+// Screen rect (x, y, width, height) where the simulation is drawn, it's as big as possible
+// while keeping the world aspect ratio, and it's centered on the screen
+pub fn simulation_screen_rect(width: usize, height: usize) -> (f32, f32, f32, f32) {
+    let scale = (screen_width() / width as f32).min(screen_height() / height as f32);
+    let dest_width = width as f32 * scale;
+    let dest_height = height as f32 * scale;
+
+    (
+        (screen_width() - dest_width) / 2.0,
+        (screen_height() - dest_height) / 2.0,
+        dest_width,
+        dest_height,
+    )
+}
+
 #[cfg(debug_assertions)]
 fn mouse_pos_to_square(width: usize, height: usize) -> (isize, isize) {
     let (mouse_x, mouse_y) = mouse_position();
-    let min = screen_height().min(screen_width());
-    let x = (mouse_x - (screen_width() - min) / 2.0) / min * width as f32;
-    let y = (mouse_y - (screen_height() - min) / 2.0) / min * height as f32;
+    let (rect_x, rect_y, rect_width, rect_height) = simulation_screen_rect(width, height);
+    let x = (mouse_x - rect_x) / rect_width * width as f32;
+    let y = (mouse_y - rect_y) / rect_height * height as f32;
 
-    (x as isize, y as isize)
+    (x.floor() as isize, y.floor() as isize)
 }
 
 pub struct Universe {
     simulation: Simulation,
     texture: Texture2D,
     paused: bool,
+    // Procedures JSON plugins can import, every JSON plugin adds its own under its name
+    procedures: ProcedureLibrary,
     #[cfg(not(target_family = "wasm"))]
     native_plugin_loader: DylibLoader,
+    // Problems found in the last JSON plugin, shown until dismissed
+    #[cfg(not(target_family = "wasm"))]
+    plugin_diagnostics: Diagnostics,
 }
 
 impl Universe {
@@ -41,28 +70,120 @@ impl Universe {
             simulation: simulation,
             texture: texture,
             paused: false,
+            procedures: ProcedureLibrary::new(),
             #[cfg(not(target_family = "wasm"))]
             native_plugin_loader: DylibLoader::new(),
+            #[cfg(not(target_family = "wasm"))]
+            plugin_diagnostics: Diagnostics::default(),
         }
     }
 
-    pub fn resize(&mut self, size: u32) {
-        self.simulation.resize(size);
+    pub fn resize(&mut self, width: u32, height: u32, anchor: ResizeAnchor) {
+        self.simulation.resize(width as usize, height as usize, anchor);
         resize_texture(
             &mut self.texture,
-            size as u32,
-            size as u32,
+            self.simulation.get_width() as u32,
+            self.simulation.get_height() as u32,
             self.simulation.get_buffer(),
         );
     }
 
     fn select_particle(&mut self, id: usize) {
-        self.simulation.set_selected_plugin(id as u8);
+        self.simulation.set_selected_plugin(id as u16);
     }
 
     pub fn set_paused(&mut self, paused: bool) {
         self.paused = paused;
     }
+
+    // Names are checked against the particles loaded so far, a plugin with only warnings still loads
+    fn load_plugin(&mut self, json: &str) {
+        let particles = self
+            .simulation
+            .get_particle_definitions()
+            .iter()
+            .filter(|definition| !definition.removed)
+            .map(|definition| definition.name.clone())
+            .collect::<Vec<_>>();
+
+        let diagnostics = match JSPlugin::load(json, Backend::default(), Some(&particles), &self.procedures) {
+            Ok((plugin, diagnostics)) => {
+                let name = plugin.get_name().to_string();
+                let procedures = plugin.get_procedures().to_vec();
+                match self.simulation.add_plugin(Box::new(plugin)) {
+                    Ok(()) => {
+                        self.procedures.add(&name, procedures);
+                        push_command(Command::NewBackgroundColor(*self.simulation.get_particle_color(0).unwrap()));
+                    }
+                    Err(error) => println!("{}", error),
+                }
+                diagnostics
+            }
+            Err(diagnostics) => {
+                println!("Error loading plugin");
+                diagnostics
+            }
+        };
+
+        self.report_diagnostics(diagnostics);
+    }
+
+    // Plugins with the same name as loaded ones take their place and keep their ids, so the world stays as it
+    // is. The old library is unloaded once none of its plugins are left
+    #[cfg(not(target_family = "wasm"))]
+    fn load_native_plugin(&mut self, path: &str) {
+        match self.native_plugin_loader.load(path) {
+            Ok(plugins) => {
+                if let Err(error) = self.simulation.add_plugins(plugins) {
+                    println!("{}", error);
+                }
+                push_command(Command::NewBackgroundColor(*self.simulation.get_particle_color(0).unwrap()));
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    // Same on native and on the web, the module runs in wasmi either way
+    fn load_wasm_plugin(&mut self, bytes: &[u8]) {
+        match WasmPlugin::new(bytes) {
+            Ok(plugin) => {
+                if let Err(error) = self.simulation.add_plugin(Box::new(plugin)) {
+                    println!("{}", error);
+                }
+                push_command(Command::NewBackgroundColor(*self.simulation.get_particle_color(0).unwrap()));
+            }
+            Err(error) => println!("Error loading wasm plugin: {}", error),
+        }
+    }
+
+    // Plugins loaded after these can import them by the name in the file
+    fn load_procedures(&mut self, json: &str) {
+        let diagnostics = match self.procedures.add_from_json(json) {
+            Ok((name, diagnostics)) => {
+                println!("Loaded procedures {}", name);
+                diagnostics
+            }
+            Err(diagnostics) => {
+                println!("Error loading procedures");
+                diagnostics
+            }
+        };
+
+        self.report_diagnostics(diagnostics);
+    }
+
+    fn report_diagnostics(&mut self, diagnostics: Diagnostics) {
+        for diagnostic in diagnostics.iter() {
+            println!("{}", diagnostic);
+        }
+
+        #[cfg(target_family = "wasm")]
+        send_plugin_diagnostics(&diagnostics);
+        #[cfg(not(target_family = "wasm"))]
+        {
+            self.plugin_diagnostics = diagnostics;
+        }
+    }
 }
 
 impl Entity for Universe {
@@ -71,36 +192,45 @@ impl Entity for Universe {
         self.texture.set_filter(FilterMode::Nearest);
         #[cfg(not(target_family = "wasm"))]
         {
-            let plugin_path = std::env::current_exe()
-                .unwrap()
-                .parent()
-                .unwrap()
-                .join(format!("default_plugins.{}", DylibLoader::extension()));
-            let plugins = self
-                .native_plugin_loader
-                .load(plugin_path.to_str().unwrap())
-                .unwrap();
-            self.simulation.add_plugins(plugins);
+            // Built along with the app so they are linked in, only other libraries go through the C interface
+            if let Err(error) = self.simulation.add_plugins(default_plugins::plugin()) {
+                println!("{}", error);
+            }
         }
     }
 
     fn receive_command(&mut self, command: &Command) {
         match command {
-            Command::NewPlugin(json) => {
-                let plugin = JSPlugin::new(json);
-                match plugin {
-                    Ok(plugin) => {
-                        self.simulation.add_plugin(Box::new(plugin));
-                        push_command(Command::NewBackgroundColor(*self.simulation.get_particle_color(0).unwrap()));
-                    }
-                    Err(error) => {
-                        println!("Error loading plugin: {}", error);
-                    }
-                }
+            Command::NewPlugin(json) => self.load_plugin(json),
+            #[cfg(not(target_family = "wasm"))]
+            Command::NewNativePlugin(path) => self.load_native_plugin(path),
+            Command::NewWasmPlugin(bytes) => self.load_wasm_plugin(bytes),
+            Command::NewReactions(json) => match reactions_from_json(json) {
+                Ok(reactions) => self.simulation.add_reactions(reactions),
+                Err(error) => println!("Error loading reactions: {}", error),
+            },
+            Command::NewProcedures(json) => self.load_procedures(json),
+            Command::CanvasSize(width, height, anchor) => {
+                self.resize(*width, *height, *anchor);
             }
-            Command::CanvasSize(size) => {
-                self.resize(*size);
+            Command::SaveSnapshot(path) => {
+                if let Err(error) = self.simulation.save_snapshot_to_file(path) {
+                    println!("{}", error);
+                }
             }
+            Command::LoadSnapshot(path) => match self.simulation.load_snapshot_from_file(path) {
+                Ok(()) => {
+                    // The snapshot might have a different size than the current world
+                    let (width, height) = (self.simulation.get_width(), self.simulation.get_height());
+                    resize_texture(
+                        &mut self.texture,
+                        width as u32,
+                        height as u32,
+                        self.simulation.get_buffer(),
+                    );
+                }
+                Err(error) => println!("{}", error),
+            },
             Command::Clear => self.simulation.clear(),
             Command::Pause(is_paused) => {
                 self.set_paused(*is_paused);
@@ -121,6 +251,17 @@ impl Entity for Universe {
         if !self.paused {
             self.simulation.update();
         }
+
+        // Commands run before this, so anything the brush or the ui changed is also here
+        if let Some(dirty_rect) = self.simulation.take_dirty_rect() {
+            upload_rows(
+                &self.texture,
+                self.simulation.get_buffer(),
+                self.simulation.get_width(),
+                dirty_rect.min_y,
+                dirty_rect.height(),
+            );
+        }
     }
 
     #[cfg(not(target_family = "wasm"))]
@@ -141,6 +282,22 @@ impl Entity for Universe {
         {
             push_command(Command::RemovePlugin(self.simulation.get_selected_plugin()));
         }
+
+        if is_key_pressed(KeyCode::M) {
+            let multithreaded = !self.simulation.is_multithreaded();
+            self.simulation.set_multithreaded(multithreaded, DEFAULT_CHUNK_SIZE);
+        }
+
+        // Cycles every edge through walls, wrapping around and the void
+        if is_key_pressed(KeyCode::B) {
+            let mode = match self.simulation.get_boundaries().bottom {
+                BoundaryMode::Wall(_) => BoundaryMode::Wrap,
+                BoundaryMode::Wrap => BoundaryMode::Void,
+                BoundaryMode::Void => BoundaryMode::Wall(None),
+            };
+            add_dbg((&format!("Boundaries: {:?}", mode), 2.0));
+            self.simulation.set_boundaries(Boundaries::all(mode));
+        }
     }
 
     fn draw(&self) {
@@ -148,7 +305,11 @@ impl Entity for Universe {
         // Opactity to max, so when a particle alpha is 0 the color fades properly. First particle alpha shouldn't be something
         // that changes but given how blockly works we can't do much about it
         clear_background(Color::from_rgba(clear_color[0], clear_color[1], clear_color[2], 255));
-        draw_simulation(&self.texture, &self.simulation.get_buffer());
+        draw_simulation(
+            &self.texture,
+            self.simulation.get_width(),
+            self.simulation.get_height(),
+        );
         // draw_text(&format!("FPS: {}", get_fps()), 10.0, 30.0, 30.0, RED);
         
         #[cfg(debug_assertions)]
@@ -163,13 +324,31 @@ impl Entity for Universe {
 
             let particle = self.simulation.get_particles()[particle_y as usize][particle_x as usize];
 
-            // Draw at mouse pos, particle id, light value and extra value
+            // Declared properties are shown by name, particles that don't declare any show the raw extra value
+            let properties = &self.simulation.get_particle_definitions()[particle.id as usize].properties;
+            let properties = match properties.is_empty() {
+                true => format!("Extra: {}", particle.extra),
+                false => properties
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, property)| format!("{}: {}", property.name, particle.get_slot(slot)))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+
+            let temperature = self
+                .simulation
+                .get_temperature(particle_x as usize, particle_y as usize)
+                .unwrap_or_default();
+
+            // Draw at mouse pos, particle id, light value, temperature and properties
             draw_text(
                 &format!(
-                    "Particle: {}\nLight: {}\nExtra: {}\n Pos: {} {}",
+                    "Particle: {}\nLight: {}\nTemperature: {:.1}\n{}\n Pos: {} {}",
                     particle.id,
                     particle.opacity,
-                    particle.extra,
+                    temperature,
+                    properties,
                     particle_x,
                     particle_y
                 ),
@@ -190,6 +369,9 @@ impl Entity for Universe {
                 ui.label(format!("FPS: {}", get_fps()));
                 for i in 0..self.simulation.get_plugin_count() {
                     let plugin = &self.simulation.get_particle_definitions()[i];
+                    if plugin.removed {
+                        continue;
+                    }
 
                     let should_hightlight = i == self.simulation.get_selected_plugin() as usize;
                     let name = &plugin.name;
@@ -201,6 +383,29 @@ impl Entity for Universe {
                     }
                 }
             });
+
+        if self.plugin_diagnostics.is_empty() {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Plugin problems")
+            .open(&mut open)
+            .default_pos(egui::pos2(200.0, 32.0))
+            .show(egui_ctx, |ui| {
+                for diagnostic in self.plugin_diagnostics.iter() {
+                    let color = match diagnostic.severity {
+                        Severity::Error => egui::Color32::LIGHT_RED,
+                        Severity::Warning => egui::Color32::YELLOW,
+                    };
+                    ui.colored_label(color, &diagnostic.message);
+                    ui.small(&diagnostic.path);
+                }
+            });
+
+        if !open {
+            self.plugin_diagnostics = Diagnostics::default();
+        }
     }
 }
 
@@ -209,15 +414,16 @@ fn resize_texture(texture: &mut Texture2D, width: u32, height: u32, buffer: &[u8
     texture.texture.resize(ctx, width, height, Some(buffer));
 }
 
-fn draw_simulation(texture: &Texture2D, bytes: &[u8]) {
+// Only whole rows are uploaded, that way the part of the buffer we send is contiguous
+fn upload_rows(texture: &Texture2D, buffer: &[u8], width: usize, first_row: usize, rows: usize) {
     let raw = texture.raw_miniquad_texture_handle();
     let ctx = unsafe { get_internal_gl().quad_context };
-    raw.update(ctx, bytes);
-
-    let pos_x = (screen_width() / 2.0 - screen_height() / 2.0).max(0.);
-    let pos_y = (screen_height() / 2.0 - screen_width() / 2.0).max(0.);
+    let bytes = &buffer[first_row * width * 4..(first_row + rows) * width * 4];
+    raw.update_texture_part(ctx, 0, first_row as i32, width as i32, rows as i32, bytes);
+}
 
-    let dest_size = screen_height().min(screen_width());
+fn draw_simulation(texture: &Texture2D, width: usize, height: usize) {
+    let (pos_x, pos_y, dest_width, dest_height) = simulation_screen_rect(width, height);
 
     // Draw the texture
     draw_texture_ex(
@@ -226,7 +432,7 @@ fn draw_simulation(texture: &Texture2D, bytes: &[u8]) {
         pos_y,
         WHITE,
         DrawTextureParams {
-            dest_size: Some(vec2(dest_size, dest_size)),
+            dest_size: Some(vec2(dest_width, dest_height)),
             ..Default::default()
         },
     );
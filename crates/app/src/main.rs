@@ -14,8 +14,8 @@ use entities::*;
 
 #[cfg(target_family = "wasm")]
 mod wasm_bindings;
-// #[cfg(target_family = "wasm")]
-// use wasm_bindings::send_to_js;
+#[cfg(target_family = "wasm")]
+use wasm_bindings::send_plugin_diagnostics;
 
 const WINDOW_WIDTH: i32 = 800;
 const WINDOW_HEIGHT: i32 = 800;
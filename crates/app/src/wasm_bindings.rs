@@ -1,3 +1,6 @@
+use app_core::{Boundaries, ResizeAnchor};
+use js_plugin::validation::Diagnostics;
+
 use crate::*;
 
 #[no_mangle]
@@ -13,6 +16,48 @@ pub extern "C" fn receive_json_plugin(data: sapp_jsutils::JsObject) {
     push_command(Command::NewPlugin(buffer));
 }
 
+// The bytes of a .wasm module, or the text of a .wat one, see crates/wasm-plugin/src/abi.rs
+#[no_mangle]
+pub extern "C" fn receive_wasm_plugin(data: sapp_jsutils::JsObject) {
+
+    if data.is_nil() {
+        return;
+    }
+
+    let mut buffer = Vec::new();
+    data.to_byte_buffer(&mut buffer);
+
+    push_command(Command::NewWasmPlugin(buffer));
+}
+
+// Same as plugins, a JSON list of reactions that are added to the ones already loaded
+#[no_mangle]
+pub extern "C" fn receive_json_reactions(data: sapp_jsutils::JsObject) {
+
+    if data.is_nil() {
+        return;
+    }
+
+    let mut buffer = String::new();
+    data.to_string(&mut buffer);
+
+    push_command(Command::NewReactions(buffer));
+}
+
+// A procedures file, { "name": ..., "procedures": [...] }. JSON plugins loaded after it can import it
+#[no_mangle]
+pub extern "C" fn receive_json_procedures(data: sapp_jsutils::JsObject) {
+
+    if data.is_nil() {
+        return;
+    }
+
+    let mut buffer = String::new();
+    data.to_string(&mut buffer);
+
+    push_command(Command::NewProcedures(buffer));
+}
+
 #[no_mangle]
 pub extern "C" fn pause(data: sapp_jsutils::JsObject) {
 
@@ -39,6 +84,22 @@ pub extern "C" fn clear() {
     add_dbg(("Clear command received", 2.0));
 }
 
+// Accepts a single size for square worlds, or "width,height" and optionally an anchor: "width,height,bottom"
+fn parse_canvas_size(data: &str) -> Result<(u32, u32, ResizeAnchor), String> {
+    let parts = data.split(',').map(|part| part.trim()).collect::<Vec<_>>();
+    let parse_size = |size: &str| size.parse::<u32>().map_err(|_| format!("Invalid size: {}", size));
+
+    match parts.as_slice() {
+        [size] => {
+            let size = parse_size(size)?;
+            Ok((size, size, ResizeAnchor::Center))
+        }
+        [width, height] => Ok((parse_size(width)?, parse_size(height)?, ResizeAnchor::Center)),
+        [width, height, anchor] => Ok((parse_size(width)?, parse_size(height)?, anchor.parse()?)),
+        _ => Err(format!("Invalid canvas size: {}", data)),
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn resize_simulation(data: sapp_jsutils::JsObject) {
 
@@ -49,15 +110,13 @@ pub extern "C" fn resize_simulation(data: sapp_jsutils::JsObject) {
     let mut buffer = String::new();
     data.to_string(&mut buffer);
 
-    let size = buffer.parse();
-
-    match size {
-        Ok(size) => {
-            push_command(Command::CanvasSize(size));
-            add_dbg((&format!("Resize command received with data: {}", size), 5.0));
+    match parse_canvas_size(&buffer) {
+        Ok((width, height, anchor)) => {
+            push_command(Command::CanvasSize(width, height, anchor));
+            add_dbg((&format!("Resize command received with data: {}", buffer), 5.0));
         }
-        Err(_) => {
-            add_dbg((&format!("Resize command received with data: {}", buffer), 2.0));
+        Err(error) => {
+            add_dbg((&format!("Resize command received with invalid data: {}", error), 2.0));
         }
     }
 }
@@ -94,10 +153,16 @@ pub extern "C" fn remove_plugin(data: sapp_jsutils::JsObject) {
 
     let mut buffer = String::new();
     data.to_string(&mut buffer);
-    let buffer = buffer.parse().unwrap(); // I don't manage errors here because this shuouldnt fail at all
-
-    add_dbg((&format!("Remove plugin command received with data: {}", buffer), 5.0));
-    push_command(Command::RemovePlugin(buffer));
+    // Anything that doesn't fit in a particle id can't be one
+    match buffer.parse() {
+        Ok(id) => {
+            add_dbg((&format!("Remove plugin command received with data: {}", id), 5.0));
+            push_command(Command::RemovePlugin(id));
+        }
+        Err(_) => {
+            add_dbg(("Remove plugin command received with invalid data", 2.0));
+        }
+    }
 }
 
 #[no_mangle]
@@ -142,6 +207,31 @@ pub extern "C" fn set_brush_size(data: sapp_jsutils::JsObject) {
     }
 }
 
+// One boundary mode for every edge or four separated by commas (left, right, top, bottom).
+// Modes are wall, wall:<particle name>, wrap or void
+#[no_mangle]
+pub extern "C" fn set_boundaries(data: sapp_jsutils::JsObject) {
+
+    if data.is_nil() {
+        return;
+    }
+
+    let mut buffer = String::new();
+    data.to_string(&mut buffer);
+
+    match buffer.parse::<Boundaries>() {
+        Ok(boundaries) => {
+            push_command(Command::SimulationMethod(Box::new(move |simulation| {
+                simulation.set_boundaries(boundaries.clone())
+            })));
+            add_dbg((&format!("Set boundaries command received with data: {}", buffer), 5.0));
+        }
+        Err(error) => {
+            add_dbg((&format!("Set boundaries command received with invalid data: {}", error), 2.0));
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn step_simulation() {
     push_command(Command::StepSimulation);
@@ -149,12 +239,21 @@ pub extern "C" fn step_simulation() {
 }
 
 
+// The page has to check the major version, ids it sends and gets back went from 8 to 16 bit in 2.0.0
 #[no_mangle]
 pub fn pixel_creator_api_crate_version() -> u32
 {
-    (1 << 24) + (0 << 16) + 0
+    (2 << 24) + (0 << 16) + 0
+}
+
+// Implemented by the page, see web/index.html. It gets the diagnostics of every JSON plugin as a
+// JSON list of { severity, path, message }, empty if there weren't any problems
+extern "C" {
+    fn plugin_diagnostics(data: sapp_jsutils::JsObject);
 }
 
-// extern "C" {
-//     pub fn send_to_js(data: sapp_jsutils::JsObject);
-// }
\ No newline at end of file
+pub fn send_plugin_diagnostics(diagnostics: &Diagnostics) {
+    unsafe {
+        plugin_diagnostics(sapp_jsutils::JsObject::string(&diagnostics.to_json()));
+    }
+}
\ No newline at end of file
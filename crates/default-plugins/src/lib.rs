@@ -2,7 +2,7 @@ use app_core::api::Plugin;
 pub mod plugins;
 use crate::plugins::*;
 
-#[no_mangle]
+// Linked into the app and the headless runner, native plugin libraries go through export_plugins! instead
 pub fn plugin() -> Vec<Box<dyn Plugin>> {
     vec![Box::new(Sand::new()), Box::new(Water::new()), Box::new(Dust::new()), Box::new(Steam::new()), Box::new(Lava::new()), Box::new(Rock::new())]
 }
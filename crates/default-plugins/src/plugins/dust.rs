@@ -2,7 +2,7 @@ use app_core::*;
 use crate::*;
 
 pub struct Dust {
-    collision_targets: [u8; 1]
+    collision_targets: [u16; 1]
 }
 
 impl Dust {
@@ -22,8 +22,14 @@ impl Plugin for Dust {
 
     fn update(&self, api: &mut ParticleApi) {
         let random_horizontal = api.gen_range(-1, 1);
+        let side = api.random_sign();
         let down = -1;
 
-        let _ = swap_if_match(api, random_horizontal, down, &self.collision_targets);
+        // Drifts to a random cell below, but falls to any of the others if that one is taken,
+        // otherwise its chunk could fall asleep while it still has somewhere to go
+        let _ = swap_if_match(api, random_horizontal, down, &self.collision_targets) ||
+                swap_if_match(api, 0, down, &self.collision_targets) ||
+                swap_if_match(api, side, down, &self.collision_targets) ||
+                swap_if_match(api, -side, down, &self.collision_targets);
     }
 }
\ No newline at end of file
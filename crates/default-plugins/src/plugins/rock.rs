@@ -2,10 +2,14 @@ use crate::*;
 use app_core::*;
 
 pub struct Rock {
-    water_id: u8,
+    water_id: u16,
 }
 
 impl Rock {
+    // Rock only turns water into rock above this temperature
+    const QUENCH_TEMPERATURE: f32 = 300.0;
+    const QUENCH_COST: f32 = 100.0;
+
     pub fn new() -> Self {
         Rock { water_id: 0 }
     }
@@ -16,40 +20,35 @@ fn register(&mut self) -> PluginResult {
         PluginResult {
             name: String::from("Rock"),
             color: app_core::Color::from_rgba(123, 133, 145, 255),
+            thermal: ThermalProperties {
+                conductivity: 0.2,
+                ..Default::default()
+            },
+            // Only the strongest blasts break it
+            blast_resistance: 10.0,
             ..Default::default()
         }
     }
 
     fn update(&self, api: &mut ParticleApi) {
-        let cell = api.get_current();
+        let mut temperature = api.get_temperature(0, 0);
 
-        if cell.extra == 0 {
+        if temperature < Rock::QUENCH_TEMPERATURE {
             return;
         }
 
-        let mut cell = cell;
-
-        let water_id = self.water_id;
+        let cell = api.get_current();
 
         for neighbor in ParticleApi::NEIGHBORS {
-            // For each neighbour, is if type water, turn it into rock and set the extra to -1
-            // We do the same if the neighbour is the same type as the cell, thay way we can transfer the extra to the neighbour
-            let neighor_id = api.get(neighbor.x, neighbor.y).id;
-            if neighor_id== water_id || neighor_id == cell.id {
-                match cell.extra.checked_add_signed(-1) {
-                    Some(result) => {
-                        cell.extra = result;
-                    }
-                    None => {
-                        cell.extra = 0;
-                    }
-                }
+            // Hot rock turns the water it touches into more rock, each time it does it loses some heat.
+            // The new rock heats up from this one, so the effect spreads while there's heat left
+            if api.get_type(neighbor.x, neighbor.y) == self.water_id {
                 api.set(neighbor.x, neighbor.y, cell);
-                api.set(0, 0, cell);
+                temperature -= Rock::QUENCH_COST;
+                api.set_temperature(0, 0, temperature);
             }
-            
 
-            if cell.extra == 0 {
+            if temperature < Rock::QUENCH_TEMPERATURE {
                 return;
             }
         }
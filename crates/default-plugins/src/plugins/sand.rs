@@ -2,7 +2,7 @@ use app_core::*;
 use crate::*;
 
 pub struct Sand {
-    collision_targets: [u8; 2]
+    collision_targets: [u16; 2]
 }
 
 impl Sand {
@@ -17,11 +17,14 @@ impl Plugin for Sand {
             name: String::from("Sand"),
             color: app_core::Color::from_hex(0xFFFF00),
             color2: app_core::Color::from_hex(0xFFFF00),
+            motion: Some(MotionProperties::default()),
+            ..Default::default()
         }
     }
 
     fn update(&self, api: &mut ParticleApi) {
-        let random_horizontal = api.gen_range(-1, 1);
+        // Never 0, so a grain that doesn't move has nowhere to go and its chunk can fall asleep
+        let random_horizontal = api.random_sign();
         let down = -1;
 
         let _ = swap_if_match(api, 0, down, &self.collision_targets) || 
@@ -1,7 +1,7 @@
 use app_core::*;
 
 pub struct Steam {
-    rock_id: u8,
+    rock_id: u16,
 }
 
 impl Steam {
@@ -2,17 +2,19 @@ use app_core::*;
 use crate::*;
 
 pub struct Water{
-    collision_targets: [u8; 2]
+    collision_targets: [u16; 2],
+    // Where the direction property ended up, looked up once instead of by name on every cell
+    direction_slot: usize,
 }
 
 impl Water {
     pub fn new() -> Self {
-        Water  { collision_targets: [0,2] }
+        Water  { collision_targets: [0,2], direction_slot: 0 }
     }
 
-    pub fn swap_if_match(api: &mut ParticleApi, x: i32, y: i32, collision_targets: &[u8], cell: &mut Particle) -> bool {
-        if api.is_any_particle_at(x, y, collision_targets) {
-            cell.extra = if x == 1 { 1 } else { 2 }; // Storing the direction of the swap
+    pub fn swap_if_match(&self, api: &mut ParticleApi, x: i32, y: i32, cell: &mut Particle) -> bool {
+        if api.is_any_particle_at(x, y, &self.collision_targets) {
+            cell.set_slot(self.direction_slot, if x == 1 { 1 } else { 2 }); // Storing the direction of the swap
             return api.swap_using(x, y, *cell);
         }
         false
@@ -25,26 +27,52 @@ fn register(&mut self) -> PluginResult {
             name: String::from("Water"),
             color: app_core::Color::from_hex(0x00FFFF),
             color2: app_core::Color::from_hex(0x00FFFF),
+            // 0 doesn't have a direction, 1 flows right and 2 flows left
+            properties: vec![PropertyDefinition::new("direction", 0, 0, 2)],
+            thermal: ThermalProperties {
+                conductivity: 0.15,
+                boiling: Some(PhaseChange::new(100.0, "Steam")),
+                ..Default::default()
+            },
+            // Light and barely bouncing, so it splashes when something lands on it
+            motion: Some(MotionProperties {
+                gravity: 0.25,
+                bounce: 0.1,
+                ..Default::default()
+            }),
+            // Small blasts only splash it around
+            blast_resistance: 3.0,
+            ..Default::default()
         }
     }
 
     fn update(&self, api: &mut ParticleApi) {
-        let p = api.get_current();
-        
-        let dir_x = if p.extra == 0  {api.gen_range(-1, 1)} else { if p.extra == 1 {1} else {-1} };
+        let mut p = api.get_current();
+        let direction = p.get_slot(self.direction_slot);
+
+        // Never 0, both sides are always tried so water that doesn't move is really stuck
+        let dir_x = match direction { 0 => api.random_sign(), 1 => 1, _ => -1 };
         let dir_y = -1;
 
-        let mut p = p;
-        let _ = swap_if_match(api, 0, dir_y, &self.collision_targets) || 
+        let moved = swap_if_match(api, 0, dir_y, &self.collision_targets) || 
                 swap_if_match(api, dir_x, dir_y, &self.collision_targets) || 
                 swap_if_match(api, -dir_x, dir_y, &self.collision_targets) || 
-                Water::swap_if_match(api, dir_x, 0, &self.collision_targets, &mut p) || 
-                Water::swap_if_match(api, -dir_x, 0, &self.collision_targets, &mut p) ||
-                api.set(0, 0, api.new_particle(p.id)); // If no swap was made, I create a new particle to reset the extra field
-                // This is because you can't directly modify an existing particle, you have to set a new one in its place
+                self.swap_if_match(api, dir_x, 0, &mut p) || 
+                self.swap_if_match(api, -dir_x, 0, &mut p);
+
+        // Stuck, it picks a new direction the next time it can flow
+        if !moved && direction != 0 {
+            p.set_slot(self.direction_slot, 0);
+            api.set_relaxed(0, 0, p);
+        }
     }
 
     fn on_plugin_changed(&mut self, api: &ParticleApi) {
         self.collision_targets[1] = api.id_from_name("Dust");
+        self.direction_slot = api
+            .get_property_definitions(api.id_from_name("Water"))
+            .iter()
+            .position(|property| property.name == "direction")
+            .unwrap_or(0);
     }
 }
@@ -0,0 +1,328 @@
+// Headless runner, it drives app_core::Simulation without macroquad so it can run on build boxes and CI.
+// It loads the default plugins plus any JSON or wasm plugin, seeds a scene, steps N frames and writes the result.
+
+use std::fs;
+use std::io::Write;
+use std::process::ExitCode;
+
+use app_core::api::Simulation;
+use app_core::{Boundaries, Particle, DEFAULT_CHUNK_SIZE};
+use js_plugin::plugins::{Backend, JSPlugin};
+use js_plugin::procedures::ProcedureLibrary;
+use js_plugin::reactions::reactions_from_json;
+use wasm_plugin::plugins::WasmPlugin;
+
+const USAGE: &str = "Usage: sand-headless [options]
+
+Options:
+  --width <n>                    World width (default 150)
+  --height <n>                   World height (default 150)
+  --frames <n>                   Frames to simulate (default 100)
+  --seed <n>                     Seed for the random number generator, random if not set
+  --multithreaded [chunk size]   Update the world in parallel chunks (default chunk size 64)
+  --no-sleep                     Update every cell every frame, even in chunks where nothing changed
+  --boundaries <modes>           What is past the edges: wall, wall:<name>, wrap or void, either one
+                                 for all of them or four separated by commas (left,right,top,bottom)
+  --plugin <path>                Load a JSON plugin, or a wasm one if it ends in .wasm or .wat,
+                                 can be repeated
+  --reactions <path>             Load a JSON list of reactions, can be repeated
+  --procedures <path>            Load a JSON procedures file plugins can import, can be repeated
+  --fill <name> <x> <y> <w> <h>  Fill a rectangle with a particle, can be repeated
+  --load <path>                  Load a snapshot before seeding the scene
+  --save <path>                  Save a snapshot after the last frame
+  --out <prefix>                 Output prefix (default \"output\")
+  --help                         Print this message
+
+Writes <prefix>.grid with the particle ids and <prefix>.pam with the color buffer.";
+
+struct Fill {
+    name: String,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+struct Options {
+    width: usize,
+    height: usize,
+    frames: u32,
+    seed: Option<u64>,
+    chunk_size: Option<usize>,
+    no_sleep: bool,
+    boundaries: Boundaries,
+    plugins: Vec<String>,
+    reactions: Vec<String>,
+    procedures: Vec<String>,
+    fills: Vec<Fill>,
+    load: Option<String>,
+    save: Option<String>,
+    out: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 150,
+            height: 150,
+            frames: 100,
+            seed: None,
+            chunk_size: None,
+            no_sleep: false,
+            boundaries: Boundaries::default(),
+            plugins: Vec::new(),
+            reactions: Vec::new(),
+            procedures: Vec::new(),
+            fills: Vec::new(),
+            load: None,
+            save: None,
+            out: String::from("output"),
+        }
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+fn next_number<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = next_value(args, flag)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number for {}: {}", flag, value))
+}
+
+// Returns None when the user just asked for help
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut args = args.peekable();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => options.width = next_number(&mut args, "--width")?,
+            "--height" => options.height = next_number(&mut args, "--height")?,
+            "--frames" => options.frames = next_number(&mut args, "--frames")?,
+            "--seed" => options.seed = Some(next_number(&mut args, "--seed")?),
+            "--multithreaded" => {
+                // The chunk size is optional, only take the next argument if it's a number
+                let chunk_size = args.next_if(|value| value.parse::<usize>().is_ok());
+                options.chunk_size = Some(
+                    chunk_size.map_or(DEFAULT_CHUNK_SIZE, |value| value.parse().unwrap()),
+                );
+            }
+            "--no-sleep" => options.no_sleep = true,
+            "--boundaries" => options.boundaries = next_value(&mut args, "--boundaries")?.parse()?,
+            "--plugin" => options.plugins.push(next_value(&mut args, "--plugin")?),
+            "--reactions" => options.reactions.push(next_value(&mut args, "--reactions")?),
+            "--procedures" => options.procedures.push(next_value(&mut args, "--procedures")?),
+            "--fill" => options.fills.push(Fill {
+                name: next_value(&mut args, "--fill")?,
+                x: next_number(&mut args, "--fill")?,
+                y: next_number(&mut args, "--fill")?,
+                width: next_number(&mut args, "--fill")?,
+                height: next_number(&mut args, "--fill")?,
+            }),
+            "--load" => options.load = Some(next_value(&mut args, "--load")?),
+            "--save" => options.save = Some(next_value(&mut args, "--save")?),
+            "--out" => options.out = next_value(&mut args, "--out")?,
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if options.width == 0 || options.height == 0 {
+        return Err(String::from("World size can't be 0"));
+    }
+
+    Ok(Some(options))
+}
+
+// Procedure files are loaded first so any plugin can import them, plugins can import the ones loaded before them
+fn load_procedures(paths: &[String]) -> Result<ProcedureLibrary, String> {
+    let mut library = ProcedureLibrary::new();
+
+    for path in paths {
+        let json = fs::read_to_string(path)
+            .map_err(|error| format!("Unable to read procedures {}: {}", path, error))?;
+        let (_, warnings) = library
+            .add_from_json(&json)
+            .map_err(|errors| format!("Error loading procedures {}:\n{}", path, errors))?;
+        for warning in warnings.iter() {
+            eprintln!("{}: {}", path, warning);
+        }
+    }
+
+    Ok(library)
+}
+
+fn load_plugins(simulation: &mut Simulation, paths: &[String], library: &mut ProcedureLibrary) -> Result<(), String> {
+    // On wasm the default plugins are linked statically, here we do the same instead of going through the dylib loader
+    simulation.add_plugins(default_plugins::plugin())?;
+
+    for path in paths {
+        if path.ends_with(".wasm") || path.ends_with(".wat") {
+            simulation.add_plugin(Box::new(WasmPlugin::from_file(path)?))?;
+            continue;
+        }
+
+        let json = fs::read_to_string(path)
+            .map_err(|error| format!("Unable to read plugin {}: {}", path, error))?;
+        let particles = simulation
+            .get_particle_definitions()
+            .iter()
+            .filter(|definition| !definition.removed)
+            .map(|definition| definition.name.clone())
+            .collect::<Vec<_>>();
+        let (plugin, warnings) = JSPlugin::load(&json, Backend::default(), Some(&particles), library)
+            .map_err(|errors| format!("Error loading plugin {}:\n{}", path, errors))?;
+        for warning in warnings.iter() {
+            eprintln!("{}: {}", path, warning);
+        }
+        library.add(plugin.get_name(), plugin.get_procedures().to_vec());
+        simulation
+            .add_plugin(Box::new(plugin))
+            .map_err(|error| format!("Error loading plugin {}: {}", path, error))?;
+    }
+
+    // Same as the app does on init, otherwise empty cells keep a zeroed color
+    simulation.repaint();
+    Ok(())
+}
+
+fn load_reactions(simulation: &mut Simulation, paths: &[String]) -> Result<(), String> {
+    for path in paths {
+        let json = fs::read_to_string(path)
+            .map_err(|error| format!("Unable to read reactions {}: {}", path, error))?;
+        let reactions = reactions_from_json(&json)
+            .map_err(|error| format!("Error loading reactions {}: {}", path, error))?;
+        simulation.add_reactions(reactions);
+    }
+
+    Ok(())
+}
+
+fn seed_scene(simulation: &mut Simulation, fills: &[Fill]) -> Result<(), String> {
+    for fill in fills {
+        let id = simulation.get_particle_id(&fill.name)?;
+        let particle = Particle::from(id);
+
+        // Rectangles are clipped to the world, out of bounds cells are just ignored
+        for y in fill.y..fill.y.saturating_add(fill.height).min(simulation.get_height()) {
+            for x in fill.x..fill.x.saturating_add(fill.width).min(simulation.get_width()) {
+                simulation.set_particle(x, y, particle);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Plain text so it can be diffed easily, a header with the size and the particle names followed by one row per line
+fn write_grid(simulation: &Simulation, path: &str) -> Result<(), String> {
+    let mut output = String::new();
+    output += &format!("{} {}\n", simulation.get_width(), simulation.get_height());
+
+    // Removed plugins leave a gap in the ids
+    for id in 0..simulation.get_plugin_count() {
+        if let Ok(name) = simulation.get_particle_name(id) {
+            output += &format!("{} {}\n", id, name);
+        }
+    }
+
+    for row in simulation.get_particles().rows() {
+        let row = row
+            .iter()
+            .map(|particle| particle.id.to_string())
+            .collect::<Vec<_>>();
+        output += &row.join(" ");
+        output.push('\n');
+    }
+
+    fs::write(path, output).map_err(|error| format!("Unable to write {}: {}", path, error))
+}
+
+// PAM supports RGBA and most image tools can open it, so the color buffer is dumped as it is
+fn write_color_buffer(simulation: &Simulation, path: &str) -> Result<(), String> {
+    let write = || -> std::io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        write!(
+            file,
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            simulation.get_width(),
+            simulation.get_height()
+        )?;
+        file.write_all(simulation.get_buffer())
+    };
+
+    write().map_err(|error| format!("Unable to write {}: {}", path, error))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut simulation = match options.seed {
+        Some(seed) => Simulation::new_with_seed(options.width, options.height, seed),
+        None => Simulation::new(options.width, options.height),
+    };
+    let mut library = load_procedures(&options.procedures)?;
+    load_plugins(&mut simulation, &options.plugins, &mut library)?;
+    load_reactions(&mut simulation, &options.reactions)?;
+
+    if let Some(chunk_size) = options.chunk_size {
+        simulation.set_multithreaded(true, chunk_size);
+    }
+
+    simulation.set_sleeping_enabled(!options.no_sleep);
+    simulation.set_boundaries(options.boundaries.clone());
+
+    // The snapshot brings its own size, fills are applied on top of it
+    if let Some(path) = &options.load {
+        simulation.load_snapshot_from_file(path)?;
+    }
+
+    seed_scene(&mut simulation, &options.fills)?;
+
+    for _ in 0..options.frames {
+        simulation.update();
+    }
+
+    if let Some(path) = &options.save {
+        simulation.save_snapshot_to_file(path)?;
+    }
+
+    write_grid(&simulation, &format!("{}.grid", options.out))?;
+    write_color_buffer(&simulation, &format!("{}.pam", options.out))?;
+
+    println!(
+        "Simulated {} frames of a {}x{} world",
+        simulation.get_frame_count(),
+        simulation.get_width(),
+        simulation.get_height()
+    );
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// Compares running JSON plugins as closures against the bytecode interpreter, with a native
+// plugin as reference. Run with cargo bench -p js-plugin
+use std::time::{Duration, Instant};
+
+use app_core::Simulation;
+use js_plugin::plugins::{Backend, JSPlugin};
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 300;
+const FRAMES: usize = 200;
+const SEED: u64 = 7;
+
+const SAND: &str = include_str!("plugins/sand.json");
+const WATER: &str = include_str!("plugins/water.json");
+const KITCHEN_SINK: &str = include_str!("plugins/kitchen_sink.json");
+
+fn setup(json: Option<&str>, backend: Backend, particle: &str) -> Simulation {
+    let mut simulation = Simulation::new_with_seed(WIDTH, HEIGHT, SEED);
+    simulation.add_plugins(default_plugins::plugin()).unwrap();
+    if let Some(json) = json {
+        simulation.add_plugin(Box::new(JSPlugin::with_backend(json, backend).unwrap())).unwrap();
+    }
+    simulation.repaint();
+    simulation.set_sleeping_enabled(false);
+
+    // Most of the top half filled, with some water mixed in so the plugins have something to react to
+    let particle = simulation.get_particle_id(particle).unwrap();
+    let water = simulation.get_particle_id("Water").unwrap();
+    for y in HEIGHT / 2..HEIGHT - 10 {
+        for x in 10..WIDTH - 10 {
+            let id = if (x * 7 + y * 13) % 11 == 0 { water } else { particle };
+            simulation.set_selected_plugin(id);
+            simulation.set_selected_particle(x, y);
+        }
+    }
+
+    simulation
+}
+
+fn run(simulation: &mut Simulation) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        simulation.update();
+    }
+    start.elapsed()
+}
+
+// Particle equality only looks at the id, so every field is compared here
+fn assert_same_world(a: &Simulation, b: &Simulation, name: &str) {
+    let fields = |simulation: &Simulation| {
+        simulation
+            .get_particles()
+            .iter()
+            .map(|p| (p.id, p.opacity, p.hue_shift, p.color_fade, p.extra, p.extra2, p.extra3))
+            .collect::<Vec<_>>()
+    };
+
+    assert!(fields(a) == fields(b), "{}: closures and bytecode ended up in different worlds", name);
+    assert!(a.get_buffer() == b.get_buffer(), "{}: closures and bytecode painted different colors", name);
+}
+
+fn compare(name: &str, json: &str, particle: &str, native: Option<&str>) {
+    let mut closures = setup(Some(json), Backend::Closures, particle);
+    let mut bytecode = setup(Some(json), Backend::Bytecode, particle);
+    let mut native = native.map(|native| setup(None, Backend::default(), native));
+
+    let closures_time = run(&mut closures);
+    let bytecode_time = run(&mut bytecode);
+    assert_same_world(&closures, &bytecode, name);
+
+    let native_time = native.as_mut().map(run);
+
+    print!(
+        "{:<14} closures {:>8.2?}  bytecode {:>8.2?}  ({:.2}x)",
+        name,
+        closures_time,
+        bytecode_time,
+        closures_time.as_secs_f64() / bytecode_time.as_secs_f64()
+    );
+
+    if let Some(native_time) = native_time {
+        print!("  native {:>8.2?}", native_time);
+    }
+    println!();
+}
+
+fn main() {
+    println!("{} frames of a {}x{} world", FRAMES, WIDTH, HEIGHT);
+    compare("sand", SAND, "Block Sand", Some("Sand"));
+    compare("water", WATER, "Block Water", Some("Water"));
+    compare("kitchen sink", KITCHEN_SINK, "Kitchen Sink", None);
+}
@@ -14,8 +14,15 @@ pub enum Actions
     If (Vec<Option<(Conditions, Vec<Actions>)>>), 
     IncreaseParticlePropierty { propierty: ParticlePropierties, number: Number,  direction: Direction },
     SetParticlePropierty { propierty: ParticlePropierties, number: Number, direction: Direction },
+    IncreaseTemperature { number: Number, direction: Direction },
+    SetTemperature { number: Number, direction: Direction },
+    AddImpulse { direction: Direction, impulse: Direction }, // Impulse in cells per frame, only particles with motion take it
+    Explode { direction: Direction, radius: Number, strength: Number, spawn: Option<Number>, chance: Option<Number> }, // Chance of spawning is a percentage
     Repeat { number: Number, block: Option<Vec<Actions>> },
     EveryXFrames { number: Number, block: Option<Vec<Actions>> },
+    SetVariable { name: String, value: VariableValue }, // Locals last for one update, unless the plugin declares it as a global
+    IncreaseVariable { name: String, number: Number },
+    Call { name: String, arguments: Vec<Argument> }, // Replaced by the procedure's blocks when the plugin loads
     None
 }
 
@@ -23,7 +30,7 @@ impl Actions {
     pub fn to_func(
         &self,
         api: &ParticleApi,
-    ) -> Box<dyn Fn(&JSPlugin, &mut ParticleApi) -> ()> {
+    ) -> ActionFunc {
         let block = self.clone();
         match block {
             Actions::Swap { direction } => match direction {
@@ -58,8 +65,7 @@ impl Actions {
             Actions::ChangeInto { direction, r#type } => {
                 match r#type {
                     Number::FromID(r#type) => {
-                        let r#type = r#type as u8;
-                        if r#type >= api.get_particle_count() {
+                        if !api.has_particle_type(r#type) {
                             return Box::new(|_, _| ());
                         }
                         let particle_id = r#type;
@@ -85,9 +91,9 @@ impl Actions {
                                 let direction = direction;
                                 Box::new(move |_, api| {
                                     let direction = api.get_transformation().transform(&direction);
-                                    let particle_id = r#type.to_number(api) as u8;
+                                    let particle_id = r#type.to_number(api) as u16;
 
-                                    if particle_id >= api.get_particle_count() {
+                                    if !api.has_particle_type(particle_id) {
                                         return;
                                     }
 
@@ -98,9 +104,9 @@ impl Actions {
                                 let direction = direction.get_direction(api);
                                 let direction = api.get_transformation().transform(&direction);
 
-                                let particle_id = r#type.to_number(api) as u8;
+                                let particle_id = r#type.to_number(api) as u16;
 
-                                    if particle_id >= api.get_particle_count() {
+                                    if !api.has_particle_type(particle_id) {
                                         return;
                                     }
                                 api.set(direction[0], direction[1], api.new_particle(particle_id));
@@ -337,6 +343,18 @@ impl Actions {
                         particle.color_fade = particle.color_fade.saturating_add_signed(number).min(100); // This is to avoid overflow
                         api.set_relaxed(direction[0], direction[1], particle);
                     }),
+                    // The range comes from the property declaration, particles without it are left alone
+                    ParticlePropierties::Named(name) => Box::new(move |_, api| {
+                        let direction = direction.get_direction(api);
+                        let direction = api.get_transformation().transform(&direction);
+                        let number = number.to_number(api);
+                        let mut particle = api.get(direction[0], direction[1]);
+                        let Some(value) = api.get_property(&particle, &name) else {
+                            return;
+                        };
+                        api.set_property(&mut particle, &name, value as i32 + number);
+                        api.set_relaxed(direction[0], direction[1], particle);
+                    }),
                 }
             }
             Actions::SetParticlePropierty {
@@ -392,7 +410,43 @@ impl Actions {
                     particle.color_fade = number;
                     api.set_relaxed(direction[0], direction[1], particle);
                 }),
+                ParticlePropierties::Named(name) => Box::new(move |_, api| {
+                    let direction = direction.get_direction(api);
+                    let direction = api.get_transformation().transform(&direction);
+                    let number = number.to_number(api);
+                    let mut particle = api.get(direction[0], direction[1]);
+                    if api.set_property(&mut particle, &name, number) {
+                        api.set_relaxed(direction[0], direction[1], particle);
+                    }
+                }),
             },
+            Actions::IncreaseTemperature { number, direction } => Box::new(move |_, api| {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let temperature = api.get_temperature(direction[0], direction[1]) + number.to_number(api) as f32;
+                api.set_temperature(direction[0], direction[1], temperature);
+            }),
+            Actions::SetTemperature { number, direction } => Box::new(move |_, api| {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                api.set_temperature(direction[0], direction[1], number.to_number(api) as f32);
+            }),
+            Actions::AddImpulse { direction, impulse } => Box::new(move |_, api| {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let impulse = impulse.get_direction(api);
+                let impulse = api.get_transformation().transform(&impulse);
+                api.add_impulse(direction[0], direction[1], [impulse[0] as f32, impulse[1] as f32]);
+            }),
+            Actions::Explode { direction, radius, strength, spawn, chance } => Box::new(move |_, api| {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let radius = radius.to_number(api);
+                let strength = strength.to_number(api);
+                let spawn = spawn.as_ref().map_or(Particle::EMPTY.id as i32, |spawn| spawn.to_number(api));
+                let chance = chance.as_ref().map_or(100, |chance| chance.to_number(api));
+                api.explode(direction[0], direction[1], explosion(radius, strength, spawn, chance));
+            }),
             Actions::Repeat { number, block } => {
                 if block.is_none() {
                     return Box::new(|_, _| ());
@@ -435,11 +489,35 @@ impl Actions {
                     if api.get_frame_count() % frames == 0 {
                         func.iter().for_each(|func| func(plugin, api));
                     }
-                    
-                    api.set(0, 0, api.get_current());
+
+                    // Nothing might change around until it's time again, the chunk can't fall asleep meanwhile
+                    api.keep_awake();
                 })
             }
-            Actions::None => Box::new(|_, _| ()),
+            Actions::SetVariable { name, value } => match value {
+                VariableValue::Number(number) => Box::new(move |_, api| set_number(&name, number.to_number(api))),
+                VariableValue::Boolean(condition) => {
+                    let func = condition.to_func(api);
+                    Box::new(move |plugin, api| set_number(&name, func(plugin, api) as i32))
+                }
+                VariableValue::Direction(direction) => {
+                    Box::new(move |_, api| set_direction(&name, direction.get_direction(api)))
+                }
+            },
+            Actions::IncreaseVariable { name, number } => {
+                Box::new(move |_, api| increase_number(&name, number.to_number(api)))
+            }
+            // Calls are expanded when the plugin loads, there shouldn't be any left
+            Actions::Call { .. } | Actions::None => Box::new(|_, _| ()),
         }
     }
 }
+
+// Spawning empty or something that isn't a particle id means destroyed cells are just left empty
+pub(crate) fn explosion(radius: i32, strength: i32, spawn: i32, chance: i32) -> Explosion {
+    let explosion = Explosion::new(clamp_reach(radius), strength as f32);
+    match u16::try_from(spawn) {
+        Ok(spawn) if spawn != Particle::EMPTY.id => explosion.with_spawn(spawn, chance.clamp(0, 100) as f32 / 100.0),
+        _ => explosion,
+    }
+}
@@ -16,12 +16,15 @@ pub enum Conditions {
     CompareBiggerThan { block1: Number, block2: Number }, // Compares two blocks
     CompareLessThan { block1: Number, block2: Number }, // Compares two blocks
     Boolean { value: bool }, // Returns a boolean value
+    GetVariable { name: String }, // True if the variable isn't 0
+    RayHits { direction: Direction, distance: Number, types: Vec<Number> }, // If the first cell that isn't empty along direction is of type X
+    IsNear { radius: Number, types: Vec<Number> }, // If any cell of type X is within the radius
 }
 
 // Implement from Block into Function
 impl Conditions {
     #[allow(unused)]
-    pub fn to_func(&self, api: &ParticleApi) -> Box<dyn Fn(&JSPlugin, &mut ParticleApi) -> bool> {
+    pub fn to_func(&self, api: &ParticleApi) -> ConditionFunc {
         let block = self.clone();
         match block {
             Conditions::CheckTypesInDirection { direction, types } => {
@@ -165,6 +168,7 @@ impl Conditions {
                 number1 < number2
             }),
             Conditions::Boolean { value } => Box::new(move |plugin, api| value),
+            Conditions::GetVariable { name } => Box::new(move |_, _| get_number(&name) != 0),
             Conditions::IsEmpty { direction } => match direction {
                 Direction::Constant(direction) => {
                     let direction = direction;
@@ -185,16 +189,12 @@ impl Conditions {
                     if number <= 1 {
                         return Box::new(move |_, _| true);
                     } else {
-                        Box::new(move |_, api| {
-                            let random_number = api.gen_range(1, number);
-                            random_number == 1
-                        })
+                        Box::new(move |_, api| one_in(api, number))
                     }
                 }
                 number => Box::new(move |plugin, api| {
                     let chance = number.to_number(api);
-                    let random_number = api.gen_range(1, chance.max(1));
-                    random_number == 1
+                    one_in(api, chance)
                 }),
             },
             Conditions::CompareBooleans { block1, block2 } => {
@@ -203,6 +203,29 @@ impl Conditions {
 
                 Box::new(move |plugin, api| func1(plugin, api) == func2(plugin, api))
             }
+            Conditions::RayHits { direction, distance, types } => Box::new(move |_, api| {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let distance = clamp_reach(distance.to_number(api));
+                let ids = particle_ids(&types, api);
+                api.ray_cast(direction[0], direction[1], distance).is_some_and(|steps| {
+                    ids.contains(&api.get_type(direction[0] * steps, direction[1] * steps))
+                })
+            }),
+            Conditions::IsNear { radius, types } => Box::new(move |_, api| {
+                let radius = clamp_reach(radius.to_number(api));
+                let ids = particle_ids(&types, api);
+                api.find_nearest(radius, |particle| ids.contains(&particle.id)).is_some()
+            }),
         }
     }
 }
+
+// A miss keeps the chunk awake, the particle is waiting for a roll and not for something around it to change
+pub(crate) fn one_in(api: &mut ParticleApi, chance: i32) -> bool {
+    let hit = api.gen_range(1, chance.max(1)) == 1;
+    if !hit {
+        api.keep_awake();
+    }
+    hit
+}
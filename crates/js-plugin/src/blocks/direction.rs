@@ -6,6 +6,11 @@ pub enum Direction {
     Constant([i32; 2]),
     Random,
     Operation(MathOperations, Box<Direction>, Box<Direction>),
+    // Written as { "getVariable": name }
+    GetVariable {
+        #[serde(rename = "getVariable")]
+        name: String,
+    },
 }
 
 impl Direction {
@@ -13,6 +18,7 @@ impl Direction {
         match self {
             Direction::Constant(direction) => *direction,
             Direction::Random => [api.gen_range(-1, 1), api.gen_range(-1, 1)],
+            Direction::GetVariable { name } => get_direction(name),
             Direction::Operation(op, dir1, dir2) => {
                 let dir1 = dir1.get_direction(api);
                 let dir2 = dir2.get_direction(api);
@@ -4,6 +4,7 @@ pub mod numbers;
 pub mod transformations;
 pub mod utiliies;
 pub mod direction;
+pub mod variables;
 
 pub(crate) use actions::*;
 pub(crate) use conditions::*;
@@ -11,10 +12,16 @@ pub(crate) use direction::*;
 pub(crate) use numbers::*;
 pub(crate) use transformations::*;
 pub(crate) use utiliies::*;
+pub(crate) use variables::*;
 
-use app_core::{ParticleApi, Transformation};
+use app_core::{Explosion, Particle, ParticleApi, Transformation, CHUNK_HALO};
 use serde::{Deserialize, Serialize};
 
 use crate::plugins::JSPlugin;
+use crate::procedures::Argument;
 type Condition = Box<Conditions>;
 
+// Send + Sync as plugins can be updated from several threads
+pub type ActionFunc = Box<dyn Fn(&JSPlugin, &mut ParticleApi) + Send + Sync>;
+pub type ConditionFunc = Box<dyn Fn(&JSPlugin, &mut ParticleApi) -> bool + Send + Sync>;
+
@@ -11,18 +11,25 @@ pub enum Number {
     Extra(Direction),
     Extra2(Direction),
     Extra3(Direction),
+    Property(String, Direction), // Property declared by the particle at direction, 0 if it doesn't have it
+    Temperature(Direction), // Rounded to whole degrees
+    GetVariable(String), // Local or global, booleans are 0 or 1
+    RayCast(Direction, Box<Number>), // Steps until something that isn't empty, 0 if nothing is that close
+    CountInRadius(Box<Number>, Vec<Number>), // Cells of the types around, the current one doesn't count
+    CountInArea(Direction, Direction, Vec<Number>), // Cells of the types between two corners
+    DistanceToNearest(Box<Number>, Vec<Number>), // Rounded, 0 if there is none in the radius
     MathOperation(MathOperations, Box<Number>, Box<Number>),
     Constant(i32),
 
     // Particle Types
-    FromID(u8), // This shouldn't be used at all, it's more an internal block
+    FromID(u16), // This shouldn't be used at all, it's more an internal block
     FromName(String),
     TypeOf(Direction),
 }
 
 // Enum that holds values that cannot be precomputed
 impl Number {
-    pub fn to_particle_id(&self, api: &ParticleApi) -> u8 {
+    pub fn to_particle_id(&self, api: &ParticleApi) -> u16 {
         match self {
             Number::FromID(id) => *id,
             Number::FromName(name) => api.id_from_name(name),
@@ -31,7 +38,7 @@ impl Number {
                 let direction = api.get_transformation().transform(&direction);
                 api.get_type(direction[0], direction[1])
             }
-            _ => self.to_number(api) as u8,
+            _ => self.to_number(api) as u16,
         }
     }
 
@@ -96,7 +103,60 @@ impl Number {
                 let direction = api.get_transformation().transform(&direction);
                 api.get(direction[0], direction[1]).extra3 as i32
             }
+            Number::Property(name, direction) => {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let particle = api.get(direction[0], direction[1]);
+                api.get_property(&particle, name).unwrap_or(0) as i32
+            }
+            Number::Temperature(direction) => {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                api.get_temperature(direction[0], direction[1]).round() as i32
+            }
+            Number::GetVariable(name) => get_number(name),
+            Number::RayCast(direction, distance) => {
+                let direction = direction.get_direction(api);
+                let direction = api.get_transformation().transform(&direction);
+                let distance = clamp_reach(distance.to_number(api));
+                api.ray_cast(direction[0], direction[1], distance).unwrap_or(0)
+            }
+            Number::CountInRadius(radius, types) => {
+                let radius = clamp_reach(radius.to_number(api));
+                let ids = particle_ids(types, api);
+                api.count_in_radius(radius, |particle| ids.contains(&particle.id))
+            }
+            Number::CountInArea(corner1, corner2, types) => {
+                let corner1 = corner1.get_direction(api);
+                let corner1 = api.get_transformation().transform(&corner1);
+                let corner2 = corner2.get_direction(api);
+                let corner2 = api.get_transformation().transform(&corner2);
+                let ids = particle_ids(types, api);
+                api.count_in_area(corner1[0], corner1[1], corner2[0], corner2[1], |particle| {
+                    ids.contains(&particle.id)
+                })
+            }
+            Number::DistanceToNearest(radius, types) => {
+                let radius = clamp_reach(radius.to_number(api));
+                let ids = particle_ids(types, api);
+                api.find_nearest(radius, |particle| ids.contains(&particle.id))
+                    .map_or(0, |[x, y]| rounded_distance(x, y))
+            }
             _ => self.to_particle_id(api) as i32,
         }
     }
 }
+
+// With several threads the world past the chunk halo isn't there, so queries never look further
+pub(crate) fn clamp_reach(distance: i32) -> i32 {
+    distance.clamp(0, CHUNK_HALO as i32)
+}
+
+pub(crate) fn rounded_distance(x: i32, y: i32) -> i32 {
+    ((x * x + y * y) as f32).sqrt().round() as i32
+}
+
+// Every type is evaluated once and in order, the bytecode does the same so random numbers match
+pub(crate) fn particle_ids(types: &[Number], api: &ParticleApi) -> Vec<u16> {
+    types.iter().map(|particle_type| particle_type.to_particle_id(api)).collect()
+}
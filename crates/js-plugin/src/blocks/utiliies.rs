@@ -11,9 +11,21 @@ pub enum ParticlePropierties {
     Extra,
     Extra2,
     Extra3,
+    // Anything else is the name of a property the plugin declared
+    #[serde(untagged)]
+    Named(String),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+impl ParticlePropierties {
+    // Names that already mean something, declared properties can't use them
+    pub fn is_builtin(name: &str) -> bool {
+        ["opacity", "hueShift", "colorFade", "extra", "extra2", "extra3"]
+            .iter()
+            .any(|builtin| builtin.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum MathOperations {
     Addition,
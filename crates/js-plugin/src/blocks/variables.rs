@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+use super::*;
+
+// What a SetVariable block stores. The type comes from the block plugged in, a variable keeps
+// the type of the first block that sets it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum VariableValue {
+    Number(Number),
+    Boolean(Conditions),
+    Direction(Direction),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableType {
+    Number,
+    Boolean,
+    Direction,
+}
+
+impl VariableValue {
+    pub fn get_type(&self) -> VariableType {
+        match self {
+            VariableValue::Number(_) => VariableType::Number,
+            VariableValue::Boolean(_) => VariableType::Boolean,
+            VariableValue::Direction(_) => VariableType::Direction,
+        }
+    }
+}
+
+impl std::fmt::Display for VariableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableType::Number => write!(f, "number"),
+            VariableType::Boolean => write!(f, "boolean"),
+            VariableType::Direction => write!(f, "direction"),
+        }
+    }
+}
+
+/// Counters declared by the plugin. They are shared by every particle of the plugin and kept
+/// between frames, so they are only deterministic when the world is updated on a single thread.
+#[derive(Debug, Default)]
+pub struct Globals {
+    names: Vec<String>,
+    values: Vec<AtomicI32>,
+}
+
+impl Globals {
+    pub fn new<'a>(globals: impl IntoIterator<Item = (&'a str, i32)>) -> Globals {
+        let (names, values) = globals
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), AtomicI32::new(value)))
+            .unzip();
+        Globals { names, values }
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|other| other == name)
+    }
+
+    pub fn get(&self, index: usize) -> i32 {
+        self.values[index].load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, index: usize, value: i32) {
+        self.values[index].store(value, Ordering::Relaxed);
+    }
+
+    // A single atomic add, so counting from several threads doesn't lose anything
+    pub fn increase(&self, index: usize, value: i32) {
+        self.values[index].fetch_add(value, Ordering::Relaxed);
+    }
+}
+
+// The bytecode keeps locals on its own stack, closures don't have anywhere to put them so
+// they live here for the duration of one update
+#[derive(Debug, Clone, Copy)]
+enum LocalValue {
+    Number(i32),
+    Direction([i32; 2]),
+}
+
+#[derive(Default)]
+struct Scope {
+    locals: HashMap<String, LocalValue>,
+    globals: Option<Arc<Globals>>,
+}
+
+thread_local! {
+    static SCOPE: RefCell<Scope> = RefCell::new(Scope::default());
+}
+
+pub(crate) fn enter_scope(globals: &Arc<Globals>) {
+    SCOPE.with(|scope| {
+        let mut scope = scope.borrow_mut();
+        scope.locals.clear();
+        scope.globals = Some(globals.clone());
+    });
+}
+
+pub(crate) fn leave_scope() {
+    SCOPE.with(|scope| scope.borrow_mut().globals = None);
+}
+
+fn with_global<T>(name: &str, func: impl FnOnce(&Globals, usize) -> T) -> Option<T> {
+    SCOPE.with(|scope| {
+        let scope = scope.borrow();
+        let globals = scope.globals.as_ref()?;
+        globals.index(name).map(|index| func(globals, index))
+    })
+}
+
+// Variables that were never set are 0, false or [0, 0]
+pub(crate) fn get_number(name: &str) -> i32 {
+    if let Some(value) = with_global(name, |globals, index| globals.get(index)) {
+        return value;
+    }
+
+    SCOPE.with(|scope| match scope.borrow().locals.get(name) {
+        Some(LocalValue::Number(value)) => *value,
+        Some(LocalValue::Direction(direction)) => direction[0],
+        None => 0,
+    })
+}
+
+pub(crate) fn get_direction(name: &str) -> [i32; 2] {
+    SCOPE.with(|scope| match scope.borrow().locals.get(name) {
+        Some(LocalValue::Direction(direction)) => *direction,
+        Some(LocalValue::Number(value)) => [*value, 0],
+        None => [0, 0],
+    })
+}
+
+pub(crate) fn set_number(name: &str, value: i32) {
+    if with_global(name, |globals, index| globals.set(index, value)).is_some() {
+        return;
+    }
+
+    SCOPE.with(|scope| scope.borrow_mut().locals.insert(name.to_string(), LocalValue::Number(value)));
+}
+
+pub(crate) fn set_direction(name: &str, direction: [i32; 2]) {
+    SCOPE.with(|scope| scope.borrow_mut().locals.insert(name.to_string(), LocalValue::Direction(direction)));
+}
+
+pub(crate) fn increase_number(name: &str, value: i32) {
+    if with_global(name, |globals, index| globals.increase(index, value)).is_some() {
+        return;
+    }
+
+    set_number(name, get_number(name).wrapping_add(value));
+}
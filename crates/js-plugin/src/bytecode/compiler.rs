@@ -0,0 +1,1077 @@
+use app_core::{Particle, ParticleApi};
+
+use super::*;
+use crate::blocks::{Actions, Conditions, Direction, Globals, Number, ParticlePropierties, VariableValue};
+
+/// Lowers the block tree of a plugin into a Program. Anything that doesn't depend on the world,
+/// like constants, math between them or particle names, is solved here instead of on every cell.
+pub struct Compiler<'a> {
+    api: &'a ParticleApi,
+    globals: &'a Globals,
+    // Names of the locals, the slots of each one start at twice its index
+    locals: Vec<String>,
+    program: Program,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn compile(actions: &[Actions], globals: &Globals, api: &ParticleApi) -> Program {
+        let mut compiler = Compiler {
+            api,
+            globals,
+            locals: Vec::new(),
+            program: Program::default(),
+        };
+        compiler.actions(actions);
+        compiler.compute_limits();
+        compiler.program.locals = compiler.locals.len() * 2;
+        compiler.program
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.program.ops.push(op);
+        self.program.ops.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.program.ops.len() as u32
+    }
+
+    // Points a jump emitted before its target was known to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        self.program.ops[at] = match self.program.ops[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfTrue(_) => Op::JumpIfTrue(target),
+            Op::JumpUnlessEmpty(dir, _) => Op::JumpUnlessEmpty(dir, target),
+            Op::JumpUnlessTypeIn(dir, set, _) => Op::JumpUnlessTypeIn(dir, set, target),
+            Op::SwapIfEmpty(direction, _) => Op::SwapIfEmpty(direction, target),
+            Op::LoopInit(_) => Op::LoopInit(target),
+            op => unreachable!("{:?} doesn't jump", op),
+        };
+    }
+
+    // Fused with the condition just emitted when there's an instruction for both
+    fn jump_if_false(&mut self) -> usize {
+        let fused = match self.program.ops.last() {
+            Some(Op::IsEmpty(dir)) => Some(Op::JumpUnlessEmpty(*dir, 0)),
+            Some(Op::TypeIn(dir, set)) => Some(Op::JumpUnlessTypeIn(*dir, *set, 0)),
+            _ => None,
+        };
+
+        match fused {
+            Some(op) => {
+                let at = self.program.ops.len() - 1;
+                self.program.ops[at] = op;
+                at
+            }
+            None => self.emit(Op::JumpIfFalse(0)),
+        }
+    }
+
+    // Turns the branch just compiled into a SwapIfEmpty if it only swaps into the cell it checked
+    fn swap_if_empty(&mut self, next: usize) -> Option<usize> {
+        let direction = match self.program.ops[next..] {
+            [Op::JumpUnlessEmpty(Dir::Constant(checked), _), Op::Swap(Dir::Constant(swapped))]
+                if checked == swapped => checked,
+            _ => return None,
+        };
+
+        self.program.ops.truncate(next);
+        Some(self.emit(Op::SwapIfEmpty(direction, 0)))
+    }
+
+    fn name_index(&mut self, name: &str) -> u16 {
+        match self.program.names.iter().position(|other| other == name) {
+            Some(index) => index as u16,
+            None => {
+                self.program.names.push(name.to_string());
+                (self.program.names.len() - 1) as u16
+            }
+        }
+    }
+
+    fn local_slot(&mut self, name: &str) -> u16 {
+        let index = match self.locals.iter().position(|other| other == name) {
+            Some(index) => index,
+            None => {
+                self.locals.push(name.to_string());
+                self.locals.len() - 1
+            }
+        };
+        (index * 2) as u16
+    }
+
+    fn global_index(&self, name: &str) -> Option<u16> {
+        self.globals.index(name).map(|index| index as u16)
+    }
+
+    fn type_set_index(&mut self, ids: &[u16]) -> u16 {
+        let set = type_set(ids);
+        match self.program.type_sets.iter().position(|other| *other == set) {
+            Some(index) => index as u16,
+            None => {
+                self.program.type_sets.push(set);
+                (self.program.type_sets.len() - 1) as u16
+            }
+        }
+    }
+
+    // Constant folding. Only values without side effects are folded, anything random
+    // has to stay so the random number generator is called the same times as before
+
+    fn fold_number(&self, number: &Number) -> Option<i32> {
+        match number {
+            Number::Constant(constant) => Some(*constant),
+            Number::FromID(id) => Some(*id as i32),
+            // Plugins are compiled again whenever ids change, so names can be solved now
+            Number::FromName(name) => Some(self.api.id_from_name(name) as i32),
+            Number::MathOperation(operation, number1, number2) => Some(apply_math(
+                *operation,
+                self.fold_number(number1)?,
+                self.fold_number(number2)?,
+            )),
+            _ => None,
+        }
+    }
+
+    fn fold_id(&self, number: &Number) -> Option<u16> {
+        self.fold_number(number).map(|id| id as u16)
+    }
+
+    fn fold_direction(&self, direction: &Direction) -> Option<[i32; 2]> {
+        match direction {
+            Direction::Constant(direction) => Some(*direction),
+            Direction::Random | Direction::GetVariable { .. } => None,
+            Direction::Operation(operation, direction1, direction2) => {
+                let direction1 = self.fold_direction(direction1)?;
+                let direction2 = self.fold_direction(direction2)?;
+                Some([
+                    apply_math(*operation, direction1[0], direction2[0]),
+                    apply_math(*operation, direction1[1], direction2[1]),
+                ])
+            }
+        }
+    }
+
+    fn fold_condition(&self, condition: &Conditions) -> Option<bool> {
+        match condition {
+            Conditions::Boolean { value } => Some(*value),
+            Conditions::Not { block } => self.fold_condition(block).map(|value| !value),
+            // The second block only matters if the first one doesn't decide already
+            Conditions::And { block1, block2 } => match self.fold_condition(block1)? {
+                false => Some(false),
+                true => self.fold_condition(block2),
+            },
+            Conditions::Or { block1, block2 } => match self.fold_condition(block1)? {
+                true => Some(true),
+                false => self.fold_condition(block2),
+            },
+            Conditions::CompareNumberEquality { block1, block2 } => {
+                Some(self.fold_number(block1)? == self.fold_number(block2)?)
+            }
+            Conditions::CompareBiggerThan { block1, block2 } => {
+                Some(self.fold_number(block1)? > self.fold_number(block2)?)
+            }
+            Conditions::CompareLessThan { block1, block2 } => {
+                Some(self.fold_number(block1)? < self.fold_number(block2)?)
+            }
+            Conditions::CompareBooleans { block1, block2 } => {
+                Some(self.fold_condition(block1)? == self.fold_condition(block2)?)
+            }
+            // Only a literal skips the random number, same as the closures did
+            Conditions::OneInXChance {
+                chance: Number::Constant(chance),
+            } if *chance <= 1 => Some(true),
+            _ => None,
+        }
+    }
+
+    // Directions that aren't constant are pushed as two values
+    fn direction(&mut self, direction: &Direction) -> Dir {
+        match self.fold_direction(direction) {
+            Some(direction) => Dir::Constant(direction),
+            None => {
+                self.push_direction(direction);
+                Dir::Stack
+            }
+        }
+    }
+
+    fn push_direction(&mut self, direction: &Direction) {
+        if let Some(direction) = self.fold_direction(direction) {
+            self.emit(Op::PushDirection(direction));
+            return;
+        }
+
+        match direction {
+            Direction::Random => {
+                self.emit(Op::RandomDirection);
+            }
+            Direction::Operation(operation, direction1, direction2) => {
+                self.push_direction(direction1);
+                self.push_direction(direction2);
+                self.emit(Op::DirectionMath(*operation));
+            }
+            Direction::GetVariable { name } => {
+                let slot = self.local_slot(name);
+                self.emit(Op::Load(slot));
+                self.emit(Op::Load(slot + 1));
+            }
+            Direction::Constant(_) => unreachable!(),
+        }
+    }
+
+    fn number(&mut self, number: &Number) {
+        if let Some(value) = self.fold_number(number) {
+            self.emit(Op::Push(value));
+            return;
+        }
+
+        match number {
+            Number::NumberOfXTouching(types) => {
+                if types.is_empty() {
+                    self.emit(Op::Push(0));
+                }
+
+                for (index, particle_type) in types.iter().enumerate() {
+                    self.id(particle_type);
+                    self.emit(Op::CountNeighbors);
+                    if index > 0 {
+                        self.emit(Op::Math(MathOperations::Addition));
+                    }
+                }
+            }
+            Number::RandomFromXToY(min, max) => {
+                self.number(min);
+                self.number(max);
+                self.emit(Op::Random);
+            }
+            Number::Opacity(direction) => self.field(Field::Opacity, direction),
+            Number::ColorFade(direction) => self.field(Field::ColorFade, direction),
+            Number::HueShift(direction) => self.field(Field::HueShift, direction),
+            Number::Extra(direction) => self.field(Field::Extra, direction),
+            Number::Extra2(direction) => self.field(Field::Extra2, direction),
+            Number::Extra3(direction) => self.field(Field::Extra3, direction),
+            Number::Property(name, direction) => {
+                let direction = self.direction(direction);
+                let name = self.name_index(name);
+                self.emit(Op::Property(name, direction));
+            }
+            Number::Temperature(direction) => {
+                let direction = self.direction(direction);
+                self.emit(Op::Temperature(direction));
+            }
+            Number::MathOperation(operation, number1, number2) => {
+                self.number(number1);
+                self.number(number2);
+                self.emit(Op::Math(*operation));
+            }
+            Number::TypeOf(direction) => {
+                let direction = self.direction(direction);
+                self.emit(Op::Type(direction));
+            }
+            Number::GetVariable(name) => self.load_variable(name),
+            Number::RayCast(direction, distance) => {
+                let direction = self.direction(direction);
+                self.number(distance);
+                self.emit(Op::RayCast(direction));
+            }
+            Number::CountInRadius(radius, types) => {
+                self.number(radius);
+                self.query_types(types);
+                self.emit(Op::CountInRadius);
+            }
+            Number::CountInArea(corner1, corner2, types) => {
+                let corner1 = self.direction(corner1);
+                let corner2 = self.direction(corner2);
+                self.query_types(types);
+                self.emit(Op::CountInArea(corner1, corner2));
+            }
+            Number::DistanceToNearest(radius, types) => {
+                self.number(radius);
+                self.query_types(types);
+                self.emit(Op::NearestDistance);
+            }
+            Number::Constant(_) | Number::FromID(_) | Number::FromName(_) => unreachable!(),
+        }
+    }
+
+    // Globals go first, a local with the same name can't be reached
+    fn load_variable(&mut self, name: &str) {
+        match self.global_index(name) {
+            Some(index) => self.emit(Op::LoadGlobal(index)),
+            None => {
+                let slot = self.local_slot(name);
+                self.emit(Op::Load(slot))
+            }
+        };
+    }
+
+    fn field(&mut self, field: Field, direction: &Direction) {
+        let direction = self.direction(direction);
+        self.emit(Op::Field(field, direction));
+    }
+
+    // Same as number but the value is used as a particle id
+    fn id(&mut self, number: &Number) {
+        if let Some(id) = self.fold_id(number) {
+            self.emit(Op::Push(id as i32));
+            return;
+        }
+
+        self.number(number);
+        if !matches!(number, Number::TypeOf(_)) {
+            self.emit(Op::ToId);
+        }
+    }
+
+    // Types that are known now go into a bitset, the rest are checked one by one after them.
+    // Constant ones have no side effects, so checking them first doesn't change anything
+    fn split_types<'b>(&self, types: &'b [Number]) -> (Vec<u16>, Vec<&'b Number>) {
+        let mut constant_types = Vec::new();
+        let mut dynamic_types = Vec::new();
+
+        for particle_type in types {
+            match self.fold_id(particle_type) {
+                Some(id) => constant_types.push(id),
+                None => dynamic_types.push(particle_type),
+            }
+        }
+
+        (constant_types, dynamic_types)
+    }
+
+    // Expects a type on the stack, leaves it there and jumps to the returned jumps if it's any of the types
+    fn type_checks(&mut self, constant_types: &[u16], dynamic_types: &[&Number], jumps: &mut Vec<usize>) {
+        if !constant_types.is_empty() {
+            let set = self.type_set_index(constant_types);
+            self.emit(Op::Dup);
+            self.emit(Op::InSet(set));
+            jumps.push(self.emit(Op::JumpIfTrue(0)));
+        }
+
+        for particle_type in dynamic_types {
+            self.emit(Op::Dup);
+            self.id(particle_type);
+            self.emit(Op::Equal);
+            jumps.push(self.emit(Op::JumpIfTrue(0)));
+        }
+    }
+
+    // Dynamic types are all evaluated in order like the closures do, then gathered with the constant ones
+    fn query_types(&mut self, types: &[Number]) {
+        let (constant_types, dynamic_types) = self.split_types(types);
+        for particle_type in &dynamic_types {
+            self.id(particle_type);
+        }
+
+        let set = self.type_set_index(&constant_types);
+        self.emit(Op::TypeSet(set, dynamic_types.len() as u8));
+    }
+
+    // Where the type checks jumped the type is still on the stack, it's swapped by the result
+    fn finish_type_checks(&mut self, jumps: Vec<usize>) {
+        self.emit(Op::Push(0));
+        let end = self.emit(Op::Jump(0));
+
+        for jump in jumps {
+            self.patch(jump);
+        }
+        self.emit(Op::Pop);
+        self.emit(Op::Push(1));
+        self.patch(end);
+    }
+
+    fn condition(&mut self, condition: &Conditions) {
+        if let Some(value) = self.fold_condition(condition) {
+            self.emit(Op::Push(value as i32));
+            return;
+        }
+
+        match condition {
+            Conditions::CheckTypesInDirection { direction, types } => {
+                let direction = self.direction(direction);
+                let (constant_types, dynamic_types) = self.split_types(types);
+
+                if dynamic_types.is_empty() {
+                    let set = self.type_set_index(&constant_types);
+                    self.emit(Op::TypeIn(direction, set));
+                    return;
+                }
+
+                let mut jumps = Vec::new();
+                self.emit(Op::Type(direction));
+                self.type_checks(&constant_types, &dynamic_types, &mut jumps);
+                self.emit(Op::Pop);
+                self.finish_type_checks(jumps);
+            }
+            Conditions::IsTouching { types } => {
+                let (constant_types, dynamic_types) = self.split_types(types);
+
+                if dynamic_types.is_empty() {
+                    let set = self.type_set_index(&constant_types);
+                    self.emit(Op::TouchingAny(set));
+                    return;
+                }
+
+                let mut jumps = Vec::new();
+                for neighbor in 0..ParticleApi::NEIGHBORS.len() {
+                    self.emit(Op::NeighborType(neighbor as u8));
+                    self.type_checks(&constant_types, &dynamic_types, &mut jumps);
+                    self.emit(Op::Pop);
+                }
+                self.finish_type_checks(jumps);
+            }
+            Conditions::Not { block } => {
+                self.condition(block);
+                self.emit(Op::Not);
+            }
+            Conditions::And { block1, block2 } => {
+                if self.fold_condition(block1) == Some(true) {
+                    self.condition(block2);
+                    return;
+                }
+
+                self.condition(block1);
+                let short_circuit = self.emit(Op::JumpIfFalse(0));
+                self.condition(block2);
+                let end = self.emit(Op::Jump(0));
+                self.patch(short_circuit);
+                self.emit(Op::Push(0));
+                self.patch(end);
+            }
+            Conditions::Or { block1, block2 } => {
+                if self.fold_condition(block1) == Some(false) {
+                    self.condition(block2);
+                    return;
+                }
+
+                self.condition(block1);
+                let short_circuit = self.emit(Op::JumpIfTrue(0));
+                self.condition(block2);
+                let end = self.emit(Op::Jump(0));
+                self.patch(short_circuit);
+                self.emit(Op::Push(1));
+                self.patch(end);
+            }
+            Conditions::OneInXChance { chance } => {
+                self.number(chance);
+                self.emit(Op::OneIn);
+            }
+            Conditions::IsEmpty { direction } => {
+                let direction = self.direction(direction);
+                self.emit(Op::IsEmpty(direction));
+            }
+            Conditions::CompareNumberEquality { block1, block2 } => {
+                self.number(block1);
+                self.number(block2);
+                self.emit(Op::Equal);
+            }
+            Conditions::CompareBiggerThan { block1, block2 } => {
+                self.number(block1);
+                self.number(block2);
+                self.emit(Op::Greater);
+            }
+            Conditions::CompareLessThan { block1, block2 } => {
+                self.number(block1);
+                self.number(block2);
+                self.emit(Op::Less);
+            }
+            Conditions::CompareBooleans { block1, block2 } => {
+                self.condition(block1);
+                self.condition(block2);
+                self.emit(Op::Equal);
+            }
+            Conditions::GetVariable { name } => {
+                self.load_variable(name);
+                self.emit(Op::Push(0));
+                self.emit(Op::Equal);
+                self.emit(Op::Not);
+            }
+            Conditions::RayHits { direction, distance, types } => {
+                let direction = self.direction(direction);
+                self.number(distance);
+                self.query_types(types);
+                self.emit(Op::RayHits(direction));
+            }
+            Conditions::IsNear { radius, types } => {
+                self.number(radius);
+                self.query_types(types);
+                self.emit(Op::NearestDistance);
+                self.emit(Op::Push(0));
+                self.emit(Op::Greater);
+            }
+            Conditions::Boolean { .. } => unreachable!(),
+        }
+    }
+
+    fn actions(&mut self, actions: &[Actions]) {
+        for action in actions {
+            self.action(action);
+        }
+    }
+
+    // Actions leave the stack as they found it
+    fn action(&mut self, action: &Actions) {
+        match action {
+            Actions::Swap { direction } => {
+                let direction = self.direction(direction);
+                self.emit(Op::Swap(direction));
+            }
+            Actions::CopyTo { direction } => {
+                let direction = self.direction(direction);
+                self.emit(Op::CopyTo(direction));
+            }
+            Actions::ChangeInto { direction, r#type } => {
+                // Particles that don't exist are skipped, if nothing else has to run the block can go away
+                let invalid = self
+                    .fold_id(r#type)
+                    .is_some_and(|id| !self.api.has_particle_type(id));
+                let skippable = matches!(r#type, Number::FromID(_)) || self.fold_direction(direction).is_some();
+                if invalid && skippable {
+                    return;
+                }
+
+                let direction = self.direction(direction);
+                self.number(r#type);
+                self.emit(Op::ChangeInto(direction));
+            }
+            Actions::RandomTransformation {
+                transformation,
+                block: Some(block),
+            } => {
+                self.emit(Op::SaveTransformation);
+                self.emit(Op::RandomTransformation(*transformation));
+                self.actions(block);
+                self.emit(Op::RestoreTransformation);
+            }
+            Actions::ForEachTransformation {
+                transformation,
+                block: Some(block),
+            } => {
+                if *transformation == TransformationInternal::None {
+                    self.actions(block);
+                    return;
+                }
+
+                self.emit(Op::SaveTransformation);
+                self.emit(Op::Push(transformations_of(*transformation).len() as i32));
+                let init = self.emit(Op::LoopInit(0));
+                let start = self.here();
+                self.emit(Op::ForEachTransformation(*transformation));
+                self.actions(block);
+                self.emit(Op::LoopBack(start));
+                self.patch(init);
+                self.emit(Op::RestoreTransformation);
+            }
+            Actions::RotatedBy {
+                number,
+                block: Some(block),
+            } => {
+                self.emit(Op::SaveTransformation);
+                self.number(number);
+                self.emit(Op::SetRotation);
+                self.actions(block);
+                self.emit(Op::RestoreTransformation);
+            }
+            Actions::If(blocks) => {
+                let mut ends = Vec::new();
+
+                for (condition, block) in blocks.iter().flatten() {
+                    match self.fold_condition(condition) {
+                        Some(false) => continue,
+                        // Nothing after a branch that always runs can be reached
+                        Some(true) => {
+                            self.actions(block);
+                            break;
+                        }
+                        None => {
+                            self.condition(condition);
+                            let next = self.jump_if_false();
+                            self.actions(block);
+                            match self.swap_if_empty(next) {
+                                Some(end) => ends.push(end),
+                                None => {
+                                    ends.push(self.emit(Op::Jump(0)));
+                                    self.patch(next);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+            Actions::IncreaseParticlePropierty {
+                propierty,
+                number,
+                direction,
+            } => {
+                let direction = self.direction(direction);
+                self.number(number);
+                match propierty {
+                    ParticlePropierties::Named(name) => {
+                        let name = self.name_index(name);
+                        self.emit(Op::IncreaseProperty(name, direction));
+                    }
+                    builtin => {
+                        self.emit(Op::IncreaseField(field_of(builtin), direction));
+                    }
+                }
+            }
+            Actions::SetParticlePropierty {
+                propierty,
+                number,
+                direction,
+            } => {
+                let direction = self.direction(direction);
+                self.number(number);
+                match propierty {
+                    ParticlePropierties::Named(name) => {
+                        let name = self.name_index(name);
+                        self.emit(Op::SetProperty(name, direction));
+                    }
+                    builtin => {
+                        self.emit(Op::SetField(field_of(builtin), direction));
+                    }
+                }
+            }
+            Actions::IncreaseTemperature { number, direction } => {
+                let direction = self.direction(direction);
+                self.number(number);
+                self.emit(Op::IncreaseTemperature(direction));
+            }
+            Actions::SetTemperature { number, direction } => {
+                let direction = self.direction(direction);
+                self.number(number);
+                self.emit(Op::SetTemperature(direction));
+            }
+            Actions::AddImpulse { direction, impulse } => {
+                let direction = self.direction(direction);
+                let impulse = self.direction(impulse);
+                self.emit(Op::AddImpulse(direction, impulse));
+            }
+            Actions::Explode { direction, radius, strength, spawn, chance } => {
+                let direction = self.direction(direction);
+                self.number(radius);
+                self.number(strength);
+                self.number(spawn.as_ref().unwrap_or(&Number::Constant(Particle::EMPTY.id as i32)));
+                self.number(chance.as_ref().unwrap_or(&Number::Constant(100)));
+                self.emit(Op::Explode(direction));
+            }
+            Actions::Repeat {
+                number,
+                block: Some(block),
+            } => {
+                if self.fold_number(number).is_some_and(|times| times <= 0) {
+                    return;
+                }
+
+                self.number(number);
+                let init = self.emit(Op::LoopInit(0));
+                let start = self.here();
+                self.actions(block);
+                self.emit(Op::LoopBack(start));
+                self.patch(init);
+            }
+            Actions::EveryXFrames {
+                number,
+                block: Some(block),
+            } => {
+                if self.fold_number(number).is_some_and(|frames| frames == 0) {
+                    return;
+                }
+
+                self.number(number);
+                let check = self.emit(Op::EveryXFrames { skip: 0, end: 0 });
+                self.actions(block);
+                let skip = self.here();
+                self.emit(Op::KeepAwake);
+                let end = self.here();
+                self.program.ops[check] = Op::EveryXFrames { skip, end };
+            }
+            Actions::SetVariable { name, value } => match value {
+                VariableValue::Number(number) => {
+                    self.number(number);
+                    self.store_variable(name);
+                }
+                VariableValue::Boolean(condition) => {
+                    self.condition(condition);
+                    self.store_variable(name);
+                }
+                VariableValue::Direction(direction) => {
+                    self.push_direction(direction);
+                    let slot = self.local_slot(name);
+                    self.emit(Op::Store(slot + 1));
+                    self.emit(Op::Store(slot));
+                }
+            },
+            Actions::IncreaseVariable { name, number } => match self.global_index(name) {
+                Some(index) => {
+                    self.number(number);
+                    self.emit(Op::IncreaseGlobal(index));
+                }
+                None => {
+                    let slot = self.local_slot(name);
+                    self.emit(Op::Load(slot));
+                    self.number(number);
+                    self.emit(Op::Math(MathOperations::Addition));
+                    self.emit(Op::Store(slot));
+                }
+            },
+            // Blocks without a body do nothing at all
+            Actions::RandomTransformation { block: None, .. }
+            | Actions::ForEachTransformation { block: None, .. }
+            | Actions::RotatedBy { block: None, .. }
+            | Actions::Repeat { block: None, .. }
+            | Actions::EveryXFrames { block: None, .. }
+            | Actions::Call { .. }
+            | Actions::None => {}
+        }
+    }
+
+    fn store_variable(&mut self, name: &str) {
+        match self.global_index(name) {
+            Some(index) => self.emit(Op::StoreGlobal(index)),
+            None => {
+                let slot = self.local_slot(name);
+                self.emit(Op::Store(slot))
+            }
+        };
+    }
+
+    // Walks every path of the program to know how deep the stacks get. Paths that meet must
+    // agree on the depth, the code above is written so they always do
+    fn compute_limits(&mut self) {
+        let ops = &self.program.ops;
+        let mut visited: Vec<Option<(usize, usize)>> = vec![None; ops.len()];
+        let mut pending = vec![(0usize, 0usize, 0usize)];
+        let mut max_stack = 0;
+        let mut max_transformations = 0;
+
+        while let Some((pc, stack, transformations)) = pending.pop() {
+            if pc >= ops.len() {
+                continue;
+            }
+
+            if let Some(depth) = visited[pc] {
+                debug_assert_eq!(depth, (stack, transformations), "Stack mismatch at {}", pc);
+                continue;
+            }
+            visited[pc] = Some((stack, transformations));
+
+            let (pops, pushes) = stack_effect(&ops[pc]);
+            let after = stack - pops + pushes;
+            max_stack = max_stack.max(stack).max(after);
+
+            match ops[pc] {
+                Op::Jump(target) => pending.push((target as usize, stack, transformations)),
+                Op::JumpIfFalse(target)
+                | Op::JumpIfTrue(target)
+                | Op::JumpUnlessEmpty(_, target)
+                | Op::JumpUnlessTypeIn(_, _, target)
+                | Op::SwapIfEmpty(_, target) => {
+                    pending.push((target as usize, after, transformations));
+                    pending.push((pc + 1, after, transformations));
+                }
+                Op::LoopInit(target) => {
+                    pending.push((target as usize, stack - 1, transformations));
+                    pending.push((pc + 1, stack, transformations));
+                }
+                Op::LoopBack(target) => {
+                    pending.push((target as usize, stack, transformations));
+                    pending.push((pc + 1, stack - 1, transformations));
+                }
+                Op::EveryXFrames { skip, end } => {
+                    pending.push((skip as usize, after, transformations));
+                    pending.push((end as usize, after, transformations));
+                    pending.push((pc + 1, after, transformations));
+                }
+                Op::SaveTransformation => {
+                    max_transformations = max_transformations.max(transformations + 1);
+                    pending.push((pc + 1, after, transformations + 1));
+                }
+                Op::RestoreTransformation => pending.push((pc + 1, after, transformations - 1)),
+                _ => pending.push((pc + 1, after, transformations)),
+            }
+        }
+
+        self.program.max_stack = max_stack;
+        self.program.max_transformations = max_transformations;
+    }
+}
+
+fn field_of(propierty: &ParticlePropierties) -> Field {
+    match propierty {
+        ParticlePropierties::Opacity => Field::Opacity,
+        ParticlePropierties::HueShift => Field::HueShift,
+        ParticlePropierties::ColorFade => Field::ColorFade,
+        ParticlePropierties::Extra => Field::Extra,
+        ParticlePropierties::Extra2 => Field::Extra2,
+        ParticlePropierties::Extra3 => Field::Extra3,
+        ParticlePropierties::Named(_) => unreachable!(),
+    }
+}
+
+// Values popped and pushed when execution goes on to the next instruction
+fn stack_effect(op: &Op) -> (usize, usize) {
+    let direction = |direction: &Dir| match direction {
+        Dir::Constant(_) => 0,
+        Dir::Stack => 2,
+    };
+
+    match op {
+        Op::Push(_) | Op::NeighborType(_) | Op::TouchingAny(_) | Op::Load(_) | Op::LoadGlobal(_) => (0, 1),
+        Op::Pop | Op::JumpIfFalse(_) | Op::JumpIfTrue(_) => (1, 0),
+        Op::JumpUnlessEmpty(dir, _) | Op::JumpUnlessTypeIn(dir, _, _) => (direction(dir), 0),
+        Op::Store(_) | Op::StoreGlobal(_) | Op::IncreaseGlobal(_) => (1, 0),
+        Op::Dup => (1, 2),
+        Op::Math(_) | Op::Random | Op::Equal | Op::Greater | Op::Less => (2, 1),
+        Op::ToId | Op::CountNeighbors | Op::Not | Op::InSet(_) | Op::OneIn => (1, 1),
+        Op::CountInRadius | Op::NearestDistance => (1, 1),
+        Op::TypeSet(_, dynamic) => (*dynamic as usize, 0),
+        Op::RayCast(dir) | Op::RayHits(dir) => (direction(dir) + 1, 1),
+        Op::CountInArea(corner1, corner2) => (direction(corner1) + direction(corner2), 1),
+        Op::PushDirection(_) | Op::RandomDirection => (0, 2),
+        Op::DirectionMath(_) => (4, 2),
+        Op::Type(dir)
+        | Op::Field(_, dir)
+        | Op::Property(_, dir)
+        | Op::Temperature(dir)
+        | Op::TypeIn(dir, _)
+        | Op::IsEmpty(dir) => (direction(dir), 1),
+        Op::Jump(_)
+        | Op::SwapIfEmpty(..)
+        | Op::SaveTransformation
+        | Op::RestoreTransformation
+        | Op::RandomTransformation(_)
+        | Op::ForEachTransformation(_)
+        | Op::KeepAwake => (0, 0),
+        // The counter stays while the loop runs
+        Op::LoopInit(_) => (1, 1),
+        Op::LoopBack(_) => (1, 1),
+        Op::EveryXFrames { .. } | Op::SetRotation => (1, 0),
+        Op::Swap(dir) | Op::CopyTo(dir) => (direction(dir), 0),
+        Op::ChangeInto(dir)
+        | Op::IncreaseField(_, dir)
+        | Op::SetField(_, dir)
+        | Op::IncreaseProperty(_, dir)
+        | Op::SetProperty(_, dir)
+        | Op::IncreaseTemperature(dir)
+        | Op::SetTemperature(dir) => (direction(dir) + 1, 0),
+        Op::AddImpulse(dir, impulse) => (direction(dir) + direction(impulse), 0),
+        Op::Explode(dir) => (direction(dir) + 4, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use app_core::Simulation;
+
+    use super::*;
+    use crate::plugins::{Backend, JSPlugin};
+
+    const SIZE: usize = 24;
+    const FRAMES: usize = 30;
+
+    // A particle running the given update blocks, with a property and a global for the blocks that use them
+    fn plugin(update: &str) -> String {
+        format!(
+            r#"{{"name": "Test", "color": [200, 80, 200], "color2": [120, 40, 160],
+                "properties": [{{"name": "charge", "default": 5, "max": 20}}],
+                "globals": [{{"name": "moves", "value": 0}}],
+                "motion": {{"gravity": 0.4, "bounce": 0.5}},
+                "update": {}}}"#,
+            update
+        )
+    }
+
+    fn simulation(update: &str, backend: Backend) -> Simulation {
+        let mut simulation = Simulation::new_with_seed(SIZE, SIZE, 3);
+        simulation.add_plugins(default_plugins::plugin()).unwrap();
+        simulation.add_plugin(Box::new(JSPlugin::with_backend(&plugin(update), backend).unwrap())).unwrap();
+        simulation.repaint();
+        simulation.set_sleeping_enabled(false);
+
+        let types = ["Test", "Water", "Test", "Sand", "Test"].map(|name| simulation.get_particle_id(name).unwrap());
+        for y in SIZE / 3..SIZE - 2 {
+            for x in 2..SIZE - 2 {
+                if (x * 5 + y * 3) % 7 != 0 {
+                    simulation.set_selected_plugin(types[(x + y * 2) % types.len()]);
+                    simulation.set_selected_particle(x, y);
+                }
+            }
+        }
+
+        simulation
+    }
+
+    // Runs the blocks with both backends and checks they leave the same world behind
+    fn assert_same(update: &str) {
+        let world = |backend| {
+            let mut simulation = simulation(update, backend);
+            for _ in 0..FRAMES {
+                simulation.update();
+            }
+
+            let particles = simulation
+                .get_particles()
+                .iter()
+                .map(|p| (p.id, p.opacity, p.hue_shift, p.color_fade, p.extra, p.extra2, p.extra3))
+                .collect::<Vec<_>>();
+            let temperatures = (0..SIZE * SIZE)
+                .map(|i| simulation.get_temperature(i % SIZE, i / SIZE).unwrap().to_bits())
+                .collect::<Vec<_>>();
+            (particles, temperatures, simulation.get_buffer().to_vec())
+        };
+
+        let closures = world(Backend::Closures);
+        let bytecode = world(Backend::Bytecode);
+        assert!(closures.0 == bytecode.0, "different particles running {}", update);
+        assert!(closures.1 == bytecode.1, "different temperatures running {}", update);
+        assert!(closures.2 == bytecode.2, "different colors running {}", update);
+    }
+
+    // Compiled against a world without particles, enough for looking at the instructions
+    fn compile(update: &str) -> Program {
+        let actions = serde_json::from_str::<Vec<Actions>>(update).unwrap();
+        let api = ParticleApi::new_with_seed(4, 4, 0);
+        Compiler::compile(&actions, &Globals::new([]), &api)
+    }
+
+    const WATER: &str = r#"[{"action": "randomTransformation", "data": {"transformation": "horizontalReflection", "block": [
+        {"action": "if", "data": [
+            [{"block": "isEmpty", "data": {"direction": [0, -1]}}, [{"action": "swap", "data": {"direction": [0, -1]}}]],
+            [{"block": "isEmpty", "data": {"direction": [1, 0]}}, [{"action": "swap", "data": {"direction": [1, 0]}}]]]}]}}]"#;
+
+    #[test]
+    fn actions() {
+        assert_same(WATER);
+        assert_same(r#"[
+            {"action": "copyTo", "data": {"direction": [0, 1]}},
+            {"action": "changeInto", "data": {"direction": [1, 0], "type": {"number": "fromName", "data": "Steam"}}},
+            {"action": "swap", "data": {"direction": null}}]"#);
+        assert_same(r#"[
+            {"action": "setParticlePropierty", "data": {"propierty": "charge", "number": {"number": "constant", "data": 9}, "direction": [0, 0]}},
+            {"action": "increaseParticlePropierty", "data": {"propierty": "hueShift", "number": {"number": "constant", "data": 3}, "direction": [0, -1]}},
+            {"action": "setParticlePropierty", "data": {"propierty": "extra3", "number": {"number": "property", "data": ["charge", [0, 0]]}, "direction": [0, 0]}},
+            {"action": "increaseTemperature", "data": {"number": {"number": "constant", "data": 15}, "direction": [0, 1]}},
+            {"action": "setTemperature", "data": {"number": {"number": "constant", "data": -20}, "direction": [1, 0]}}]"#);
+        assert_same(r#"[
+            {"action": "addImpulse", "data": {"direction": [0, 0], "impulse": [1, 2]}},
+            {"action": "explode", "data": {"direction": [1, 0], "radius": {"number": "constant", "data": 2}, "strength": {"number": "constant", "data": 1},
+                "spawn": {"number": "fromName", "data": "Steam"}, "chance": {"number": "constant", "data": 40}}}]"#);
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_same(r#"[
+            {"action": "repeat", "data": {"number": {"number": "randomFromXToY", "data": [{"number": "constant", "data": -1}, {"number": "constant", "data": 3}]},
+                "block": [{"action": "increaseParticlePropierty", "data": {"propierty": "extra2", "number": {"number": "constant", "data": 1}, "direction": [0, 0]}}]}},
+            {"action": "everyXFrames", "data": {"number": {"number": "constant", "data": 3},
+                "block": [{"action": "swap", "data": {"direction": [0, 1]}}]}},
+            {"action": "forEachTransformation", "data": {"transformation": "rotation",
+                "block": [{"action": "if", "data": [[{"block": "isEmpty", "data": {"direction": [0, 1]}}, [{"action": "swap", "data": {"direction": [0, 1]}}]]]}]}},
+            {"action": "rotatedBy", "data": {"number": {"number": "randomFromXToY", "data": [{"number": "constant", "data": 0}, {"number": "constant", "data": 7}]},
+                "block": [{"action": "swap", "data": {"direction": [1, 1]}}]}},
+            {"action": "randomTransformation", "data": {"transformation": "reflection",
+                "block": [{"action": "if", "data": [
+                    [{"block": "boolean", "data": {"value": false}}, [{"action": "swap", "data": {"direction": [0, -1]}}]],
+                    null,
+                    [{"block": "oneInXChance", "data": {"chance": {"number": "constant", "data": 2}}}, [{"action": "swap", "data": {"direction": [-1, 0]}}]]]}]}}]"#);
+    }
+
+    #[test]
+    fn conditions() {
+        let condition = |condition: &str| {
+            assert_same(&format!(
+                r#"[{{"action": "if", "data": [[{}, [{{"action": "swap", "data": {{"direction": [0, -1]}}}}]],
+                    [{{"block": "boolean", "data": {{"value": true}}}}, [{{"action": "swap", "data": {{"direction": [1, 0]}}}}]]]}}]"#,
+                condition
+            ))
+        };
+
+        condition(r#"{"block": "checkTypesInDirection", "data": {"direction": [0, -1], "types": [{"number": "fromName", "data": "Empty"}, {"number": "typeOf", "data": [0, 1]}]}}"#);
+        condition(r#"{"block": "not", "data": {"block": {"block": "isEmpty", "data": {"direction": [0, -1]}}}}"#);
+        condition(r#"{"block": "and", "data": {"block1": {"block": "isEmpty", "data": {"direction": [0, -1]}}, "block2": {"block": "oneInXChance", "data": {"chance": {"number": "constant", "data": 3}}}}}"#);
+        condition(r#"{"block": "or", "data": {"block1": {"block": "isTouching", "data": {"types": [{"number": "fromName", "data": "Water"}]}}, "block2": {"block": "isEmpty", "data": {"direction": [0, -1]}}}}"#);
+        condition(r#"{"block": "compareNumberEquality", "data": {"block1": {"number": "typeOf", "data": [0, -1]}, "block2": {"number": "fromName", "data": "Empty"}}}"#);
+        condition(r#"{"block": "compareBooleans", "data": {"block1": {"block": "isEmpty", "data": {"direction": [0, -1]}}, "block2": {"block": "isEmpty", "data": {"direction": [1, 0]}}}}"#);
+        condition(r#"{"block": "compareBiggerThan", "data": {"block1": {"number": "temperature", "data": [0, 0]}, "block2": {"number": "constant", "data": 20}}}"#);
+        condition(r#"{"block": "compareLessThan", "data": {"block1": {"number": "extra", "data": [0, -1]}, "block2": {"number": "constant", "data": 1}}}"#);
+        condition(r#"{"block": "rayHits", "data": {"direction": [0, -1], "distance": {"number": "constant", "data": 6}, "types": [{"number": "fromName", "data": "Sand"}]}}"#);
+        condition(r#"{"block": "isNear", "data": {"radius": {"number": "constant", "data": 3}, "types": [{"number": "fromName", "data": "Water"}]}}"#);
+    }
+
+    #[test]
+    fn numbers() {
+        let number = |number: &str| {
+            assert_same(&format!(
+                r#"[{{"action": "setParticlePropierty", "data": {{"propierty": "extra", "number": {}, "direction": [0, 0]}}}},
+                    {{"action": "if", "data": [[{{"block": "compareBiggerThan", "data": {{"block1": {{"number": "extra", "data": [0, 0]}},
+                        "block2": {{"number": "constant", "data": 2}}}}}}, [{{"action": "swap", "data": {{"direction": [0, -1]}}}}]]]}}]"#,
+                number
+            ))
+        };
+
+        number(r#"{"number": "numberOfXTouching", "data": [{"number": "fromName", "data": "Test"}, {"number": "typeOf", "data": null}]}"#);
+        number(r#"{"number": "randomFromXToY", "data": [{"number": "constant", "data": 5}, {"number": "constant", "data": 0}]}"#);
+        number(r#"{"number": "opacity", "data": [0, -1]}"#);
+        number(r#"{"number": "colorFade", "data": [1, 0]}"#);
+        number(r#"{"number": "hueShift", "data": [0, 0]}"#);
+        number(r#"{"number": "temperature", "data": [0, 1]}"#);
+        number(r#"{"number": "rayCast", "data": [[0, -1], {"number": "constant", "data": 8}]}"#);
+        number(r#"{"number": "countInRadius", "data": [{"number": "constant", "data": 2}, [{"number": "fromName", "data": "Water"}]]}"#);
+        number(r#"{"number": "countInArea", "data": [[-2, -1], [1, 2], [{"number": "fromName", "data": "Test"}]]}"#);
+        number(r#"{"number": "distanceToNearest", "data": [{"number": "constant", "data": 5}, [{"number": "fromName", "data": "Sand"}]]}"#);
+        for operation in ["addition", "subtraction", "multiplication", "division", "modulo", "difference"] {
+            number(&format!(
+                r#"{{"number": "mathOperation", "data": ["{}", {{"number": "typeOf", "data": [0, -1]}}, {{"number": "constant", "data": -3}}]}}"#,
+                operation
+            ));
+        }
+    }
+
+    #[test]
+    fn directions_and_variables() {
+        assert_same(r#"[
+            {"action": "setVariable", "data": {"name": "fall", "value": ["addition", [0, -1], null]}},
+            {"action": "setVariable", "data": {"name": "free", "value": {"block": "isEmpty", "data": {"direction": {"getVariable": "fall"}}}}},
+            {"action": "setVariable", "data": {"name": "heat", "value": {"number": "temperature", "data": [0, 0]}}},
+            {"action": "if", "data": [[{"block": "getVariable", "data": {"name": "free"}}, [
+                {"action": "swap", "data": {"direction": {"getVariable": "fall"}}},
+                {"action": "increaseVariable", "data": {"name": "moves", "number": {"number": "constant", "data": 1}}},
+                {"action": "increaseVariable", "data": {"name": "heat", "number": {"number": "getVariable", "data": "moves"}}}]]]},
+            {"action": "setParticlePropierty", "data": {"propierty": "extra3", "number": {"number": "getVariable", "data": "heat"}, "direction": [0, 0]}},
+            {"action": "swap", "data": {"direction": ["subtraction", [1, 0], {"getVariable": "fall"}]}}]"#);
+    }
+
+    #[test]
+    fn fused_jumps() {
+        // Both branches only swap into the cell they check
+        assert_eq!(compile(WATER).ops, [
+            Op::SaveTransformation,
+            Op::RandomTransformation(TransformationInternal::HorizontalReflection),
+            Op::SwapIfEmpty([0, -1], 4),
+            Op::SwapIfEmpty([1, 0], 4),
+            Op::RestoreTransformation,
+        ]);
+
+        // Swapping somewhere else, or doing more, only fuses the check with its jump
+        let elsewhere = compile(r#"[{"action": "if", "data": [[{"block": "isEmpty", "data": {"direction": [0, -1]}},
+            [{"action": "swap", "data": {"direction": [1, -1]}}]]]}]"#);
+        assert!(elsewhere.ops.iter().any(|op| matches!(op, Op::JumpUnlessEmpty(..))));
+        assert!(!elsewhere.ops.iter().any(|op| matches!(op, Op::SwapIfEmpty(..) | Op::IsEmpty(_) | Op::JumpIfFalse(_))));
+        let more = compile(r#"[{"action": "if", "data": [[{"block": "isEmpty", "data": {"direction": [0, -1]}},
+            [{"action": "swap", "data": {"direction": [0, -1]}}, {"action": "copyTo", "data": {"direction": [0, 0]}}]]]}]"#);
+        assert!(!more.ops.iter().any(|op| matches!(op, Op::SwapIfEmpty(..))));
+
+        // The condition is negated after the check, so there's nothing to fuse with
+        let negated = compile(r#"[{"action": "if", "data": [[{"block": "not", "data": {"block": {"block": "isEmpty", "data": {"direction": [0, 1]}}}},
+            [{"action": "swap", "data": {"direction": [0, 1]}}]]]}]"#);
+        assert!(negated.ops.iter().any(|op| matches!(op, Op::JumpIfFalse(_))));
+    }
+}
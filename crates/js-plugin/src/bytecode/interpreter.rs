@@ -0,0 +1,422 @@
+use std::borrow::Cow;
+
+use app_core::{ParticleApi, Transformation};
+
+use super::*;
+use crate::blocks::{clamp_reach, explosion, one_in, rounded_distance, Globals};
+
+// Programs that fit run with their stacks on the native stack, so nothing is allocated per cell.
+// The small size is for the many programs that barely use them, like water's, zeroing the
+// bigger arrays for every cell took longer than running those
+const SMALL: (usize, usize, usize) = (4, 2, 2);
+const INLINE: (usize, usize, usize) = (32, 8, 16);
+
+// Everything the program works with during one update, locals start at 0 every time
+struct Frame<'a> {
+    stack: &'a mut [i32],
+    transformations: &'a mut [Transformation],
+    locals: &'a mut [i32],
+}
+
+impl Program {
+    pub fn run(&self, api: &mut ParticleApi, globals: &Globals) {
+        if self.fits(SMALL) {
+            self.run_inline::<{ SMALL.0 }, { SMALL.1 }, { SMALL.2 }>(api, globals);
+        } else if self.fits(INLINE) {
+            self.run_inline::<{ INLINE.0 }, { INLINE.1 }, { INLINE.2 }>(api, globals);
+        } else {
+            let mut stack = vec![0; self.max_stack];
+            let mut transformations = vec![Transformation::None; self.max_transformations];
+            let mut locals = vec![0; self.locals];
+            let frame = Frame {
+                stack: &mut stack,
+                transformations: &mut transformations,
+                locals: &mut locals,
+            };
+            self.execute(api, globals, frame);
+        }
+    }
+
+    fn fits(&self, (stack, transformations, locals): (usize, usize, usize)) -> bool {
+        self.max_stack <= stack && self.max_transformations <= transformations && self.locals <= locals
+    }
+
+    fn run_inline<const STACK: usize, const TRANSFORMATIONS: usize, const LOCALS: usize>(
+        &self,
+        api: &mut ParticleApi,
+        globals: &Globals,
+    ) {
+        let mut stack = [0; STACK];
+        let mut transformations = [Transformation::None; TRANSFORMATIONS];
+        let mut locals = [0; LOCALS];
+        let frame = Frame {
+            stack: &mut stack,
+            transformations: &mut transformations,
+            locals: &mut locals,
+        };
+        self.execute(api, globals, frame);
+    }
+
+    // Inlined into each size, the call alone was noticeable on programs only a few instructions long
+    #[inline(always)]
+    fn execute(&self, api: &mut ParticleApi, globals: &Globals, frame: Frame) {
+        let Frame {
+            stack,
+            transformations,
+            locals,
+        } = frame;
+        let mut pc = 0;
+        let mut sp = 0;
+        let mut tp = 0;
+        // Only copied when types that aren't constant are added to it
+        let mut type_set = Cow::Borrowed(&[] as &[u64]);
+
+        macro_rules! push {
+            ($value:expr) => {{
+                let value = $value;
+                stack[sp] = value;
+                sp += 1;
+            }};
+        }
+
+        macro_rules! pop {
+            () => {{
+                sp -= 1;
+                stack[sp]
+            }};
+        }
+
+        // Stack directions are popped after any other operand
+        macro_rules! direction {
+            ($dir:expr) => {{
+                let direction = match $dir {
+                    Dir::Constant(direction) => direction,
+                    Dir::Stack => {
+                        sp -= 2;
+                        [stack[sp], stack[sp + 1]]
+                    }
+                };
+                api.get_transformation().transform(&direction)
+            }};
+        }
+
+        while let Some(op) = self.ops.get(pc) {
+            pc += 1;
+
+            match *op {
+                Op::Push(value) => push!(value),
+                Op::Pop => sp -= 1,
+                Op::Dup => push!(stack[sp - 1]),
+                Op::Math(operation) => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(apply_math(operation, a, b));
+                }
+                Op::Random => {
+                    let max = pop!();
+                    let min = pop!();
+                    push!(api.gen_range(min.min(max), min.max(max)));
+                }
+                Op::ToId => push!(pop!() as u16 as i32),
+                Op::PushDirection(direction) => {
+                    push!(direction[0]);
+                    push!(direction[1]);
+                }
+                Op::RandomDirection => {
+                    push!(api.gen_range(-1, 1));
+                    push!(api.gen_range(-1, 1));
+                }
+                Op::DirectionMath(operation) => {
+                    let y2 = pop!();
+                    let x2 = pop!();
+                    let y1 = pop!();
+                    let x1 = pop!();
+                    push!(apply_math(operation, x1, x2));
+                    push!(apply_math(operation, y1, y2));
+                }
+                Op::Type(dir) => {
+                    let direction = direction!(dir);
+                    push!(api.get_type(direction[0], direction[1]) as i32);
+                }
+                Op::NeighborType(neighbor) => {
+                    let neighbor = &ParticleApi::NEIGHBORS[neighbor as usize];
+                    push!(api.get_type(neighbor.x, neighbor.y) as i32);
+                }
+                Op::Field(field, dir) => {
+                    let direction = direction!(dir);
+                    let particle = api.get(direction[0], direction[1]);
+                    let value = match field {
+                        Field::Opacity => particle.opacity,
+                        Field::HueShift => particle.hue_shift,
+                        Field::ColorFade => particle.color_fade,
+                        Field::Extra => particle.extra,
+                        Field::Extra2 => particle.extra2,
+                        Field::Extra3 => particle.extra3,
+                    };
+                    push!(value as i32);
+                }
+                Op::Property(name, dir) => {
+                    let direction = direction!(dir);
+                    let particle = api.get(direction[0], direction[1]);
+                    push!(api.get_property(&particle, &self.names[name as usize]).unwrap_or(0) as i32);
+                }
+                Op::Temperature(dir) => {
+                    let direction = direction!(dir);
+                    push!(api.get_temperature(direction[0], direction[1]).round() as i32);
+                }
+                Op::CountNeighbors => {
+                    let id = pop!() as u16;
+                    let count = ParticleApi::NEIGHBORS
+                        .iter()
+                        .filter(|neighbor| api.get_type(neighbor.x, neighbor.y) == id)
+                        .count();
+                    push!(count as i32);
+                }
+                Op::Not => push!((pop!() == 0) as i32),
+                Op::Equal => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!((a == b) as i32);
+                }
+                Op::Greater => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!((a > b) as i32);
+                }
+                Op::Less => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!((a < b) as i32);
+                }
+                Op::InSet(set) => {
+                    let id = pop!() as u16;
+                    push!(set_contains(&self.type_sets[set as usize], id) as i32);
+                }
+                Op::TypeIn(dir, set) => {
+                    let direction = direction!(dir);
+                    let id = api.get_type(direction[0], direction[1]);
+                    push!(set_contains(&self.type_sets[set as usize], id) as i32);
+                }
+                Op::TouchingAny(set) => {
+                    let set = &self.type_sets[set as usize];
+                    let touching = ParticleApi::NEIGHBORS
+                        .iter()
+                        .any(|neighbor| set_contains(set, api.get_type(neighbor.x, neighbor.y)));
+                    push!(touching as i32);
+                }
+                Op::IsEmpty(dir) => {
+                    let direction = direction!(dir);
+                    push!(api.is_empty(direction[0], direction[1]) as i32);
+                }
+                Op::OneIn => {
+                    let chance = pop!();
+                    push!(one_in(api, chance) as i32);
+                }
+                Op::TypeSet(set, dynamic) => {
+                    type_set = Cow::Borrowed(&self.type_sets[set as usize]);
+                    for _ in 0..dynamic {
+                        let id = pop!() as u16;
+                        set_insert(type_set.to_mut(), id);
+                    }
+                }
+                Op::RayCast(dir) => {
+                    let distance = clamp_reach(pop!());
+                    let direction = direction!(dir);
+                    push!(api.ray_cast(direction[0], direction[1], distance).unwrap_or(0));
+                }
+                Op::RayHits(dir) => {
+                    let distance = clamp_reach(pop!());
+                    let direction = direction!(dir);
+                    let hits = api.ray_cast(direction[0], direction[1], distance).is_some_and(|steps| {
+                        set_contains(&type_set, api.get_type(direction[0] * steps, direction[1] * steps))
+                    });
+                    push!(hits as i32);
+                }
+                Op::CountInRadius => {
+                    let radius = clamp_reach(pop!());
+                    push!(api.count_in_radius(radius, |particle| set_contains(&type_set, particle.id)));
+                }
+                Op::CountInArea(corner1, corner2) => {
+                    let corner2 = direction!(corner2);
+                    let corner1 = direction!(corner1);
+                    let count = api.count_in_area(corner1[0], corner1[1], corner2[0], corner2[1], |particle| {
+                        set_contains(&type_set, particle.id)
+                    });
+                    push!(count);
+                }
+                Op::NearestDistance => {
+                    let radius = clamp_reach(pop!());
+                    let nearest = api.find_nearest(radius, |particle| set_contains(&type_set, particle.id));
+                    push!(nearest.map_or(0, |[x, y]| rounded_distance(x, y)));
+                }
+                Op::Load(slot) => push!(locals[slot as usize]),
+                Op::Store(slot) => locals[slot as usize] = pop!(),
+                Op::LoadGlobal(index) => push!(globals.get(index as usize)),
+                Op::StoreGlobal(index) => globals.set(index as usize, pop!()),
+                Op::IncreaseGlobal(index) => globals.increase(index as usize, pop!()),
+                Op::Jump(target) => pc = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if pop!() == 0 {
+                        pc = target as usize;
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if pop!() != 0 {
+                        pc = target as usize;
+                    }
+                }
+                Op::JumpUnlessEmpty(dir, target) => {
+                    let direction = direction!(dir);
+                    if !api.is_empty(direction[0], direction[1]) {
+                        pc = target as usize;
+                    }
+                }
+                Op::JumpUnlessTypeIn(dir, set, target) => {
+                    let direction = direction!(dir);
+                    let id = api.get_type(direction[0], direction[1]);
+                    if !set_contains(&self.type_sets[set as usize], id) {
+                        pc = target as usize;
+                    }
+                }
+                Op::SwapIfEmpty(direction, target) => {
+                    let direction = api.get_transformation().transform(&direction);
+                    if api.is_empty(direction[0], direction[1]) {
+                        api.swap(direction[0], direction[1]);
+                        pc = target as usize;
+                    }
+                }
+                Op::LoopInit(target) => {
+                    if stack[sp - 1] <= 0 {
+                        sp -= 1;
+                        pc = target as usize;
+                    }
+                }
+                Op::LoopBack(target) => {
+                    stack[sp - 1] -= 1;
+                    match stack[sp - 1] > 0 {
+                        true => pc = target as usize,
+                        false => sp -= 1,
+                    }
+                }
+                Op::EveryXFrames { skip, end } => {
+                    // Negative numbers wrap around, same as they always did
+                    let frames = pop!() as u32;
+                    if frames == 0 {
+                        pc = end as usize;
+                    } else if !api.get_frame_count().is_multiple_of(frames) {
+                        pc = skip as usize;
+                    }
+                }
+                Op::SaveTransformation => {
+                    transformations[tp] = *api.get_transformation();
+                    tp += 1;
+                }
+                Op::RestoreTransformation => {
+                    tp -= 1;
+                    api.set_transformation(transformations[tp]);
+                }
+                Op::RandomTransformation(kind) => {
+                    let transformation = kind.to_transformation(api);
+                    api.set_transformation(transformation);
+                }
+                Op::ForEachTransformation(kind) => {
+                    let list = transformations_of(kind);
+                    let remaining = stack[sp - 1] as usize;
+                    api.set_transformation(list[list.len() - remaining]);
+                }
+                Op::SetRotation => {
+                    let rotations = pop!().rem_euclid(8);
+                    api.set_transformation(Transformation::Rotation(rotations as usize));
+                }
+                Op::Swap(dir) => {
+                    let direction = direction!(dir);
+                    api.swap(direction[0], direction[1]);
+                }
+                Op::CopyTo(dir) => {
+                    let direction = direction!(dir);
+                    api.set(direction[0], direction[1], api.get_current());
+                }
+                Op::ChangeInto(dir) => {
+                    let id = pop!() as u16;
+                    let direction = direction!(dir);
+                    if api.has_particle_type(id) {
+                        api.set(direction[0], direction[1], api.new_particle(id));
+                    }
+                }
+                Op::IncreaseField(field, dir) => {
+                    let number = pop!() as i8;
+                    let direction = direction!(dir);
+                    let mut particle = api.get(direction[0], direction[1]);
+                    // Values can't go over 100, hue shift wraps around instead
+                    match field {
+                        Field::Opacity => particle.opacity = particle.opacity.saturating_add_signed(number).min(100),
+                        Field::HueShift => particle.hue_shift = particle.hue_shift.saturating_add_signed(number) % 101,
+                        Field::ColorFade => particle.color_fade = particle.color_fade.saturating_add_signed(number).min(100),
+                        Field::Extra => particle.extra = particle.extra.saturating_add_signed(number).min(100),
+                        Field::Extra2 => particle.extra2 = particle.extra2.saturating_add_signed(number).min(100),
+                        Field::Extra3 => particle.extra3 = particle.extra3.saturating_add_signed(number).min(100),
+                    }
+                    api.set_relaxed(direction[0], direction[1], particle);
+                }
+                Op::SetField(field, dir) => {
+                    let number = pop!().clamp(0, 100) as u8;
+                    let direction = direction!(dir);
+                    let mut particle = api.get(direction[0], direction[1]);
+                    match field {
+                        Field::Opacity => particle.opacity = number,
+                        Field::HueShift => particle.hue_shift = number,
+                        Field::ColorFade => particle.color_fade = number,
+                        Field::Extra => particle.extra = number,
+                        Field::Extra2 => particle.extra2 = number,
+                        Field::Extra3 => particle.extra3 = number,
+                    }
+                    api.set_relaxed(direction[0], direction[1], particle);
+                }
+                Op::IncreaseProperty(name, dir) => {
+                    let number = pop!();
+                    let direction = direction!(dir);
+                    let name = &self.names[name as usize];
+                    let mut particle = api.get(direction[0], direction[1]);
+                    // The range comes from the property declaration, particles without it are left alone
+                    if let Some(value) = api.get_property(&particle, name) {
+                        api.set_property(&mut particle, name, value as i32 + number);
+                        api.set_relaxed(direction[0], direction[1], particle);
+                    }
+                }
+                Op::SetProperty(name, dir) => {
+                    let number = pop!();
+                    let direction = direction!(dir);
+                    let mut particle = api.get(direction[0], direction[1]);
+                    if api.set_property(&mut particle, &self.names[name as usize], number) {
+                        api.set_relaxed(direction[0], direction[1], particle);
+                    }
+                }
+                Op::IncreaseTemperature(dir) => {
+                    let number = pop!();
+                    let direction = direction!(dir);
+                    let temperature = api.get_temperature(direction[0], direction[1]) + number as f32;
+                    api.set_temperature(direction[0], direction[1], temperature);
+                }
+                Op::SetTemperature(dir) => {
+                    let number = pop!();
+                    let direction = direction!(dir);
+                    api.set_temperature(direction[0], direction[1], number as f32);
+                }
+                Op::AddImpulse(dir, impulse) => {
+                    let impulse = direction!(impulse);
+                    let direction = direction!(dir);
+                    api.add_impulse(direction[0], direction[1], [impulse[0] as f32, impulse[1] as f32]);
+                }
+                Op::Explode(dir) => {
+                    let chance = pop!();
+                    let spawn = pop!();
+                    let strength = pop!();
+                    let radius = pop!();
+                    let direction = direction!(dir);
+                    api.explode(direction[0], direction[1], explosion(radius, strength, spawn, chance));
+                }
+                Op::KeepAwake => api.keep_awake(),
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+pub mod compiler;
+pub mod interpreter;
+
+pub(crate) use compiler::*;
+
+use app_core::Transformation;
+
+use crate::blocks::{MathOperations, TransformationInternal};
+
+// Where an instruction reads its direction from. Both are transformed at runtime as the transformation
+// can change between frames, with Stack the x and y were pushed before any other operand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Dir {
+    Constant([i32; 2]),
+    Stack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Field {
+    Opacity,
+    HueShift,
+    ColorFade,
+    Extra,
+    Extra2,
+    Extra3,
+}
+
+// Booleans are 0 or 1 on the stack. Jump targets are instruction indices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    // Values
+    Push(i32),
+    Pop,
+    Dup,
+    Math(MathOperations), // Pops b and a, pushes a op b
+    Random,               // Pops max and min, pushes a random number between them
+    ToId,                 // Truncates the value the same way a particle id is
+    // Directions take two values, x is pushed first
+    PushDirection([i32; 2]),
+    RandomDirection,
+    DirectionMath(MathOperations),
+    // Reading the world
+    Type(Dir),
+    NeighborType(u8), // Not transformed, index in ParticleApi::NEIGHBORS
+    Field(Field, Dir),
+    Property(u16, Dir), // Index in Program::names
+    Temperature(Dir),
+    CountNeighbors, // Pops a type, pushes how many of the 8 neighbours are of it
+    // Conditions
+    Not,
+    Equal,
+    Greater,
+    Less,
+    InSet(u16),      // Pops a type, index in Program::type_sets
+    TypeIn(Dir, u16), // Type and InSet in one go, it's the most used condition by far
+    TouchingAny(u16),
+    IsEmpty(Dir),
+    OneIn, // Pops the chance
+    // Area queries look for the types in the type set of the frame
+    TypeSet(u16, u8), // Starts from Program::type_sets and adds that many types popped from the stack
+    RayCast(Dir),     // Pops the max distance
+    RayHits(Dir),     // Pops the max distance
+    CountInRadius,    // Pops the radius
+    CountInArea(Dir, Dir),
+    NearestDistance, // Pops the radius
+    // Variables. Each local has two slots so directions fit, numbers and booleans use the first one
+    Load(u16),
+    Store(u16), // Pops the value
+    LoadGlobal(u16),
+    StoreGlobal(u16),
+    IncreaseGlobal(u16),
+    // Control flow
+    Jump(u32),
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    // A condition and the JumpIfFalse after it in one go, for the conditions if blocks use the most
+    JumpUnlessEmpty(Dir, u32),
+    JumpUnlessTypeIn(Dir, u16, u32),
+    // A whole if branch that only swaps into the cell it checked is empty, jumps to the end when it does
+    SwapIfEmpty([i32; 2], u32),
+    LoopInit(u32), // Pops how many times, skips to the target if it's 0 or less, otherwise it stays as the counter
+    LoopBack(u32), // Decrements the counter and jumps back while it's above 0, pops it when it's done
+    EveryXFrames { skip: u32, end: u32 }, // Pops the frames, end is also after the particle is touched
+    // Transformations
+    SaveTransformation,
+    RestoreTransformation,
+    RandomTransformation(TransformationInternal),
+    ForEachTransformation(TransformationInternal), // Picks the one the loop counter points to
+    SetRotation, // Pops the rotations
+    // Actions, values are popped before the direction
+    Swap(Dir),
+    CopyTo(Dir),
+    ChangeInto(Dir),
+    IncreaseField(Field, Dir),
+    SetField(Field, Dir),
+    IncreaseProperty(u16, Dir),
+    SetProperty(u16, Dir),
+    IncreaseTemperature(Dir),
+    SetTemperature(Dir),
+    AddImpulse(Dir, Dir), // Cell, then the impulse
+    Explode(Dir), // Pops the chance, spawned type, strength and radius
+    KeepAwake, // Waiting for the next time an EveryXFrames runs, the chunk can't fall asleep meanwhile
+}
+
+/// Flat instruction stream a JSON plugin is lowered to. It's rebuilt every time plugins
+/// change, so particle names can be turned into ids while compiling.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub(crate) ops: Vec<Op>,
+    // Bitsets of particle ids, only as long as the highest id in them needs
+    pub(crate) type_sets: Vec<Vec<u64>>,
+    pub(crate) names: Vec<String>,
+    // Computed after compiling so the interpreter can size its stacks
+    pub(crate) max_stack: usize,
+    pub(crate) max_transformations: usize,
+    pub(crate) locals: usize,
+}
+
+impl Program {
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+pub(crate) fn type_set(ids: &[u16]) -> Vec<u64> {
+    let mut set = Vec::new();
+    for id in ids {
+        set_insert(&mut set, *id);
+    }
+    set
+}
+
+pub(crate) fn set_insert(set: &mut Vec<u64>, id: u16) {
+    let word = id as usize / 64;
+    if word >= set.len() {
+        set.resize(word + 1, 0);
+    }
+    set[word] |= 1 << (id as usize % 64);
+}
+
+#[inline(always)]
+pub(crate) fn set_contains(set: &[u64], id: u16) -> bool {
+    set.get(id as usize / 64).is_some_and(|word| word & (1 << (id as usize % 64)) != 0)
+}
+
+// Division and modulo by 0 give 0 instead of panicking, constants are folded with this too
+#[inline(always)]
+pub(crate) fn apply_math(operation: MathOperations, a: i32, b: i32) -> i32 {
+    match operation {
+        MathOperations::Addition => a.wrapping_add(b),
+        MathOperations::Subtraction => a.wrapping_sub(b),
+        MathOperations::Multiplication => a.wrapping_mul(b),
+        MathOperations::Division => a.checked_div(b).unwrap_or(0),
+        MathOperations::Modulo => a.checked_rem(b).unwrap_or(0),
+        MathOperations::Difference => a.wrapping_sub(b).wrapping_abs(),
+    }
+}
+
+// Same order ForEachTransformation blocks always used
+pub(crate) fn transformations_of(kind: TransformationInternal) -> &'static [Transformation] {
+    match kind {
+        TransformationInternal::HorizontalReflection => &[
+            Transformation::HorizontalReflection(true),
+            Transformation::HorizontalReflection(false),
+        ],
+        TransformationInternal::VerticalReflection => &[
+            Transformation::VerticalReflection(true),
+            Transformation::VerticalReflection(false),
+        ],
+        TransformationInternal::Reflection => &[
+            Transformation::Reflection(true, true),
+            Transformation::Reflection(false, false),
+            Transformation::Reflection(false, true),
+            Transformation::Reflection(true, false),
+        ],
+        TransformationInternal::Rotation => &[
+            Transformation::Rotation(0),
+            Transformation::Rotation(1),
+            Transformation::Rotation(2),
+            Transformation::Rotation(3),
+            Transformation::Rotation(4),
+            Transformation::Rotation(5),
+            Transformation::Rotation(6),
+            Transformation::Rotation(7),
+        ],
+        TransformationInternal::None => &[Transformation::None],
+    }
+}
@@ -1,2 +1,6 @@
 pub mod plugins;
-pub mod blocks;
\ No newline at end of file
+pub mod blocks;
+pub mod reactions;
+pub mod procedures;
+pub mod bytecode;
+pub mod validation;
\ No newline at end of file
@@ -1,35 +1,274 @@
+use std::sync::Arc;
+
 use app_core::ParticleApi;
 use app_core::PluginResult;
+use app_core::{LifecycleHooks, MotionProperties, DEFAULT_BLAST_RESISTANCE, Particle, PhaseChange, PropertyDefinition, ThermalProperties};
 use app_core::api::Plugin;
 use serde::*;
-use crate::blocks::Actions;
+use crate::blocks::{enter_scope, leave_scope, ActionFunc, Actions, Globals};
+use crate::bytecode::{Compiler, Program};
+use crate::procedures::{expand_calls, expand_procedures, JSProcedureData, ProcedureLibrary};
+use crate::reactions::JSReactionData;
+use crate::validation::{parse_plugin, validate_plugin, Diagnostics};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct JSPluginData {
     pub name: String,
     pub color: [u8; 3],
     pub color2: [u8; 3],
+    #[serde(default)]
+    pub properties: Vec<JSPropertyData>,
+    #[serde(default)]
+    pub thermal: Option<JSThermalData>,
+    #[serde(default)]
+    pub reactions: Vec<JSReactionData>,
+    // Particles without it can't fly, impulses on them do nothing
+    #[serde(default)]
+    pub motion: Option<JSMotionData>,
+    // Power an explosion needs to destroy the particle, the core default without it
+    #[serde(default)]
+    pub blast_resistance: Option<f32>,
+    #[serde(default)]
+    pub globals: Vec<JSGlobalData>,
+    #[serde(default)]
+    pub procedures: Vec<JSProcedureData>,
+    // Names of other plugins or procedure files whose procedures this one calls
+    #[serde(default)]
+    pub imports: Vec<String>,
     pub update: Vec<Actions>,
+    // Run when the particle is placed or set over another type, and when something else takes its place
+    #[serde(default)]
+    pub on_create: Vec<Actions>,
+    #[serde(default)]
+    pub on_destroy: Vec<Actions>,
+}
+
+// Number shared by every particle of the plugin, blocks use it like any other variable
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSGlobalData {
+    pub name: String,
+    #[serde(default)]
+    pub value: i32,
+}
+
+// Named value stored per particle, blocks can read and write it by name
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSPropertyData {
+    pub name: String,
+    #[serde(default)]
+    pub default: u8,
+    #[serde(default)]
+    pub min: u8,
+    // Same limit the blocks used for the extra fields
+    #[serde(default = "JSPropertyData::default_max")]
+    pub max: u8,
+}
+
+impl JSPropertyData {
+    fn default_max() -> u8 {
+        100
+    }
+}
+
+// How the particle reacts to heat, particles without it use the core defaults
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSThermalData {
+    #[serde(default = "JSThermalData::default_conductivity")]
+    pub conductivity: f32,
+    #[serde(default = "JSThermalData::default_temperature")]
+    pub temperature: f32,
+    #[serde(default)]
+    pub ambient_exchange: f32,
+    #[serde(default)]
+    pub melting: Option<JSPhaseChangeData>,
+    #[serde(default)]
+    pub boiling: Option<JSPhaseChangeData>,
+    #[serde(default)]
+    pub freezing: Option<JSPhaseChangeData>,
+}
+
+// Ballistic movement, missing fields use the core defaults
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSMotionData {
+    #[serde(default = "JSMotionData::default_gravity")]
+    pub gravity: f32,
+    #[serde(default = "JSMotionData::default_drag")]
+    pub drag: f32,
+    #[serde(default = "JSMotionData::default_bounce")]
+    pub bounce: f32,
+}
+
+impl JSMotionData {
+    fn default_gravity() -> f32 {
+        MotionProperties::default().gravity
+    }
+
+    fn default_drag() -> f32 {
+        MotionProperties::default().drag
+    }
+
+    fn default_bounce() -> f32 {
+        MotionProperties::default().bounce
+    }
+
+    fn to_motion_properties(&self) -> MotionProperties {
+        MotionProperties {
+            gravity: self.gravity,
+            drag: self.drag,
+            bounce: self.bounce,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSPhaseChangeData {
+    pub temperature: f32,
+    pub into: String,
+}
+
+impl JSThermalData {
+    fn default_conductivity() -> f32 {
+        ThermalProperties::default().conductivity
+    }
+
+    fn default_temperature() -> f32 {
+        ThermalProperties::default().temperature
+    }
+
+    fn to_thermal_properties(&self) -> ThermalProperties {
+        let phase_change = |phase_change: &Option<JSPhaseChangeData>| {
+            phase_change
+                .as_ref()
+                .map(|phase_change| PhaseChange::new(phase_change.temperature, &phase_change.into))
+        };
+
+        ThermalProperties {
+            conductivity: self.conductivity,
+            temperature: self.temperature,
+            ambient_exchange: self.ambient_exchange,
+            melting: phase_change(&self.melting),
+            boiling: phase_change(&self.boiling),
+            freezing: phase_change(&self.freezing),
+        }
+    }
 }
 
+// Blocks are compiled to bytecode, the closures are what we used before and they are
+// only kept around for the benches and tests to check the bytecode against them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    Bytecode,
+    Closures,
+}
+
+// A list of actions ready to run with either backend
+struct Blocks {
+    // With every call replaced by the procedure's blocks
+    actions: Vec<Actions>,
+    program: Program,
+    func: ActionFunc,
+}
+
+impl Blocks {
+    fn new(actions: Vec<Actions>) -> Blocks {
+        Blocks {
+            actions,
+            program: Program::default(),
+            func: Box::new(|_, _| {}),
+        }
+    }
+
+    fn compile(&mut self, backend: Backend, globals: &Globals, api: &ParticleApi) {
+        if backend == Backend::Bytecode {
+            self.program = Compiler::compile(&self.actions, globals, api);
+            return;
+        }
+
+        let func_vec = self.actions
+            .iter()
+            .map(|block| block.to_func(api))
+            .collect::<Vec<_>>();
+
+        self.func = Box::new(move |plugin, api| {
+            func_vec.iter().for_each(|func| func(plugin, api));
+        });
+    }
+
+    fn run(&self, plugin: &JSPlugin, api: &mut ParticleApi) {
+        match plugin.backend {
+            Backend::Bytecode => self.program.run(api, &plugin.globals),
+            Backend::Closures => {
+                enter_scope(&plugin.globals);
+                (self.func)(plugin, api);
+                leave_scope();
+            }
+        }
+    }
+}
 
 pub struct JSPlugin
 {
-    update:Box<dyn Fn(&JSPlugin, &mut ParticleApi)>,
+    update: Blocks,
+    on_create: Blocks,
+    on_destroy: Blocks,
+    backend: Backend,
+    globals: Arc<Globals>,
+    procedures: Vec<JSProcedureData>,
     plugin_data: JSPluginData,
 }
 
 impl JSPlugin
 {
-    pub fn new(json: &str) -> Result<JSPlugin, serde_json::Error>
+    pub fn new(json: &str) -> Result<JSPlugin, Diagnostics>
+    {
+        JSPlugin::with_backend(json, Backend::default())
+    }
+
+    pub fn with_backend(json: &str, backend: Backend) -> Result<JSPlugin, Diagnostics>
     {
-        let data = serde_json::from_str(json)?;
+        JSPlugin::load(json, backend, None, &ProcedureLibrary::default()).map(|(plugin, _)| plugin)
+    }
 
-       Ok(JSPlugin{
-           update: Box::new(|_, _| {}),
+    // Also checks particle names against the ones given. Warnings come back along with the plugin,
+    // it's only refused if there's an error. Imports are looked up in the library
+    pub fn load(
+        json: &str,
+        backend: Backend,
+        particles: Option<&[String]>,
+        library: &ProcedureLibrary,
+    ) -> Result<(JSPlugin, Diagnostics), Diagnostics>
+    {
+        let data = parse_plugin(json)?;
+        let diagnostics = validate_plugin(&data, particles, library);
+        if diagnostics.has_errors() {
+            return Err(diagnostics);
+        }
+
+       Ok((JSPlugin{
+           update: Blocks::new(expand_calls(&data.update, &data, library)),
+           on_create: Blocks::new(expand_calls(&data.on_create, &data, library)),
+           on_destroy: Blocks::new(expand_calls(&data.on_destroy, &data, library)),
+           backend,
+           globals: Arc::new(Globals::new(data.globals.iter().map(|global| (global.name.as_str(), global.value)))),
+           procedures: expand_procedures(&data, library),
            plugin_data: data
-       })
+       }, diagnostics))
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.plugin_data.name
+    }
+
+    // Procedures declared by the plugin with their calls expanded, other plugins can import them by the plugin name
+    pub fn get_procedures(&self) -> &[JSProcedureData] {
+        &self.procedures
     }
 }
 
@@ -38,25 +277,61 @@ impl Plugin for JSPlugin
 {
     fn update(&self, api: &mut ParticleApi)
     {
-        (self.update)(self, api);
+        self.update.run(self, api);
+    }
+
+    fn on_create(&self, api: &mut ParticleApi) {
+        self.on_create.run(self, api);
+    }
+
+    // Blocks only see the cell, which already holds whatever replaced the particle
+    fn on_destroy(&self, api: &mut ParticleApi, _particle: Particle) {
+        self.on_destroy.run(self, api);
     }
     
     fn register(&mut self) -> app_core::PluginResult {
         PluginResult{
             name: self.plugin_data.name.clone(),
             color: self.plugin_data.color.into(),
-            color2: self.plugin_data.color2.into()
+            color2: self.plugin_data.color2.into(),
+            properties: self
+                .plugin_data
+                .properties
+                .iter()
+                .map(|property| {
+                    PropertyDefinition::new(&property.name, property.default, property.min, property.max)
+                })
+                .collect(),
+            thermal: self
+                .plugin_data
+                .thermal
+                .as_ref()
+                .map(JSThermalData::to_thermal_properties)
+                .unwrap_or_default(),
+            reactions: self
+                .plugin_data
+                .reactions
+                .iter()
+                // The plugin name is the reactant when it's not set, so this can't fail
+                .filter_map(|reaction| reaction.to_reaction(Some(&self.plugin_data.name)).ok())
+                .collect(),
+            motion: self
+                .plugin_data
+                .motion
+                .as_ref()
+                .map(JSMotionData::to_motion_properties),
+            blast_resistance: self.plugin_data.blast_resistance.unwrap_or(DEFAULT_BLAST_RESISTANCE),
+            hooks: LifecycleHooks {
+                on_create: !self.plugin_data.on_create.is_empty(),
+                on_destroy: !self.plugin_data.on_destroy.is_empty(),
+                on_neighbor_changed: false,
+            },
         }
     }
 
     fn on_plugin_changed(&mut self, api: &ParticleApi) {
-        let func_vec = self.plugin_data.update
-            .iter()
-            .map(|block| block.to_func(api))
-            .collect::<Vec<_>>();
-        
-        self.update = Box::new(move |plugin, api| {
-            func_vec.iter().for_each(|func| func(plugin, api));
-        });
+        self.update.compile(self.backend, &self.globals, api);
+        self.on_create.compile(self.backend, &self.globals, api);
+        self.on_destroy.compile(self.backend, &self.globals, api);
     }
 }
\ No newline at end of file
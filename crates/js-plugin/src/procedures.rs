@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+
+use serde::*;
+
+use crate::blocks::{Actions, Conditions, Direction, Number, VariableValue};
+use crate::plugins::JSPluginData;
+use crate::validation::{parse_json, validate_plugin, Diagnostics};
+
+// Named list of blocks a plugin can call with { "action": "call", "data": { "name": ..., "arguments": [...] } }
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSProcedureData {
+    pub name: String,
+    #[serde(default)]
+    pub parameters: Vec<JSParameterData>,
+    pub block: Vec<Actions>,
+}
+
+// Inside the procedure a parameter is read like a variable with its name
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSParameterData {
+    pub name: String,
+    pub r#type: ParameterType,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ParameterType {
+    Direction,
+    Type,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Argument {
+    Type(Number),
+    Direction(Direction),
+}
+
+impl std::fmt::Display for ParameterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterType::Direction => write!(f, "direction"),
+            ParameterType::Type => write!(f, "type"),
+        }
+    }
+}
+
+impl Argument {
+    pub fn get_type(&self) -> ParameterType {
+        match self {
+            Argument::Type(_) => ParameterType::Type,
+            Argument::Direction(_) => ParameterType::Direction,
+        }
+    }
+
+    // Arguments without side effects are pasted where the parameter is used, the rest are
+    // evaluated once into a variable before the procedure runs
+    fn is_constant(&self) -> bool {
+        fn constant_number(number: &Number) -> bool {
+            match number {
+                Number::Constant(_) | Number::FromID(_) | Number::FromName(_) => true,
+                Number::MathOperation(_, number1, number2) => constant_number(number1) && constant_number(number2),
+                _ => false,
+            }
+        }
+
+        fn constant_direction(direction: &Direction) -> bool {
+            match direction {
+                Direction::Constant(_) => true,
+                Direction::Operation(_, direction1, direction2) => {
+                    constant_direction(direction1) && constant_direction(direction2)
+                }
+                Direction::Random | Direction::GetVariable { .. } => false,
+            }
+        }
+
+        match self {
+            Argument::Type(number) => constant_number(number),
+            Argument::Direction(direction) => constant_direction(direction),
+        }
+    }
+}
+
+// A procedures file, other plugins import them by its name
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSProcedureLibraryData {
+    pub name: String,
+    pub procedures: Vec<JSProcedureData>,
+}
+
+/// Procedures plugins can import by name. Every JSON plugin that loads adds its own procedures under
+/// its name, procedure files add theirs under the name they declare. Imports are solved when a plugin
+/// loads, changing a library later doesn't change plugins that were already loaded.
+#[derive(Debug, Clone, Default)]
+pub struct ProcedureLibrary {
+    libraries: HashMap<String, Vec<JSProcedureData>>,
+}
+
+impl ProcedureLibrary {
+    pub fn new() -> ProcedureLibrary {
+        ProcedureLibrary::default()
+    }
+
+    pub fn add(&mut self, name: &str, procedures: Vec<JSProcedureData>) {
+        self.libraries.insert(name.to_lowercase(), procedures);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[JSProcedureData]> {
+        self.libraries.get(&name.to_lowercase()).map(|procedures| procedures.as_slice())
+    }
+
+    // Checked the same way a plugin with only procedures would be. Returns the library name
+    pub fn add_from_json(&mut self, json: &str) -> Result<(String, Diagnostics), Diagnostics> {
+        let library: JSProcedureLibraryData = parse_json(json)?;
+        let data = JSPluginData {
+            name: library.name.clone(),
+            procedures: library.procedures,
+            ..Default::default()
+        };
+
+        let diagnostics = validate_plugin(&data, None, self);
+        if diagnostics.has_errors() {
+            return Err(diagnostics);
+        }
+
+        let procedures = expand_procedures(&data, self);
+        self.add(&library.name, procedures);
+        Ok((library.name, diagnostics))
+    }
+
+    // Procedures a plugin can call, its own first. Imports that don't exist are left out, validation reports them
+    pub(crate) fn visible<'a>(&'a self, data: &'a JSPluginData) -> Vec<&'a JSProcedureData> {
+        let imported = data
+            .imports
+            .iter()
+            .filter_map(|import| self.get(import))
+            .flatten();
+
+        data.procedures.iter().chain(imported).collect()
+    }
+}
+
+/// Pastes every call in place, so the compiler and the closures never see procedures.
+/// Locals of a procedure get a name of their own for each call, that way calls can't see each other's.
+pub(crate) fn expand_calls(actions: &[Actions], data: &JSPluginData, library: &ProcedureLibrary) -> Vec<Actions> {
+    Expander::new(data, library).actions(actions, &Scope::default())
+}
+
+// What the plugin shares with the ones importing it. Calls are already pasted in, so importing a
+// plugin doesn't mean importing everything it imports too
+pub(crate) fn expand_procedures(data: &JSPluginData, library: &ProcedureLibrary) -> Vec<JSProcedureData> {
+    let mut expander = Expander::new(data, library);
+
+    data.procedures
+        .iter()
+        .map(|procedure| {
+            expander.stack.push(&procedure.name);
+            let block = expander.actions(&procedure.block, &Scope::default());
+            expander.stack.pop();
+
+            JSProcedureData {
+                block,
+                ..procedure.clone()
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Scope {
+    // None outside procedures, variables keep their names there
+    prefix: Option<String>,
+    constants: HashMap<String, Argument>,
+}
+
+struct Expander<'a> {
+    procedures: HashMap<&'a str, &'a JSProcedureData>,
+    globals: Vec<&'a str>,
+    calls: usize,
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(data: &'a JSPluginData, library: &'a ProcedureLibrary) -> Expander<'a> {
+        let mut procedures = HashMap::new();
+        for procedure in library.visible(data) {
+            procedures.entry(procedure.name.as_str()).or_insert(procedure);
+        }
+
+        Expander {
+            procedures,
+            globals: data.globals.iter().map(|global| global.name.as_str()).collect(),
+            calls: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    fn variable(&self, name: &str, scope: &Scope) -> String {
+        match &scope.prefix {
+            Some(prefix) if !self.globals.contains(&name) => format!("{}{}", prefix, name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn actions(&mut self, actions: &[Actions], scope: &Scope) -> Vec<Actions> {
+        actions
+            .iter()
+            .flat_map(|action| self.action(action, scope))
+            .collect()
+    }
+
+    fn block(&mut self, block: &Option<Vec<Actions>>, scope: &Scope) -> Option<Vec<Actions>> {
+        block.as_ref().map(|block| self.actions(block, scope))
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Argument], scope: &Scope) -> Vec<Actions> {
+        // Unknown procedures and recursion were already reported
+        let Some(&procedure) = self.procedures.get(name) else {
+            return Vec::new();
+        };
+        if self.stack.contains(&procedure.name.as_str()) {
+            return Vec::new();
+        }
+
+        self.calls += 1;
+        let mut inner = Scope {
+            prefix: Some(format!("{}#{}.", procedure.name, self.calls)),
+            constants: HashMap::new(),
+        };
+
+        let mut actions = Vec::new();
+        for (parameter, argument) in procedure.parameters.iter().zip(arguments) {
+            let argument = match argument {
+                Argument::Type(number) => Argument::Type(self.number(number, scope)),
+                Argument::Direction(direction) => Argument::Direction(self.direction(direction, scope)),
+            };
+
+            if argument.is_constant() {
+                inner.constants.insert(parameter.name.clone(), argument);
+                continue;
+            }
+
+            let value = match argument {
+                Argument::Type(number) => VariableValue::Number(number),
+                Argument::Direction(direction) => VariableValue::Direction(direction),
+            };
+            actions.push(Actions::SetVariable {
+                name: self.variable(&parameter.name, &inner),
+                value,
+            });
+        }
+
+        self.stack.push(&procedure.name);
+        actions.extend(self.actions(&procedure.block, &inner));
+        self.stack.pop();
+
+        actions
+    }
+
+    fn action(&mut self, action: &Actions, scope: &Scope) -> Vec<Actions> {
+        let action = match action {
+            Actions::Call { name, arguments } => return self.call(name, arguments, scope),
+            Actions::Swap { direction } => Actions::Swap {
+                direction: self.direction(direction, scope),
+            },
+            Actions::CopyTo { direction } => Actions::CopyTo {
+                direction: self.direction(direction, scope),
+            },
+            Actions::ChangeInto { direction, r#type } => Actions::ChangeInto {
+                direction: self.direction(direction, scope),
+                r#type: self.number(r#type, scope),
+            },
+            Actions::RandomTransformation { transformation, block } => Actions::RandomTransformation {
+                transformation: *transformation,
+                block: self.block(block, scope),
+            },
+            Actions::ForEachTransformation { transformation, block } => Actions::ForEachTransformation {
+                transformation: *transformation,
+                block: self.block(block, scope),
+            },
+            Actions::RotatedBy { number, block } => Actions::RotatedBy {
+                number: self.number(number, scope),
+                block: self.block(block, scope),
+            },
+            Actions::If(branches) => Actions::If(
+                branches
+                    .iter()
+                    .map(|branch| {
+                        branch.as_ref().map(|(condition, block)| {
+                            (self.condition(condition, scope), self.actions(block, scope))
+                        })
+                    })
+                    .collect(),
+            ),
+            Actions::IncreaseParticlePropierty { propierty, number, direction } => Actions::IncreaseParticlePropierty {
+                propierty: propierty.clone(),
+                number: self.number(number, scope),
+                direction: self.direction(direction, scope),
+            },
+            Actions::SetParticlePropierty { propierty, number, direction } => Actions::SetParticlePropierty {
+                propierty: propierty.clone(),
+                number: self.number(number, scope),
+                direction: self.direction(direction, scope),
+            },
+            Actions::IncreaseTemperature { number, direction } => Actions::IncreaseTemperature {
+                number: self.number(number, scope),
+                direction: self.direction(direction, scope),
+            },
+            Actions::SetTemperature { number, direction } => Actions::SetTemperature {
+                number: self.number(number, scope),
+                direction: self.direction(direction, scope),
+            },
+            Actions::AddImpulse { direction, impulse } => Actions::AddImpulse {
+                direction: self.direction(direction, scope),
+                impulse: self.direction(impulse, scope),
+            },
+            Actions::Explode { direction, radius, strength, spawn, chance } => Actions::Explode {
+                direction: self.direction(direction, scope),
+                radius: self.number(radius, scope),
+                strength: self.number(strength, scope),
+                spawn: spawn.as_ref().map(|spawn| self.number(spawn, scope)),
+                chance: chance.as_ref().map(|chance| self.number(chance, scope)),
+            },
+            Actions::Repeat { number, block } => Actions::Repeat {
+                number: self.number(number, scope),
+                block: self.block(block, scope),
+            },
+            Actions::EveryXFrames { number, block } => Actions::EveryXFrames {
+                number: self.number(number, scope),
+                block: self.block(block, scope),
+            },
+            Actions::SetVariable { name, value } => Actions::SetVariable {
+                name: self.variable(name, scope),
+                value: match value {
+                    VariableValue::Number(number) => VariableValue::Number(self.number(number, scope)),
+                    VariableValue::Boolean(condition) => VariableValue::Boolean(self.condition(condition, scope)),
+                    VariableValue::Direction(direction) => VariableValue::Direction(self.direction(direction, scope)),
+                },
+            },
+            Actions::IncreaseVariable { name, number } => Actions::IncreaseVariable {
+                name: self.variable(name, scope),
+                number: self.number(number, scope),
+            },
+            Actions::None => Actions::None,
+        };
+
+        vec![action]
+    }
+
+    fn condition(&mut self, condition: &Conditions, scope: &Scope) -> Conditions {
+        let condition_box = |expander: &mut Self, condition: &Conditions| Box::new(expander.condition(condition, scope));
+
+        match condition {
+            Conditions::CheckTypesInDirection { direction, types } => Conditions::CheckTypesInDirection {
+                direction: self.direction(direction, scope),
+                types: self.numbers(types, scope),
+            },
+            Conditions::Not { block } => Conditions::Not {
+                block: condition_box(self, block),
+            },
+            Conditions::And { block1, block2 } => Conditions::And {
+                block1: condition_box(self, block1),
+                block2: condition_box(self, block2),
+            },
+            Conditions::Or { block1, block2 } => Conditions::Or {
+                block1: condition_box(self, block1),
+                block2: condition_box(self, block2),
+            },
+            Conditions::CompareBooleans { block1, block2 } => Conditions::CompareBooleans {
+                block1: condition_box(self, block1),
+                block2: condition_box(self, block2),
+            },
+            Conditions::IsTouching { types } => Conditions::IsTouching {
+                types: self.numbers(types, scope),
+            },
+            Conditions::OneInXChance { chance } => Conditions::OneInXChance {
+                chance: self.number(chance, scope),
+            },
+            Conditions::IsEmpty { direction } => Conditions::IsEmpty {
+                direction: self.direction(direction, scope),
+            },
+            Conditions::CompareNumberEquality { block1, block2 } => Conditions::CompareNumberEquality {
+                block1: self.number(block1, scope),
+                block2: self.number(block2, scope),
+            },
+            Conditions::CompareBiggerThan { block1, block2 } => Conditions::CompareBiggerThan {
+                block1: self.number(block1, scope),
+                block2: self.number(block2, scope),
+            },
+            Conditions::CompareLessThan { block1, block2 } => Conditions::CompareLessThan {
+                block1: self.number(block1, scope),
+                block2: self.number(block2, scope),
+            },
+            Conditions::RayHits { direction, distance, types } => Conditions::RayHits {
+                direction: self.direction(direction, scope),
+                distance: self.number(distance, scope),
+                types: self.numbers(types, scope),
+            },
+            Conditions::IsNear { radius, types } => Conditions::IsNear {
+                radius: self.number(radius, scope),
+                types: self.numbers(types, scope),
+            },
+            Conditions::Boolean { value } => Conditions::Boolean { value: *value },
+            // Same as reading the variable, true if it isn't 0
+            Conditions::GetVariable { name } => match scope.constants.get(name) {
+                Some(Argument::Type(number)) => Conditions::Not {
+                    block: Box::new(Conditions::CompareNumberEquality {
+                        block1: number.clone(),
+                        block2: Number::Constant(0),
+                    }),
+                },
+                Some(Argument::Direction(_)) => Conditions::Boolean { value: false },
+                None => Conditions::GetVariable {
+                    name: self.variable(name, scope),
+                },
+            },
+        }
+    }
+
+    fn numbers(&mut self, numbers: &[Number], scope: &Scope) -> Vec<Number> {
+        numbers.iter().map(|number| self.number(number, scope)).collect()
+    }
+
+    fn number(&mut self, number: &Number, scope: &Scope) -> Number {
+        match number {
+            Number::NumberOfXTouching(types) => Number::NumberOfXTouching(self.numbers(types, scope)),
+            Number::RandomFromXToY(min, max) => Number::RandomFromXToY(
+                Box::new(self.number(min, scope)),
+                Box::new(self.number(max, scope)),
+            ),
+            Number::Opacity(direction) => Number::Opacity(self.direction(direction, scope)),
+            Number::ColorFade(direction) => Number::ColorFade(self.direction(direction, scope)),
+            Number::HueShift(direction) => Number::HueShift(self.direction(direction, scope)),
+            Number::Extra(direction) => Number::Extra(self.direction(direction, scope)),
+            Number::Extra2(direction) => Number::Extra2(self.direction(direction, scope)),
+            Number::Extra3(direction) => Number::Extra3(self.direction(direction, scope)),
+            Number::Property(name, direction) => Number::Property(name.clone(), self.direction(direction, scope)),
+            Number::Temperature(direction) => Number::Temperature(self.direction(direction, scope)),
+            Number::TypeOf(direction) => Number::TypeOf(self.direction(direction, scope)),
+            Number::MathOperation(operation, number1, number2) => Number::MathOperation(
+                *operation,
+                Box::new(self.number(number1, scope)),
+                Box::new(self.number(number2, scope)),
+            ),
+            Number::RayCast(direction, distance) => Number::RayCast(
+                self.direction(direction, scope),
+                Box::new(self.number(distance, scope)),
+            ),
+            Number::CountInRadius(radius, types) => {
+                Number::CountInRadius(Box::new(self.number(radius, scope)), self.numbers(types, scope))
+            }
+            Number::CountInArea(corner1, corner2, types) => Number::CountInArea(
+                self.direction(corner1, scope),
+                self.direction(corner2, scope),
+                self.numbers(types, scope),
+            ),
+            Number::DistanceToNearest(radius, types) => {
+                Number::DistanceToNearest(Box::new(self.number(radius, scope)), self.numbers(types, scope))
+            }
+            Number::GetVariable(name) => match scope.constants.get(name) {
+                Some(Argument::Type(number)) => number.clone(),
+                Some(Argument::Direction(_)) => Number::Constant(0),
+                None => Number::GetVariable(self.variable(name, scope)),
+            },
+            Number::Constant(_) | Number::FromID(_) | Number::FromName(_) => number.clone(),
+        }
+    }
+
+    fn direction(&mut self, direction: &Direction, scope: &Scope) -> Direction {
+        match direction {
+            Direction::GetVariable { name } => match scope.constants.get(name) {
+                Some(Argument::Direction(direction)) => direction.clone(),
+                Some(Argument::Type(_)) => Direction::Constant([0, 0]),
+                None => Direction::GetVariable {
+                    name: self.variable(name, scope),
+                },
+            },
+            Direction::Operation(operation, direction1, direction2) => Direction::Operation(
+                *operation,
+                Box::new(self.direction(direction1, scope)),
+                Box::new(self.direction(direction2, scope)),
+            ),
+            Direction::Constant(_) | Direction::Random => direction.clone(),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use app_core::Reaction;
+use serde::*;
+
+// Reaction as written in JSON, either inside a plugin or in a reactions file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JSReactionData {
+    // Can be left out inside a plugin, it's the plugin particle then
+    #[serde(default)]
+    pub reactant: Option<String>,
+    pub neighbor: String,
+    // What the reactant and the neighbour turn into, they stay as they are if not set
+    #[serde(default)]
+    pub into: Option<String>,
+    #[serde(default)]
+    pub neighbor_into: Option<String>,
+    #[serde(default = "JSReactionData::default_probability")]
+    pub probability: f32,
+    // Chance for each side, same order as ParticleApi::NEIGHBORS. Replaces probability if set
+    #[serde(default)]
+    pub probabilities: Option<[f32; 8]>,
+    // Only neighbours at these sides react, like [[0, 1]] for the one on top
+    #[serde(default)]
+    pub directions: Option<Vec<[i32; 2]>>,
+}
+
+impl JSReactionData {
+    fn default_probability() -> f32 {
+        1.0
+    }
+
+    pub fn to_reaction(&self, default_reactant: Option<&str>) -> Result<Reaction, String> {
+        let reactant = self
+            .reactant
+            .as_deref()
+            .or(default_reactant)
+            .ok_or_else(|| format!("Reaction with {} doesn't have a reactant", self.neighbor))?;
+
+        let mut reaction = Reaction::new(
+            reactant,
+            &self.neighbor,
+            self.into.as_deref(),
+            self.neighbor_into.as_deref(),
+            self.probability,
+        );
+
+        if let Some(probabilities) = self.probabilities {
+            reaction.probabilities = probabilities;
+        }
+
+        match &self.directions {
+            Some(directions) => Ok(reaction.only_towards(directions)),
+            None => Ok(reaction),
+        }
+    }
+}
+
+// A reactions file is just a list of them, each one with its reactant
+pub fn reactions_from_json(json: &str) -> Result<Vec<Reaction>, serde_json::Error> {
+    let data: Vec<JSReactionData> = serde_json::from_str(json)?;
+
+    data.iter()
+        .map(|reaction| reaction.to_reaction(None))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(<serde_json::Error as serde::de::Error>::custom)
+}
@@ -0,0 +1,790 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use app_core::{CHUNK_HALO, MAX_SPEED, PROPERTY_SLOTS};
+use serde::de::DeserializeOwned;
+use serde::*;
+
+use crate::blocks::{Actions, Conditions, Direction, MathOperations, Number, ParticlePropierties, VariableType, VariableValue};
+use crate::bytecode::apply_math;
+use crate::plugins::JSPluginData;
+use crate::procedures::{Argument, JSParameterData, JSProcedureData, ParameterType, ProcedureLibrary};
+
+// Errors stop the plugin from loading, warnings are things that load fine but probably aren't what was meant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // JSON path to the offending value, like $.update[0].data.direction
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{} at {}: {}", severity, self.path, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(path: &str, message: String) -> Diagnostics {
+        Diagnostics(vec![Diagnostic {
+            severity: Severity::Error,
+            path: path.to_string(),
+            message,
+        }])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.0.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+
+    // What the web side gets, a list of { severity, path, message }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| String::from("[]"))
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, diagnostic) in self.0.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses a plugin keeping track of where serde gave up, so a typo deep in the blocks still points somewhere
+pub fn parse_plugin(json: &str) -> Result<JSPluginData, Diagnostics> {
+    parse_json(json)
+}
+
+pub(crate) fn parse_json<T: DeserializeOwned>(json: &str) -> Result<T, Diagnostics> {
+    let deserializer = &mut serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(deserializer).map_err(|error| {
+        let path = match error.path().to_string().as_str() {
+            "." => String::from("$"),
+            path => format!("$.{}", path),
+        };
+        Diagnostics::error(&path, error.inner().to_string())
+    })
+}
+
+/// Walks a parsed plugin looking for anything that would silently do nothing or blow up while running.
+/// Names are only checked when the particles already loaded are given, as plugins can be loaded in any order.
+/// Imports are looked up in the library, so procedures the plugin calls have to be there before it loads.
+pub fn validate_plugin(data: &JSPluginData, particles: Option<&[String]>, library: &ProcedureLibrary) -> Diagnostics {
+    let mut validator = Validator {
+        data,
+        particles,
+        library,
+        procedures: library.visible(data),
+        variables: HashMap::new(),
+        reads: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+
+    validator.properties();
+    validator.thermal();
+    validator.reactions();
+    validator.imports();
+    validator.globals();
+    validator.procedures();
+    for (index, action) in data.update.iter().enumerate() {
+        validator.action(action, &format!("$.update[{}]", index));
+    }
+    validator.variable_reads();
+    validator.scoped(&data.on_create, "$.onCreate", &[]);
+    validator.scoped(&data.on_destroy, "$.onDestroy", &[]);
+
+    Diagnostics(validator.diagnostics)
+}
+
+struct Validator<'a> {
+    data: &'a JSPluginData,
+    particles: Option<&'a [String]>,
+    library: &'a ProcedureLibrary,
+    // Own procedures first, then the imported ones
+    procedures: Vec<&'a JSProcedureData>,
+    // Type of every variable, from the first block that sets it. Globals are always numbers
+    variables: HashMap<String, VariableType>,
+    // Reads are checked at the end, a loop can read a variable before the block that sets it
+    reads: Vec<(String, VariableType, String)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Names of the procedures called anywhere inside the blocks
+fn called_procedures<'a>(actions: &'a [Actions], calls: &mut Vec<&'a str>) {
+    for action in actions {
+        match action {
+            Actions::Call { name, .. } => calls.push(name),
+            Actions::RandomTransformation { block: Some(block), .. }
+            | Actions::ForEachTransformation { block: Some(block), .. }
+            | Actions::RotatedBy { block: Some(block), .. }
+            | Actions::Repeat { block: Some(block), .. }
+            | Actions::EveryXFrames { block: Some(block), .. } => called_procedures(block, calls),
+            Actions::If(branches) => {
+                for (_, block) in branches.iter().flatten() {
+                    called_procedures(block, calls);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Numbers and booleans share the same value, only directions can't be mixed with them
+fn compatible(type1: VariableType, type2: VariableType) -> bool {
+    type1 == type2 || (type1 != VariableType::Direction && type2 != VariableType::Direction)
+}
+
+impl Validator<'_> {
+    fn error(&mut self, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn properties(&mut self) {
+        let properties = &self.data.properties;
+
+        if properties.len() > PROPERTY_SLOTS {
+            self.error(
+                "$.properties",
+                format!(
+                    "A particle can't have more than {} properties, {} has {}",
+                    PROPERTY_SLOTS,
+                    self.data.name,
+                    properties.len()
+                ),
+            );
+        }
+
+        for (index, property) in properties.iter().enumerate() {
+            let path = format!("$.properties[{}]", index);
+
+            if ParticlePropierties::is_builtin(&property.name) {
+                self.error(&path, format!("{} is a built in property, it can't be declared", property.name));
+            }
+
+            if properties[..index]
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&property.name))
+            {
+                self.error(&path, format!("Property {} is declared twice", property.name));
+            }
+
+            if property.min > property.max || property.default < property.min || property.default > property.max {
+                self.error(&path, format!("Property {} must have min <= default <= max", property.name));
+            }
+        }
+    }
+
+    fn thermal(&mut self) {
+        let Some(thermal) = &self.data.thermal else {
+            return;
+        };
+
+        let phase_changes = [("melting", &thermal.melting), ("boiling", &thermal.boiling), ("freezing", &thermal.freezing)];
+        for (name, phase_change) in phase_changes {
+            if let Some(phase_change) = phase_change {
+                self.particle_name(&phase_change.into, &format!("$.thermal.{}.into", name));
+            }
+        }
+    }
+
+    fn reactions(&mut self) {
+        for (index, reaction) in self.data.reactions.iter().enumerate() {
+            let path = format!("$.reactions[{}]", index);
+
+            let names = [
+                ("reactant", &reaction.reactant),
+                ("into", &reaction.into),
+                ("neighborInto", &reaction.neighbor_into),
+            ];
+            for (field, name) in names {
+                if let Some(name) = name {
+                    self.particle_name(name, &format!("{}.{}", path, field));
+                }
+            }
+            self.particle_name(&reaction.neighbor, &format!("{}.neighbor", path));
+
+            for (side, direction) in reaction.directions.iter().flatten().enumerate() {
+                if direction[0].abs() > 1 || direction[1].abs() > 1 || *direction == [0, 0] {
+                    self.error(
+                        &format!("{}.directions[{}]", path, side),
+                        format!("{:?} isn't a neighbour, reactions only look at the 8 cells around", direction),
+                    );
+                }
+            }
+        }
+    }
+
+    fn globals(&mut self) {
+        for (index, global) in self.data.globals.iter().enumerate() {
+            let path = format!("$.globals[{}]", index);
+            if self.variables.insert(global.name.clone(), VariableType::Number).is_some() {
+                self.error(&path, format!("Global {} is declared twice", global.name));
+            }
+        }
+    }
+
+    fn imports(&mut self) {
+        for (index, import) in self.data.imports.iter().enumerate() {
+            if self.library.get(import).is_none() {
+                self.error(
+                    &format!("$.imports[{}]", index),
+                    format!("There are no procedures called {}, they have to be loaded before this plugin", import),
+                );
+            }
+        }
+    }
+
+    fn procedures(&mut self) {
+        let own = self.data.procedures.len();
+
+        for (index, procedure) in self.procedures.clone().into_iter().enumerate() {
+            if self.procedures[..index].iter().any(|other| other.name == procedure.name) {
+                let path = match index < own {
+                    true => format!("$.procedures[{}].name", index),
+                    false => String::from("$.imports"),
+                };
+                self.error(&path, format!("Procedure {} is declared twice", procedure.name));
+            }
+        }
+
+        for (index, procedure) in self.data.procedures.iter().enumerate() {
+            let path = format!("$.procedures[{}]", index);
+
+            if let Some(cycle) = self.calls_itself(procedure, &mut vec![procedure.name.clone()]) {
+                self.error(&path, format!("Procedure {} calls itself through {}, procedures can't be recursive", procedure.name, cycle));
+            }
+
+            self.scoped(&procedure.block, &format!("{}.block", path), &procedure.parameters);
+        }
+    }
+
+    // Procedures and hooks have variables of their own, only globals are shared with the rest of the plugin
+    fn scoped(&mut self, actions: &[Actions], path: &str, parameters: &[JSParameterData]) {
+        let variables = std::mem::take(&mut self.variables);
+        let reads = std::mem::take(&mut self.reads);
+        for global in &self.data.globals {
+            self.variables.insert(global.name.clone(), VariableType::Number);
+        }
+
+        // The block list is the last part of the path, parameters are next to it
+        let parent = path.rsplit_once('.').map_or(path, |(parent, _)| parent);
+        for (index, parameter) in parameters.iter().enumerate() {
+            let parameter_path = format!("{}.parameters[{}]", parent, index);
+            let variable_type = match parameter.r#type {
+                ParameterType::Direction => VariableType::Direction,
+                ParameterType::Type => VariableType::Number,
+            };
+
+            if self.is_global(&parameter.name) {
+                self.error(&parameter_path, format!("Parameter {} has the same name as a global", parameter.name));
+            } else if self.variables.insert(parameter.name.clone(), variable_type).is_some() {
+                self.error(&parameter_path, format!("Parameter {} is declared twice", parameter.name));
+            }
+        }
+
+        self.actions(actions, path);
+        self.variable_reads();
+
+        self.variables = variables;
+        self.reads = reads;
+    }
+
+    // Follows calls depth first, imported procedures come expanded so only the plugin's own can loop.
+    // Returns the chain of calls that gets back to the first procedure
+    fn calls_itself(&self, procedure: &JSProcedureData, stack: &mut Vec<String>) -> Option<String> {
+        let mut calls = Vec::new();
+        called_procedures(&procedure.block, &mut calls);
+
+        for name in calls {
+            if name == stack[0] {
+                stack.push(name.to_string());
+                return Some(stack[1..].join(" -> "));
+            }
+            if stack.iter().any(|other| other == name) {
+                continue;
+            }
+
+            let Some(callee) = self.data.procedures.iter().find(|other| other.name == name) else {
+                continue;
+            };
+            stack.push(name.to_string());
+            if let Some(cycle) = self.calls_itself(callee, stack) {
+                return Some(cycle);
+            }
+            stack.pop();
+        }
+
+        None
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Argument], path: &str) {
+        let field = |field: &str| format!("{}.data.{}", path, field);
+
+        for (index, argument) in arguments.iter().enumerate() {
+            match argument {
+                Argument::Type(number) => self.number(number, &format!("{}[{}]", field("arguments"), index)),
+                Argument::Direction(direction) => self.direction(direction, &format!("{}[{}]", field("arguments"), index)),
+            }
+        }
+
+        let Some(procedure) = self.procedures.iter().find(|procedure| procedure.name == name).copied() else {
+            self.error(&field("name"), format!("There's no procedure called {}, is it imported?", name));
+            return;
+        };
+
+        if arguments.len() != procedure.parameters.len() {
+            let message = format!(
+                "{} takes {} arguments but {} were given",
+                name,
+                procedure.parameters.len(),
+                arguments.len()
+            );
+            self.error(&field("arguments"), message);
+            return;
+        }
+
+        for (index, (parameter, argument)) in procedure.parameters.iter().zip(arguments).enumerate() {
+            if parameter.r#type != argument.get_type() {
+                let message = format!(
+                    "Parameter {} of {} is a {}, it can't be given a {}",
+                    parameter.name,
+                    name,
+                    parameter.r#type,
+                    argument.get_type()
+                );
+                self.error(&format!("{}[{}]", field("arguments"), index), message);
+            }
+            // Imported procedures don't know about the plugin's globals
+            if self.is_global(&parameter.name) {
+                self.error(&field("name"), format!("Parameter {} of {} has the same name as a global", parameter.name, name));
+            }
+        }
+    }
+
+    fn is_global(&self, name: &str) -> bool {
+        self.data.globals.iter().any(|global| global.name == name)
+    }
+
+    fn set_variable(&mut self, name: &str, variable_type: VariableType, path: &str) {
+        if self.is_global(name) && variable_type == VariableType::Direction {
+            self.error(path, format!("{} is a global, globals can only be numbers", name));
+            return;
+        }
+
+        match self.variables.get(name) {
+            Some(previous) if !compatible(*previous, variable_type) => {
+                let message = format!("{} is a {}, it can't be set to a {}", name, previous, variable_type);
+                self.error(path, message);
+            }
+            Some(_) => (),
+            None => {
+                self.variables.insert(name.to_string(), variable_type);
+            }
+        }
+    }
+
+    fn read_variable(&mut self, name: &str, variable_type: VariableType, path: &str) {
+        self.reads.push((name.to_string(), variable_type, path.to_string()));
+    }
+
+    fn variable_reads(&mut self) {
+        for (name, variable_type, path) in std::mem::take(&mut self.reads) {
+            match self.variables.get(&name) {
+                Some(set_type) if !compatible(*set_type, variable_type) => {
+                    let message = format!("{} is a {}, it can't be used as a {}", name, set_type, variable_type);
+                    self.error(&path, message);
+                }
+                Some(_) => (),
+                None => self.warning(&path, format!("{} is never set, it's always 0", name)),
+            }
+        }
+    }
+
+    // The plugin itself isn't loaded yet the first time, but it can always refer to itself
+    fn particle_name(&mut self, name: &str, path: &str) {
+        let Some(particles) = self.particles else {
+            return;
+        };
+
+        let known = name.eq_ignore_ascii_case(&self.data.name)
+            || particles.iter().any(|particle| particle.eq_ignore_ascii_case(name));
+        if !known {
+            self.warning(path, format!("There's no particle called {}, this does nothing until one is loaded", name));
+        }
+    }
+
+    fn body(&mut self, block: &Option<Vec<Actions>>, path: &str, name: &str) {
+        match block {
+            Some(block) => self.actions(block, &format!("{}.data.block", path)),
+            None => self.warning(path, format!("{} has nothing inside, it does nothing", name)),
+        }
+    }
+
+    fn actions(&mut self, actions: &[Actions], path: &str) {
+        for (index, action) in actions.iter().enumerate() {
+            self.action(action, &format!("{}[{}]", path, index));
+        }
+    }
+
+    fn action(&mut self, action: &Actions, path: &str) {
+        let data = format!("{}.data", path);
+        let field = |name: &str| format!("{}.{}", data, name);
+
+        match action {
+            Actions::Swap { direction } | Actions::CopyTo { direction } => {
+                self.direction(direction, &field("direction"));
+            }
+            Actions::ChangeInto { direction, r#type } => {
+                self.direction(direction, &field("direction"));
+                self.number(r#type, &field("type"));
+            }
+            Actions::RandomTransformation { block, .. } => self.body(block, path, "Random transformation"),
+            Actions::ForEachTransformation { block, .. } => self.body(block, path, "For each transformation"),
+            Actions::RotatedBy { number, block } => {
+                self.number(number, &field("number"));
+                self.body(block, path, "Rotated by");
+            }
+            Actions::If(branches) => {
+                let mut always_taken = None;
+
+                for (index, branch) in branches.iter().enumerate() {
+                    let Some((condition, block)) = branch else {
+                        continue;
+                    };
+                    let branch_path = format!("{}[{}]", data, index);
+
+                    if let Some(taken) = always_taken {
+                        self.warning(
+                            &branch_path,
+                            format!("Branch {} always runs, this one can't be reached", taken),
+                        );
+                        continue;
+                    }
+
+                    self.condition(condition, &format!("{}[0]", branch_path));
+                    match fold_condition(condition) {
+                        Some(true) => always_taken = Some(index),
+                        Some(false) => {
+                            self.warning(&branch_path, String::from("The condition is never true, this can't be reached"));
+                            continue;
+                        }
+                        None => (),
+                    }
+                    self.actions(block, &format!("{}[1]", branch_path));
+                }
+            }
+            Actions::IncreaseParticlePropierty { propierty, number, direction }
+            | Actions::SetParticlePropierty { propierty, number, direction } => {
+                self.direction(direction, &field("direction"));
+                self.number(number, &field("number"));
+                // Only the particle itself is known to have the property, other ones might declare it too
+                if let ParticlePropierties::Named(name) = propierty {
+                    let declared = self.data.properties.iter().any(|property| property.name.eq_ignore_ascii_case(name));
+                    if !declared && fold_direction(direction) == Some([0, 0]) {
+                        self.warning(&field("propierty"), format!("{} doesn't declare a property called {}", self.data.name, name));
+                    }
+                }
+            }
+            Actions::IncreaseTemperature { number, direction } | Actions::SetTemperature { number, direction } => {
+                self.direction(direction, &field("direction"));
+                self.number(number, &field("number"));
+            }
+            Actions::AddImpulse { direction, impulse } => {
+                self.direction(direction, &field("direction"));
+                self.impulse(impulse, &field("impulse"));
+            }
+            Actions::Explode { direction, radius, strength, spawn, chance } => {
+                self.direction(direction, &field("direction"));
+                self.reach(radius, &field("radius"));
+                self.number(strength, &field("strength"));
+                if let Some(spawn) = spawn {
+                    self.number(spawn, &field("spawn"));
+                }
+                if let Some(chance) = chance {
+                    self.number(chance, &field("chance"));
+                }
+                if fold_number(strength).is_some_and(|strength| strength <= 0) {
+                    self.warning(&field("strength"), String::from("An explosion without strength does nothing"));
+                }
+            }
+            Actions::Repeat { number, block } => {
+                self.number(number, &field("number"));
+                match fold_number(number) {
+                    Some(times) if times <= 0 => self.warning(path, format!("Repeating {} times, this can't be reached", times)),
+                    _ => self.body(block, path, "Repeat"),
+                }
+            }
+            Actions::EveryXFrames { number, block } => {
+                self.number(number, &field("number"));
+                match fold_number(number) {
+                    Some(0) => self.warning(path, String::from("Every 0 frames never runs, this can't be reached")),
+                    _ => self.body(block, path, "Every X frames"),
+                }
+            }
+            Actions::SetVariable { name, value } => {
+                match value {
+                    VariableValue::Number(number) => self.number(number, &field("value")),
+                    VariableValue::Boolean(condition) => self.condition(condition, &field("value")),
+                    VariableValue::Direction(direction) => self.direction(direction, &field("value")),
+                }
+                self.set_variable(name, value.get_type(), &field("name"));
+            }
+            // Counting from a variable that was never set is fine, it starts at 0
+            Actions::IncreaseVariable { name, number } => {
+                self.number(number, &field("number"));
+                self.set_variable(name, VariableType::Number, &field("name"));
+            }
+            Actions::Call { name, arguments } => self.call(name, arguments, path),
+            Actions::None => (),
+        }
+    }
+
+    fn condition(&mut self, condition: &Conditions, path: &str) {
+        let field = |name: &str| format!("{}.data.{}", path, name);
+
+        match condition {
+            Conditions::CheckTypesInDirection { direction, types } => {
+                self.direction(direction, &field("direction"));
+                self.numbers(types, &field("types"));
+            }
+            Conditions::Not { block } => self.condition(block, &field("block")),
+            Conditions::And { block1, block2 } | Conditions::Or { block1, block2 } | Conditions::CompareBooleans { block1, block2 } => {
+                self.condition(block1, &field("block1"));
+                self.condition(block2, &field("block2"));
+            }
+            Conditions::IsTouching { types } => self.numbers(types, &field("types")),
+            Conditions::OneInXChance { chance } => self.number(chance, &field("chance")),
+            Conditions::IsEmpty { direction } => self.direction(direction, &field("direction")),
+            Conditions::CompareNumberEquality { block1, block2 }
+            | Conditions::CompareBiggerThan { block1, block2 }
+            | Conditions::CompareLessThan { block1, block2 } => {
+                self.number(block1, &field("block1"));
+                self.number(block2, &field("block2"));
+            }
+            Conditions::GetVariable { name } => self.read_variable(name, VariableType::Boolean, &field("name")),
+            Conditions::RayHits { direction, distance, types } => {
+                self.ray(direction, distance, &field("direction"), &field("distance"));
+                self.numbers(types, &field("types"));
+            }
+            Conditions::IsNear { radius, types } => {
+                self.reach(radius, &field("radius"));
+                self.numbers(types, &field("types"));
+            }
+            Conditions::Boolean { .. } => (),
+        }
+    }
+
+    fn numbers(&mut self, numbers: &[Number], path: &str) {
+        for (index, number) in numbers.iter().enumerate() {
+            self.number(number, &format!("{}[{}]", path, index));
+        }
+    }
+
+    // Numbers with several values keep them in a list, so their path is data[index]
+    fn number(&mut self, number: &Number, path: &str) {
+        let data = format!("{}.data", path);
+
+        match number {
+            Number::NumberOfXTouching(types) => self.numbers(types, &data),
+            Number::RandomFromXToY(min, max) => {
+                self.number(min, &format!("{}[0]", data));
+                self.number(max, &format!("{}[1]", data));
+            }
+            Number::Opacity(direction)
+            | Number::ColorFade(direction)
+            | Number::HueShift(direction)
+            | Number::Extra(direction)
+            | Number::Extra2(direction)
+            | Number::Extra3(direction)
+            | Number::Temperature(direction)
+            | Number::TypeOf(direction) => self.direction(direction, &data),
+            Number::Property(_, direction) => self.direction(direction, &format!("{}[1]", data)),
+            Number::MathOperation(operation, number1, number2) => {
+                self.number(number1, &format!("{}[1]", data));
+                self.number(number2, &format!("{}[2]", data));
+                if divides_by_zero(*operation, fold_number(number2)) {
+                    self.error(&format!("{}[2]", data), String::from("Division by 0"));
+                }
+            }
+            Number::FromName(name) => self.particle_name(name, &data),
+            Number::GetVariable(name) => self.read_variable(name, VariableType::Number, &data),
+            Number::RayCast(direction, distance) => {
+                self.ray(direction, distance, &format!("{}[0]", data), &format!("{}[1]", data));
+            }
+            Number::CountInRadius(radius, types) | Number::DistanceToNearest(radius, types) => {
+                self.reach(radius, &format!("{}[0]", data));
+                self.numbers(types, &format!("{}[1]", data));
+            }
+            Number::CountInArea(corner1, corner2, types) => {
+                self.direction(corner1, &format!("{}[0]", data));
+                self.direction(corner2, &format!("{}[1]", data));
+                self.numbers(types, &format!("{}[2]", data));
+            }
+            Number::Constant(_) | Number::FromID(_) => (),
+        }
+    }
+
+    fn ray(&mut self, direction: &Direction, distance: &Number, direction_path: &str, distance_path: &str) {
+        self.direction(direction, direction_path);
+        self.reach(distance, distance_path);
+        if fold_direction(direction) == Some([0, 0]) {
+            self.warning(direction_path, String::from("A ray going nowhere never hits anything"));
+        }
+    }
+
+    // Distances and radiuses are clamped when the plugin runs, so going over is only a warning
+    fn reach(&mut self, number: &Number, path: &str) {
+        self.number(number, path);
+
+        let reach = CHUNK_HALO as i32;
+        if let Some(folded) = fold_number(number) {
+            if folded > reach {
+                self.warning(path, format!("{} is more than {} cells, queries don't look further than that", folded, reach));
+            }
+        }
+    }
+
+    // An impulse is a speed and not a cell, so it isn't limited by the halo, only clamped to the top speed
+    fn impulse(&mut self, impulse: &Direction, path: &str) {
+        let Some(folded) = fold_direction(impulse) else {
+            self.direction(impulse, path);
+            return;
+        };
+
+        if folded == [0, 0] {
+            self.warning(path, String::from("An impulse of [0, 0] does nothing"));
+        }
+        let max_speed = MAX_SPEED as i32;
+        if folded[0].abs() > max_speed || folded[1].abs() > max_speed {
+            self.warning(path, format!("{:?} is faster than {} cells per frame, it will be clamped", folded, max_speed));
+        }
+    }
+
+    // Blocks can't reach further than the chunk halo, otherwise they break when updated on several threads
+    fn direction(&mut self, direction: &Direction, path: &str) {
+        if let Direction::GetVariable { name } = direction {
+            self.read_variable(name, VariableType::Direction, &format!("{}.getVariable", path));
+        }
+
+        if let Direction::Operation(operation, direction1, direction2) = direction {
+            self.direction(direction1, &format!("{}[1]", path));
+            self.direction(direction2, &format!("{}[2]", path));
+
+            let divisor = fold_direction(direction2);
+            if divisor.is_some_and(|divisor| divides_by_zero(*operation, Some(divisor[0])) || divides_by_zero(*operation, Some(divisor[1]))) {
+                self.error(&format!("{}[2]", path), String::from("Division by 0"));
+                return;
+            }
+        }
+
+        let reach = CHUNK_HALO as i32;
+        if let Some(folded) = fold_direction(direction) {
+            if folded[0].abs() > reach || folded[1].abs() > reach {
+                self.error(
+                    path,
+                    format!("{:?} is out of range, blocks can't reach further than {} cells", folded, reach),
+                );
+            }
+        }
+    }
+}
+
+fn divides_by_zero(operation: MathOperations, divisor: Option<i32>) -> bool {
+    matches!(operation, MathOperations::Division | MathOperations::Modulo) && divisor == Some(0)
+}
+
+// Same folding the compiler does, except particle names, which aren't known here
+
+fn fold_number(number: &Number) -> Option<i32> {
+    match number {
+        Number::Constant(constant) => Some(*constant),
+        Number::FromID(id) => Some(*id as i32),
+        Number::MathOperation(operation, number1, number2) => {
+            let number2 = fold_number(number2)?;
+            if divides_by_zero(*operation, Some(number2)) {
+                return None;
+            }
+            Some(apply_math(*operation, fold_number(number1)?, number2))
+        }
+        _ => None,
+    }
+}
+
+fn fold_direction(direction: &Direction) -> Option<[i32; 2]> {
+    match direction {
+        Direction::Constant(direction) => Some(*direction),
+        Direction::Random | Direction::GetVariable { .. } => None,
+        Direction::Operation(operation, direction1, direction2) => {
+            let direction1 = fold_direction(direction1)?;
+            let direction2 = fold_direction(direction2)?;
+            Some([
+                apply_math(*operation, direction1[0], direction2[0]),
+                apply_math(*operation, direction1[1], direction2[1]),
+            ])
+        }
+    }
+}
+
+fn fold_condition(condition: &Conditions) -> Option<bool> {
+    match condition {
+        Conditions::Boolean { value } => Some(*value),
+        Conditions::Not { block } => fold_condition(block).map(|value| !value),
+        Conditions::And { block1, block2 } => match fold_condition(block1)? {
+            false => Some(false),
+            true => fold_condition(block2),
+        },
+        Conditions::Or { block1, block2 } => match fold_condition(block1)? {
+            true => Some(true),
+            false => fold_condition(block2),
+        },
+        Conditions::CompareNumberEquality { block1, block2 } => Some(fold_number(block1)? == fold_number(block2)?),
+        Conditions::CompareBiggerThan { block1, block2 } => Some(fold_number(block1)? > fold_number(block2)?),
+        Conditions::CompareLessThan { block1, block2 } => Some(fold_number(block1)? < fold_number(block2)?),
+        Conditions::CompareBooleans { block1, block2 } => Some(fold_condition(block1)? == fold_condition(block2)?),
+        Conditions::OneInXChance {
+            chance: Number::Constant(chance),
+        } if *chance <= 1 => Some(true),
+        _ => None,
+    }
+}
@@ -0,0 +1,52 @@
+;; The default Sand plugin written against the guest ABI: falls down, or diagonally to a random side,
+;; through empty cells and water. Any language that compiles to wasm works as long as it imports and
+;; exports the same functions, this one is written by hand so it can be loaded as it is
+(module
+  (import "sand" "get" (func $get (param i32 i32) (result i32)))
+  (import "sand" "swap" (func $swap (param i32 i32) (result i32)))
+  (import "sand" "gen_range" (func $gen_range (param i32 i32) (result i32)))
+  (import "sand" "id_from_name" (func $id_from_name (param i32 i32) (result i32)))
+  (import "sand" "register_name" (func $register_name (param i32 i32)))
+  (import "sand" "register_colors" (func $register_colors (param i32 i32)))
+  (import "sand" "register_motion" (func $register_motion (param f32 f32 f32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "Wasm Sand")
+  (data (i32.const 16) "Water")
+
+  ;; Looked up in on_plugin_changed, 65535 until then or if there's no water loaded
+  (global $water (mut i32) (i32.const 65535))
+
+  (func (export "sand_abi_version") (result i32)
+    (i32.const 3))
+
+  (func (export "register")
+    (call $register_name (i32.const 0) (i32.const 9))
+    (call $register_colors (i32.const 0xFFCC00) (i32.const 0xFFCC00))
+    ;; Same as MotionProperties::default()
+    (call $register_motion (f32.const 0.3) (f32.const 0.02) (f32.const 0.2)))
+
+  (func (export "on_plugin_changed")
+    (global.set $water (call $id_from_name (i32.const 16) (i32.const 5))))
+
+  ;; swap_if_match with empty cells and water as targets
+  (func $swap_if_match (param $x i32) (param $y i32) (result i32)
+    (local $target i32)
+    (local.set $target (call $get (local.get $x) (local.get $y)))
+    (if (result i32)
+      (i32.or
+        (i32.eqz (local.get $target))
+        (i32.eq (local.get $target) (global.get $water)))
+      (then (call $swap (local.get $x) (local.get $y)))
+      (else (i32.const 0))))
+
+  ;; The side is -1 or 1, never 0, so a grain that doesn't move has nowhere to go and its chunk can fall asleep
+  (func (export "update")
+    (local $horizontal i32)
+    (local.set $horizontal
+      (i32.sub (i32.mul (call $gen_range (i32.const 0) (i32.const 1)) (i32.const 2)) (i32.const 1)))
+    (if (call $swap_if_match (i32.const 0) (i32.const -1))
+      (then (return)))
+    (if (call $swap_if_match (local.get $horizontal) (i32.const -1))
+      (then (return)))
+    (drop (call $swap_if_match (i32.sub (i32.const 0) (local.get $horizontal)) (i32.const -1)))))
@@ -0,0 +1,176 @@
+use std::ptr;
+
+use app_core::{Color, MotionProperties, ParticleApi, DEFAULT_BLAST_RESISTANCE};
+use wasmi::{Caller, Error, Linker};
+
+// Guest ABI, what a .wasm module has to export and what it can import to be a particle plugin.
+// Bump it whenever an import or export changes, modules built for another version are refused
+// instead of calling functions that don't do what they expect. Version 2 made ids 16 bit, 65535 is the invalid one,
+// version 3 added keep_awake
+pub const ABI_VERSION: i32 = 3;
+
+// Every host function is imported from this module:
+//   get(x: i32, y: i32) -> i32                     type id of the particle there. Past the world edge it depends on
+//                                                  the boundary: a wall gives its particle type (65535 if it has none,
+//                                                  the default), void gives empty (0) and wrap the cell on the other side
+//   set(x: i32, y: i32, id: i32) -> i32            places a new particle of that type, 1 if it was set
+//   swap(x: i32, y: i32) -> i32                    1 if it was swapped
+//   move_to(x: i32, y: i32) -> i32                 1 if it moved
+//   gen_range(min: i32, max: i32) -> i32           both ends included
+//   id_from_name(name: i32, len: i32) -> i32       UTF-8 name in the guest memory, 65535 if there's no such particle
+//   keep_awake()                                   the chunk keeps being updated even if nothing was written, for guests
+//                                                  waiting on gen_range or a number of frames instead of their surroundings
+// And only while registering:
+//   register_name(name: i32, len: i32)
+//   register_colors(color: i32, color2: i32)       0xRRGGBB
+//   register_motion(gravity: f32, drag: f32, bounce: f32)
+//   register_blast_resistance(resistance: f32)
+// Coordinates are relative to the particle being updated, with y going up like the rest of the api
+pub const IMPORT_MODULE: &str = "sand";
+
+// Exports, all of them take and return nothing unless noted
+pub const MEMORY_EXPORT: &str = "memory";
+// () -> i32, has to return ABI_VERSION
+pub const VERSION_EXPORT: &str = "sand_abi_version";
+pub const REGISTER_EXPORT: &str = "register";
+pub const UPDATE_EXPORT: &str = "update";
+// Optional, called before the first update and whenever plugins change, to look up ids by name
+pub const PLUGIN_CHANGED_EXPORT: &str = "on_plugin_changed";
+
+// Instructions a single call can run before it's stopped, so a guest stuck in a loop doesn't freeze the simulation
+pub(crate) const FUEL_PER_CALL: u64 = 1_000_000;
+
+// What the guest said about itself while registering
+#[derive(Debug, Clone)]
+pub(crate) struct Registration {
+    pub name: Option<String>,
+    pub color: Color,
+    pub color2: Color,
+    pub motion: Option<MotionProperties>,
+    pub blast_resistance: f32,
+}
+
+impl Default for Registration {
+    fn default() -> Self {
+        Registration {
+            name: None,
+            color: Color::from_hex(0xFFFFFF),
+            color2: Color::from_hex(0xFFFFFF),
+            motion: None,
+            blast_resistance: DEFAULT_BLAST_RESISTANCE,
+        }
+    }
+}
+
+pub(crate) struct Host {
+    // Only set while the guest runs for a particle, the api isn't around otherwise
+    pub api: *mut ParticleApi,
+    pub registering: bool,
+    pub registration: Registration,
+}
+
+// The api pointer is only set for the length of a call, made from the thread that owns the api
+unsafe impl Send for Host {}
+
+impl Host {
+    pub fn new() -> Host {
+        Host {
+            api: ptr::null_mut(),
+            registering: false,
+            registration: Registration::default(),
+        }
+    }
+}
+
+fn api<'a>(caller: &'a mut Caller<'_, Host>, function: &str) -> Result<&'a mut ParticleApi, Error> {
+    let api = caller.data().api;
+    if api.is_null() {
+        return Err(Error::new(format!("{} can only be called from update or on_plugin_changed", function)));
+    }
+    Ok(unsafe { &mut *api })
+}
+
+fn registration<'a>(caller: &'a mut Caller<'_, Host>, function: &str) -> Result<&'a mut Registration, Error> {
+    if !caller.data().registering {
+        return Err(Error::new(format!("{} can only be called from register", function)));
+    }
+    Ok(&mut caller.data_mut().registration)
+}
+
+fn read_string(caller: &Caller<'_, Host>, pointer: i32, length: i32) -> Result<String, Error> {
+    let memory = caller
+        .get_export(MEMORY_EXPORT)
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| Error::new("the module doesn't export its memory"))?;
+    let bytes = memory
+        .data(caller)
+        .get(pointer as u32 as usize..)
+        .and_then(|bytes| bytes.get(..length as u32 as usize))
+        .ok_or_else(|| Error::new("string out of the module memory"))?;
+
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::new("string isn't valid UTF-8"))
+}
+
+fn color(color: i32) -> Color {
+    Color::from_hex(color as u32 & 0xFFFFFF)
+}
+
+pub(crate) fn link(linker: &mut Linker<Host>) -> Result<(), Error> {
+    linker.func_wrap(IMPORT_MODULE, "get", |mut caller: Caller<'_, Host>, x: i32, y: i32| {
+        Ok(api(&mut caller, "get")?.get(x, y).id as i32)
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "set", |mut caller: Caller<'_, Host>, x: i32, y: i32, id: i32| {
+        let api = api(&mut caller, "set")?;
+        // Unknown types are ignored like JSON plugins do, there's no particle to build
+        if !u16::try_from(id).is_ok_and(|id| api.has_particle_type(id)) {
+            return Ok(0);
+        }
+        Ok(api.set(x, y, api.new_particle(id as u16)) as i32)
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "swap", |mut caller: Caller<'_, Host>, x: i32, y: i32| {
+        Ok(api(&mut caller, "swap")?.swap(x, y) as i32)
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "move_to", |mut caller: Caller<'_, Host>, x: i32, y: i32| {
+        Ok(api(&mut caller, "move_to")?.move_to(x, y) as i32)
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "gen_range", |mut caller: Caller<'_, Host>, min: i32, max: i32| {
+        if min > max {
+            return Err(Error::new(format!("gen_range called with {} greater than {}", min, max)));
+        }
+        Ok(api(&mut caller, "gen_range")?.gen_range(min, max))
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "id_from_name", |mut caller: Caller<'_, Host>, name: i32, length: i32| {
+        let name = read_string(&caller, name, length)?;
+        Ok(api(&mut caller, "id_from_name")?.id_from_name(&name) as i32)
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "keep_awake", |mut caller: Caller<'_, Host>| {
+        api(&mut caller, "keep_awake")?.keep_awake();
+        Ok(())
+    })?;
+
+    linker.func_wrap(IMPORT_MODULE, "register_name", |mut caller: Caller<'_, Host>, name: i32, length: i32| {
+        let name = read_string(&caller, name, length)?;
+        registration(&mut caller, "register_name")?.name = Some(name);
+        Ok(())
+    })?;
+    linker.func_wrap(IMPORT_MODULE, "register_colors", |mut caller: Caller<'_, Host>, color1: i32, color2: i32| {
+        let registration = registration(&mut caller, "register_colors")?;
+        registration.color = color(color1);
+        registration.color2 = color(color2);
+        Ok(())
+    })?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "register_motion",
+        |mut caller: Caller<'_, Host>, gravity: f32, drag: f32, bounce: f32| {
+            registration(&mut caller, "register_motion")?.motion = Some(MotionProperties { gravity, drag, bounce });
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(IMPORT_MODULE, "register_blast_resistance", |mut caller: Caller<'_, Host>, resistance: f32| {
+        registration(&mut caller, "register_blast_resistance")?.blast_resistance = resistance;
+        Ok(())
+    })?;
+
+    Ok(())
+}
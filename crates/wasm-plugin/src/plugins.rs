@@ -0,0 +1,205 @@
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+use app_core::api::Plugin;
+use app_core::{ParticleApi, PluginResult};
+use wasmi::{Config, Engine, Error, Linker, Module, Store, TypedFunc};
+
+use crate::abi::*;
+
+// A particle plugin compiled to WebAssembly. The module is run by an interpreter, so the same file
+// works on every platform, and the guest can only touch the world through the functions in abi
+pub struct WasmPlugin {
+    module: Module,
+    linker: Linker<Host>,
+    registration: Registration,
+    // A store can't be used from two threads at once, so every thread updating particles keeps its
+    // own instance in GUESTS, found by this id. Each one has its own memory, guests shouldn't keep
+    // anything between updates besides the ids they look up
+    id: usize,
+    // Bumped when plugins change, instances that saw an older one look their ids up again
+    generation: AtomicUsize,
+    // Instances of plugins that were dropped are cleaned up once this is gone
+    alive: Arc<()>,
+    // Set after the first error so a broken guest doesn't flood the output, its particles just stop updating
+    failed: AtomicBool,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static GUESTS: RefCell<Vec<CachedGuest>> = const { RefCell::new(Vec::new()) };
+}
+
+struct CachedGuest {
+    plugin: usize,
+    alive: Weak<()>,
+    guest: Guest,
+}
+
+struct Guest {
+    store: Store<Host>,
+    update: TypedFunc<(), ()>,
+    on_plugin_changed: Option<TypedFunc<(), ()>>,
+    // The plugin generation on_plugin_changed last ran for, if it did
+    ids_ready: Option<usize>,
+}
+
+impl Guest {
+    fn new(module: &Module, linker: &Linker<Host>) -> Result<Guest, Error> {
+        let mut store = Store::new(module.engine(), Host::new());
+        store.set_fuel(FUEL_PER_CALL)?;
+        let instance = linker.instantiate(&mut store, module)?.start(&mut store)?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&store, VERSION_EXPORT)?
+            .call(&mut store, ())?;
+        if version != ABI_VERSION {
+            return Err(Error::new(format!(
+                "the module was built for ABI version {}, this version supports {}",
+                version, ABI_VERSION
+            )));
+        }
+
+        // Registering is cheap, so every instance does it and the first one keeps the result
+        store.data_mut().registering = true;
+        store.set_fuel(FUEL_PER_CALL)?;
+        instance
+            .get_typed_func::<(), ()>(&store, REGISTER_EXPORT)?
+            .call(&mut store, ())?;
+        store.data_mut().registering = false;
+
+        Ok(Guest {
+            update: instance.get_typed_func(&store, UPDATE_EXPORT)?,
+            on_plugin_changed: instance.get_typed_func(&store, PLUGIN_CHANGED_EXPORT).ok(),
+            ids_ready: None,
+            store,
+        })
+    }
+
+    fn update(&mut self, api: &mut ParticleApi, generation: usize) -> Result<(), Error> {
+        self.store.data_mut().api = api;
+        let result = self.call_update(generation);
+        self.store.data_mut().api = ptr::null_mut();
+        result
+    }
+
+    fn call_update(&mut self, generation: usize) -> Result<(), Error> {
+        if self.ids_ready != Some(generation) {
+            if let Some(on_plugin_changed) = self.on_plugin_changed {
+                self.store.set_fuel(FUEL_PER_CALL)?;
+                on_plugin_changed.call(&mut self.store, ())?;
+            }
+            self.ids_ready = Some(generation);
+        }
+
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        self.update.call(&mut self.store, ())
+    }
+}
+
+impl WasmPlugin {
+    // Takes a binary module, or one in the text format
+    pub fn new(bytes: &[u8]) -> Result<WasmPlugin, String> {
+        let wasm = wat::parse_bytes(bytes).map_err(|error| error.to_string())?;
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm).map_err(|error| error.to_string())?;
+
+        let mut linker = Linker::new(&engine);
+        link(&mut linker).map_err(|error| error.to_string())?;
+
+        let guest = Guest::new(&module, &linker).map_err(|error| error.to_string())?;
+        let registration = guest.store.data().registration.clone();
+        if registration.name.is_none() {
+            return Err(String::from("the module didn't call register_name in register"));
+        }
+
+        let plugin = WasmPlugin {
+            module,
+            linker,
+            registration,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            generation: AtomicUsize::new(0),
+            alive: Arc::new(()),
+            failed: AtomicBool::new(false),
+        };
+        plugin.put_back(guest);
+        Ok(plugin)
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub fn from_file(path: &str) -> Result<WasmPlugin, String> {
+        let bytes = std::fs::read(path).map_err(|error| format!("Unable to read plugin {}: {}", path, error))?;
+        WasmPlugin::new(&bytes).map_err(|error| format!("Error loading plugin {}: {}", path, error))
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.registration.name.as_deref().unwrap_or_default()
+    }
+
+    fn take_guest(&self) -> Option<Guest> {
+        GUESTS.with_borrow_mut(|guests| {
+            let index = guests.iter().position(|cached| cached.plugin == self.id)?;
+            Some(guests.swap_remove(index).guest)
+        })
+    }
+
+    fn put_back(&self, guest: Guest) {
+        GUESTS.with_borrow_mut(|guests| {
+            guests.retain(|cached| cached.alive.strong_count() > 0);
+            guests.push(CachedGuest {
+                plugin: self.id,
+                alive: Arc::downgrade(&self.alive),
+                guest,
+            });
+        });
+    }
+
+    fn fail(&self, error: Error) {
+        if !self.failed.swap(true, Ordering::Relaxed) {
+            println!("{} stopped updating: {}", self.get_name(), error);
+        }
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn register(&mut self) -> PluginResult {
+        let registration = self.registration.clone();
+        PluginResult {
+            name: registration.name.unwrap_or_default(),
+            color: registration.color,
+            color2: registration.color2,
+            motion: registration.motion,
+            blast_resistance: registration.blast_resistance,
+            ..Default::default()
+        }
+    }
+
+    fn update(&self, api: &mut ParticleApi) {
+        if self.failed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Taken out while it runs, so nothing stays borrowed while the guest calls back into the world
+        let guest = match self.take_guest() {
+            Some(guest) => Ok(guest),
+            None => Guest::new(&self.module, &self.linker),
+        };
+
+        // A guest that failed might be left halfway through something, so it isn't put back
+        let generation = self.generation.load(Ordering::Relaxed);
+        match guest.and_then(|mut guest| guest.update(api, generation).map(|_| guest)) {
+            Ok(guest) => self.put_back(guest),
+            Err(error) => self.fail(error),
+        }
+    }
+
+    fn on_plugin_changed(&mut self, _: &ParticleApi) {
+        *self.generation.get_mut() += 1;
+    }
+}